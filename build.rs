@@ -0,0 +1,13 @@
+/// With the `grpc` feature, generate the gRPC server for `proto/bank.proto`.
+/// The proto is parsed by `protox`, so no `protoc` install is needed.
+fn main() {
+    println!("cargo:rerun-if-changed=proto/bank.proto");
+    #[cfg(feature = "grpc")]
+    {
+        let descriptors = protox::compile(["proto/bank.proto"], ["proto"]).expect("proto/bank.proto should parse");
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_fds(descriptors)
+            .expect("the gRPC server should generate");
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::currency::{Balance, Currency};
+use crate::time::SECONDS_PER_YEAR;
+use crate::types::AccountId;
+
+/// Withdrawals a savings account allows in each monthly window.
+pub const SAVINGS_WITHDRAWALS_PER_MONTH: u32 = 6;
+
+/// Longest term a term deposit can be opened for, about a century.
+pub const MAX_TERM_DAYS: u64 = 100 * 365;
+
+const MONTH: Duration = Duration::from_secs(SECONDS_PER_YEAR / 12);
+
+static NEXT_ACCOUNT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Make sure newly opened accounts get ids above `id`, e.g. after loading
+/// existing accounts from disk.
+pub fn reserve_ids_through(id: AccountId) {
+    NEXT_ACCOUNT_ID.fetch_max(u32::from(id) + 1, Ordering::Relaxed);
+}
+
+/// The id the next new account will get.
+pub fn next_id() -> AccountId {
+    AccountId::from(NEXT_ACCOUNT_ID.load(Ordering::Relaxed))
+}
+
+/// What an account is for, which decides how it behaves.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccountKind {
+    /// Everyday account: unlimited withdrawals, no interest.
+    Checking,
+    /// Earns interest, with a limited number of withdrawals per month. The
+    /// default, matching how every account behaved before kinds existed.
+    #[default]
+    Savings,
+    /// Earns interest; no funds can leave before `maturity`.
+    TermDeposit { maturity: SystemTime },
+}
+
+impl AccountKind {
+    /// A term deposit maturing `days` days after `now`, at most
+    /// `MAX_TERM_DAYS`.
+    pub fn term_deposit(now: SystemTime, days: u64) -> Result<Self, String> {
+        let too_long = || format!("Term too long; the longest is {} days", MAX_TERM_DAYS);
+        if days > MAX_TERM_DAYS {
+            return Err(too_long());
+        }
+        let term = days.checked_mul(24 * 60 * 60).map(Duration::from_secs).ok_or_else(too_long)?;
+        let maturity = now.checked_add(term).ok_or_else(too_long)?;
+        Ok(AccountKind::TermDeposit { maturity })
+    }
+
+    pub fn earns_interest(self) -> bool {
+        !matches!(self, AccountKind::Checking)
+    }
+
+    /// Short name used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            AccountKind::Checking => "checking",
+            AccountKind::Savings => "savings",
+            AccountKind::TermDeposit { .. } => "term",
+        }
+    }
+}
+
+impl fmt::Display for AccountKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for AccountKind {
+    type Err = String;
+
+    /// Parse "checking" or "savings"; term deposits also need a maturity, see
+    /// `AccountKind::term_deposit`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "checking" => Ok(AccountKind::Checking),
+            "savings" => Ok(AccountKind::Savings),
+            _ => Err(format!("Unknown account kind '{}'; expected checking or savings", s)),
+        }
+    }
+}
+
+/// One of a user's accounts: its balances, kind and the state its rules
+/// depend on. `borrowable` lets other users borrow against its deposits.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Account {
+   pub id: AccountId,
+   pub kind: AccountKind,
+   pub balances: HashMap<Currency, Balance>,
+   pub borrowable: bool,
+   /// Start of the current monthly withdrawal window for savings accounts.
+   pub window_start: Option<SystemTime>,
+   pub window_withdrawals: u32,
+}
+
+impl Account {
+    pub fn new(kind: AccountKind) -> Self {
+        Account {
+            id: AccountId::from(NEXT_ACCOUNT_ID.fetch_add(1, Ordering::Relaxed)),
+            kind,
+            ..Account::default()
+        }
+    }
+
+    /// Totals held in `currency`, zero if the account never touched it.
+    pub fn balance(&self, currency: Currency) -> Balance {
+        self.balances.get(&currency).copied().unwrap_or_default()
+    }
+
+    pub fn balance_mut(&mut self, currency: Currency) -> &mut Balance {
+        self.balances.entry(currency).or_default()
+    }
+
+    /// Fail if the account's funds cannot leave it at `now`.
+    pub fn ensure_unlocked(&self, now: SystemTime) -> Result<(), String> {
+        match self.kind {
+            AccountKind::TermDeposit { maturity } if now < maturity => {
+                let days = maturity.duration_since(now).unwrap_or_default().as_secs().div_ceil(24 * 60 * 60);
+                Err(format!("Term deposit funds are locked for {} more day(s)", days))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fail if a withdrawal at `now` is not allowed. Does not count it; call
+    /// `record_withdrawal` once it has gone through.
+    pub fn check_withdrawal(&self, now: SystemTime) -> Result<(), String> {
+        self.ensure_unlocked(now)?;
+        if self.kind == AccountKind::Savings && self.withdrawals_in_window(now) >= SAVINGS_WITHDRAWALS_PER_MONTH {
+            return Err(format!(
+                "Savings accounts allow {} withdrawals per month",
+                SAVINGS_WITHDRAWALS_PER_MONTH
+            ));
+        }
+        Ok(())
+    }
+
+    /// Count a withdrawal made at `now` against the monthly limit.
+    pub fn record_withdrawal(&mut self, now: SystemTime) {
+        if self.kind != AccountKind::Savings {
+            return;
+        }
+        if self.withdrawals_in_window(now) == 0 {
+            self.window_start = Some(now);
+            self.window_withdrawals = 0;
+        }
+        self.window_withdrawals += 1;
+    }
+
+    fn withdrawals_in_window(&self, now: SystemTime) -> u32 {
+        match self.window_start {
+            Some(start) if now < start + MONTH => self.window_withdrawals,
+            _ => 0,
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::money::Money;
+
+/// Share of the expected salary that can be advanced, in basis points.
+pub const ADVANCE_LIMIT_BPS: u32 = 5_000; // 50%
+
+/// Flat fee the treasury charges for each advance, in the advance's currency.
+pub const ADVANCE_FEE: Money = Money::from_major(5);
+
+/// A short-term advance from the treasury against a user's next salary. It is
+/// paid back, fee first, out of the next salary-like credit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SalaryAdvance {
+   pub currency: Currency,
+   /// Principal still owed.
+   pub owed: Money,
+   /// Part of the flat fee still owed.
+   pub fee: Money,
+   pub taken: SystemTime,
+}
+
+impl SalaryAdvance {
+    pub fn new(currency: Currency, amount: Money, taken: SystemTime) -> Self {
+        SalaryAdvance {
+            currency,
+            owed: amount,
+            fee: ADVANCE_FEE,
+            taken,
+        }
+    }
+
+    /// Principal and fee still owed.
+    pub fn total(&self) -> Money {
+        self.owed.checked_add(self.fee).unwrap_or(Money::from_minor(u64::MAX))
+    }
+
+    /// Pay back up to `amount`, settling the fee before principal. Returns the
+    /// fee and principal paid.
+    pub fn repay(&mut self, amount: Money) -> (Money, Money) {
+        let fee = amount.min(self.fee);
+        let principal = amount.checked_sub(fee).unwrap_or(Money::ZERO).min(self.owed);
+        self.fee = self.fee.checked_sub(fee).unwrap_or(Money::ZERO);
+        self.owed = self.owed.checked_sub(principal).unwrap_or(Money::ZERO);
+        (fee, principal)
+    }
+
+    pub fn is_repaid(&self) -> bool {
+        self.total() == Money::ZERO
+    }
+}
+
+impl fmt::Display for SalaryAdvance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "salary advance {}: owes {} plus {} fee", self.currency, self.owed, self.fee)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::{SalaryAdvance, ADVANCE_FEE};
+    use crate::currency::Currency;
+    use crate::money::Money;
+
+    fn advance(amount: u64) -> SalaryAdvance {
+        SalaryAdvance::new(Currency::Usd, Money::from_major(amount), SystemTime::UNIX_EPOCH)
+    }
+
+    #[test]
+    fn the_fee_is_settled_before_principal() {
+        let mut advance = advance(100);
+        assert_eq!(advance.total(), Money::from_major(105));
+        assert_eq!(advance.repay(Money::from_major(3)), (Money::from_major(3), Money::ZERO));
+        assert_eq!(advance.repay(Money::from_major(12)), (Money::from_major(2), Money::from_major(10)));
+        assert_eq!((advance.fee, advance.owed), (Money::ZERO, Money::from_major(90)));
+        assert!(!advance.is_repaid());
+    }
+
+    #[test]
+    fn repayments_are_capped_at_what_is_owed() {
+        let mut advance = advance(100);
+        assert_eq!(advance.repay(Money::from_major(1_000)), (ADVANCE_FEE, Money::from_major(100)));
+        assert!(advance.is_repaid());
+        assert_eq!(advance.repay(Money::from_major(1)), (Money::ZERO, Money::ZERO));
+    }
+}
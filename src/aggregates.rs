@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::currency::Currency;
+use crate::money::Money;
+use crate::user::User;
+
+/// Bank-wide totals held in one currency.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Totals {
+   /// Sum of every user's deposited balance.
+   pub deposits: Money,
+   /// Sum of the principal still owed on every loan.
+   pub lent: Money,
+   /// Sum of every overdrawn balance.
+   pub overdrawn: Money,
+   /// Sum of the principal still owed on salary advances.
+   pub advanced: Money,
+   /// Sum of the price still owed on installment plans, less merchant fees.
+   pub financed: Money,
+}
+
+/// Per-currency `Totals`, adjusted by each operation for the users it touched
+/// instead of being recomputed by scanning every account.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Aggregates {
+    totals: BTreeMap<Currency, Totals>,
+}
+
+impl Aggregates {
+    /// Recompute the totals from scratch over `users`.
+    pub fn scan<'a>(users: impl IntoIterator<Item = &'a User>) -> Self {
+        let mut aggregates = Aggregates::default();
+        for user in users {
+            aggregates.add(&Aggregates::of(user));
+        }
+        aggregates
+    }
+
+    /// What a single user contributes to the totals. Loans count on the
+    /// borrower's side only, so each is counted once.
+    pub fn of(user: &User) -> Self {
+        let mut aggregates = Aggregates::default();
+        for (currency, balance) in user.total_balance() {
+            aggregates.entry(currency).deposits = balance.deposited;
+        }
+        for loan in user.debts() {
+            let totals = aggregates.entry(loan.currency);
+            totals.lent = totals.lent.checked_add(loan.remaining).unwrap_or(Money::from_minor(u64::MAX));
+        }
+        if let Some(overdraft) = &user.overdraft {
+            aggregates.entry(overdraft.currency).overdrawn = overdraft.overdrawn;
+        }
+        if let Some(advance) = &user.salary_advance {
+            aggregates.entry(advance.currency).advanced = advance.owed;
+        }
+        for plan in &user.installment_plans {
+            let totals = aggregates.entry(plan.currency);
+            totals.financed = totals.financed.checked_add(plan.financed()).unwrap_or(Money::from_minor(u64::MAX));
+        }
+        aggregates
+    }
+
+    pub fn totals(&self, currency: Currency) -> Totals {
+        self.totals.get(&currency).copied().unwrap_or_default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Currency, Totals)> + '_ {
+        self.totals.iter().map(|(currency, totals)| (*currency, *totals))
+    }
+
+    pub fn add(&mut self, other: &Aggregates) {
+        for (currency, totals) in other.iter() {
+            let entry = self.entry(currency);
+            entry.deposits = entry.deposits.checked_add(totals.deposits).unwrap_or(Money::from_minor(u64::MAX));
+            entry.lent = entry.lent.checked_add(totals.lent).unwrap_or(Money::from_minor(u64::MAX));
+            entry.overdrawn = entry.overdrawn.checked_add(totals.overdrawn).unwrap_or(Money::from_minor(u64::MAX));
+            entry.advanced = entry.advanced.checked_add(totals.advanced).unwrap_or(Money::from_minor(u64::MAX));
+            entry.financed = entry.financed.checked_add(totals.financed).unwrap_or(Money::from_minor(u64::MAX));
+        }
+    }
+
+    pub fn subtract(&mut self, other: &Aggregates) {
+        for (currency, totals) in other.iter() {
+            let entry = self.entry(currency);
+            entry.deposits = entry.deposits.checked_sub(totals.deposits).unwrap_or(Money::ZERO);
+            entry.lent = entry.lent.checked_sub(totals.lent).unwrap_or(Money::ZERO);
+            entry.overdrawn = entry.overdrawn.checked_sub(totals.overdrawn).unwrap_or(Money::ZERO);
+            entry.advanced = entry.advanced.checked_sub(totals.advanced).unwrap_or(Money::ZERO);
+            entry.financed = entry.financed.checked_sub(totals.financed).unwrap_or(Money::ZERO);
+        }
+    }
+
+    /// Describe every currency where `self` and `expected` disagree.
+    pub fn mismatches(&self, expected: &Aggregates) -> Vec<String> {
+        let mut currencies: Vec<Currency> = self.totals.keys().chain(expected.totals.keys()).copied().collect();
+        currencies.sort();
+        currencies.dedup();
+        currencies
+            .into_iter()
+            .filter(|currency| self.totals(*currency) != expected.totals(*currency))
+            .map(|currency| {
+                let (actual, expected) = (self.totals(currency), expected.totals(currency));
+                format!(
+                    "{}: tracked deposits {} / lent {} / overdrawn {} / advanced {} / financed {}, \
+                     recomputed deposits {} / lent {} / overdrawn {} / advanced {} / financed {}",
+                    currency,
+                    actual.deposits,
+                    actual.lent,
+                    actual.overdrawn,
+                    actual.advanced,
+                    actual.financed,
+                    expected.deposits,
+                    expected.lent,
+                    expected.overdrawn,
+                    expected.advanced,
+                    expected.financed
+                )
+            })
+            .collect()
+    }
+
+    fn entry(&mut self, currency: Currency) -> &mut Totals {
+        self.totals.entry(currency).or_default()
+    }
+}
+
+impl fmt::Display for Aggregates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Totals")?;
+        for (currency, totals) in self.iter() {
+            write!(
+                f,
+                "\n  {}: deposits {}, lent {}, overdrawn {}, advanced {}, financed {}",
+                currency, totals.deposits, totals.lent, totals.overdrawn, totals.advanced, totals.financed
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::AccountKind;
+    use crate::bank::Bank;
+
+    #[test]
+    fn totals_follow_every_operation() {
+        let mut bank = Bank::new();
+        let ada = bank.open_account_of_kind("Ada", AccountKind::Checking).unwrap();
+        let bob = bank.open_account_of_kind("Bob", AccountKind::Checking).unwrap();
+        bank.deposit(ada, Money::from_major(1_000), Currency::Usd, true).unwrap();
+        bank.deposit(bob, Money::from_major(10), Currency::Eur, false).unwrap();
+        bank.borrow_between(bob, ada, Money::from_major(50), Currency::Usd).unwrap();
+        assert!(bank.transfer(ada, bob, Money::from_major(1_000_000), Currency::Usd).is_err());
+        bank.transfer(ada, bob, Money::from_major(100), Currency::Usd).unwrap();
+        assert_eq!(bank.treasury.aggregates.totals(Currency::Usd).deposits, Money::from_major(980));
+
+        bank.grant_overdraft(bob, Currency::Usd, Money::from_major(20), 1_000).unwrap();
+        bank.withdraw(bob, Money::from_major(160), Currency::Usd).unwrap();
+        let totals = bank.treasury.aggregates.totals(Currency::Usd);
+        assert_eq!(totals.lent, Money::from_major(50));
+        assert!(totals.overdrawn > Money::ZERO);
+        assert_eq!(bank.treasury.aggregates.totals(Currency::Eur).deposits, Money::from_minor(980));
+        assert_eq!(bank.treasury.aggregates, Aggregates::scan(bank.users()));
+        assert!(bank.check_integrity().is_empty());
+    }
+
+    #[test]
+    fn mismatches_name_the_currency() {
+        let mut tracked = Aggregates::default();
+        tracked.entry(Currency::Gbp).deposits = Money::from_major(1);
+        let mismatches = tracked.mismatches(&Aggregates::default());
+        assert_eq!(mismatches.len(), 1);
+        assert!(mismatches[0].starts_with("GBP: tracked deposits 1.00"), "{}", mismatches[0]);
+        assert!(tracked.mismatches(&tracked.clone()).is_empty());
+    }
+
+    #[test]
+    fn subtracting_more_than_is_tracked_stops_at_zero() {
+        let mut small = Aggregates::default();
+        small.entry(Currency::Usd).lent = Money::from_major(1);
+        let mut large = Aggregates::default();
+        large.entry(Currency::Usd).lent = Money::from_major(5);
+        small.subtract(&large);
+        assert_eq!(small.totals(Currency::Usd).lent, Money::ZERO);
+        large.add(&large.clone());
+        assert_eq!(large.totals(Currency::Usd).lent, Money::from_major(10));
+    }
+}
@@ -0,0 +1,277 @@
+use std::time::SystemTime;
+
+use async_graphql::{EmptySubscription, Error, ErrorExtensions, Object, Schema, SimpleObject};
+
+use crate::bank::{AsyncBank, BankError};
+use crate::currency::{Balance, Currency};
+use crate::ledger::Transaction;
+use crate::loan::Loan;
+use crate::money::Money;
+use crate::store::Store;
+use crate::types::UserId;
+use crate::user::User;
+
+/// The GraphQL schema over an `AsyncBank`, served at `/graphql` by
+/// `api::http::router`.
+///
+/// Queries read users with their accounts, balances, loans and transactions;
+/// mutations deposit, withdraw, transfer and borrow. Amounts are decimal
+/// strings such as `"12.50"` and currencies are codes, defaulting to USD.
+/// Errors carry a `code` extension of `NOT_FOUND`, `REJECTED`, `INTERNAL` or
+/// `BAD_USER_INPUT`.
+pub type BankSchema<S> = Schema<Query<S>, Mutation<S>, EmptySubscription>;
+
+pub fn schema<S: Store + Send + 'static>(bank: AsyncBank<S>) -> BankSchema<S> {
+    Schema::new(Query { bank: bank.clone() }, Mutation { bank }, EmptySubscription)
+}
+
+pub struct Query<S: Store> {
+    bank: AsyncBank<S>,
+}
+
+pub struct Mutation<S: Store> {
+    bank: AsyncBank<S>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "User")]
+struct UserNode {
+    id: u32,
+    name: String,
+    accounts: Vec<AccountNode>,
+    /// Loans the user owes or has made.
+    loans: Vec<LoanNode>,
+    transactions: Vec<TransactionNode>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Account")]
+struct AccountNode {
+    id: u32,
+    kind: String,
+    borrowable: bool,
+    balances: Vec<BalanceNode>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Balance")]
+struct BalanceNode {
+    currency: String,
+    deposited: String,
+    withdrawn: String,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Loan")]
+struct LoanNode {
+    id: u32,
+    borrower: u32,
+    lender: u32,
+    currency: String,
+    principal: String,
+    remaining: String,
+    accrued_interest: String,
+    closed: bool,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Transaction")]
+struct TransactionNode {
+    id: u64,
+    /// Seconds since the Unix epoch.
+    timestamp: u64,
+    kind: String,
+    amount: String,
+    currency: String,
+    fee: String,
+    counterparty: Option<u32>,
+    tags: Vec<String>,
+}
+
+impl From<&User> for UserNode {
+    fn from(user: &User) -> Self {
+        UserNode {
+            id: u32::from(user.id),
+            name: user.name.clone(),
+            accounts: user
+                .accounts
+                .iter()
+                .map(|account| AccountNode {
+                    id: u32::from(account.id),
+                    kind: account.kind.to_string(),
+                    borrowable: account.borrowable,
+                    balances: balances(account.balances.iter().map(|(currency, balance)| (*currency, *balance))),
+                })
+                .collect(),
+            loans: user.loans.iter().map(LoanNode::from).collect(),
+            transactions: user.transactions.iter().map(TransactionNode::from).collect(),
+        }
+    }
+}
+
+impl From<&Loan> for LoanNode {
+    fn from(loan: &Loan) -> Self {
+        LoanNode {
+            id: u32::from(loan.id),
+            borrower: u32::from(loan.borrower),
+            lender: u32::from(loan.lender),
+            currency: loan.currency.to_string(),
+            principal: loan.principal.to_string(),
+            remaining: loan.remaining.to_string(),
+            accrued_interest: loan.accrued_interest.to_string(),
+            closed: loan.is_closed(),
+        }
+    }
+}
+
+impl From<&Transaction> for TransactionNode {
+    fn from(transaction: &Transaction) -> Self {
+        TransactionNode {
+            id: transaction.id,
+            timestamp: transaction
+                .timestamp
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            kind: format!("{:?}", transaction.kind),
+            amount: transaction.amount.to_string(),
+            currency: transaction.currency.to_string(),
+            fee: transaction.fee.to_string(),
+            counterparty: transaction.counterparty.map(u32::from),
+            tags: transaction.tags.iter().cloned().collect(),
+        }
+    }
+}
+
+#[Object(name = "Query")]
+impl<S: Store + Send + 'static> Query<S> {
+    /// Every user, by id.
+    async fn users(&self) -> Vec<UserNode> {
+        self.bank
+            .read(|bank| {
+                let mut users: Vec<&User> = bank.users().collect();
+                users.sort_by_key(|user| user.id);
+                users.into_iter().map(UserNode::from).collect()
+            })
+            .await
+    }
+
+    async fn user(&self, id: u32) -> Option<UserNode> {
+        self.bank.read(|bank| bank.get_user(UserId::from(id)).map(UserNode::from)).await
+    }
+
+    /// Every loan, once, by id.
+    async fn loans(&self) -> Vec<LoanNode> {
+        self.bank
+            .read(|bank| {
+                let mut loans: Vec<&Loan> = bank.users().flat_map(User::debts).collect();
+                loans.sort_by_key(|loan| loan.id);
+                loans.into_iter().map(LoanNode::from).collect()
+            })
+            .await
+    }
+}
+
+#[Object(name = "Mutation")]
+impl<S: Store + Send + 'static> Mutation<S> {
+    /// Deposit with the entry fee deducted into the user's primary account.
+    /// Returns the user's balance afterwards.
+    async fn deposit(
+        &self,
+        user: u32,
+        amount: String,
+        #[graphql(default = "USD")] currency: String,
+        #[graphql(default = false)] borrowable: bool,
+    ) -> Result<BalanceNode, Error> {
+        let (id, amount, currency) = (UserId::from(user), parse::<Money>(&amount)?, parse::<Currency>(&currency)?);
+        self.bank.deposit(id, amount, currency, borrowable).await.map_err(error)?;
+        self.balance(id, currency).await
+    }
+
+    /// Withdraw the amount plus the exit fee from the user's primary account.
+    /// Returns the user's balance afterwards.
+    async fn withdraw(
+        &self,
+        user: u32,
+        amount: String,
+        #[graphql(default = "USD")] currency: String,
+    ) -> Result<BalanceNode, Error> {
+        let (id, amount, currency) = (UserId::from(user), parse::<Money>(&amount)?, parse::<Currency>(&currency)?);
+        self.bank.withdraw(id, amount, currency).await.map_err(error)?;
+        self.balance(id, currency).await
+    }
+
+    /// Move money between two users' primary accounts. Returns the amount sent.
+    async fn transfer(
+        &self,
+        from: u32,
+        to: u32,
+        amount: String,
+        #[graphql(default = "USD")] currency: String,
+    ) -> Result<String, Error> {
+        let (amount, currency) = (parse::<Money>(&amount)?, parse::<Currency>(&currency)?);
+        let sent = self
+            .bank
+            .transfer(UserId::from(from), UserId::from(to), amount, currency)
+            .await
+            .map_err(error)?;
+        Ok(sent.to_string())
+    }
+
+    /// Borrow from another user's borrowable deposit. Returns the new loan.
+    async fn borrow(
+        &self,
+        borrower: u32,
+        lender: u32,
+        amount: String,
+        #[graphql(default = "USD")] currency: String,
+    ) -> Result<LoanNode, Error> {
+        let (amount, currency) = (parse::<Money>(&amount)?, parse::<Currency>(&currency)?);
+        let borrower = UserId::from(borrower);
+        let (loan, _) = super::borrow(&self.bank, borrower, UserId::from(lender), amount, currency)
+            .await
+            .map_err(error)?;
+        self.bank
+            .read(|bank| {
+                let user = bank.get_user(borrower)?;
+                user.debts().find(|debt| debt.id == loan).map(LoanNode::from)
+            })
+            .await
+            .ok_or_else(|| error(BankError::Internal(String::from("The new loan is missing"))))
+    }
+}
+
+impl<S: Store + Send + 'static> Mutation<S> {
+    async fn balance(&self, id: UserId, currency: Currency) -> Result<BalanceNode, Error> {
+        let balance = self.bank.balance(id, currency).await.map_err(error)?;
+        Ok(balances([(currency, balance)]).remove(0))
+    }
+}
+
+/// One node per currency, in a stable order.
+fn balances(balances: impl IntoIterator<Item = (Currency, Balance)>) -> Vec<BalanceNode> {
+    let mut balances: Vec<_> = balances.into_iter().collect();
+    balances.sort_by_key(|(currency, _)| *currency);
+    balances
+        .into_iter()
+        .map(|(currency, balance)| BalanceNode {
+            currency: currency.to_string(),
+            deposited: balance.deposited.to_string(),
+            withdrawn: balance.withdrawn.to_string(),
+        })
+        .collect()
+}
+
+fn parse<T: std::str::FromStr<Err = String>>(input: &str) -> Result<T, Error> {
+    input
+        .parse()
+        .map_err(|err: String| Error::new(err).extend_with(|_, extensions| extensions.set("code", "BAD_USER_INPUT")))
+}
+
+fn error(err: BankError) -> Error {
+    let code = match err {
+        BankError::NotFound(_) => "NOT_FOUND",
+        BankError::Rejected(_) => "REJECTED",
+        BankError::Internal(_) => "INTERNAL",
+    };
+    Error::new(err.message()).extend_with(|_, extensions| extensions.set("code", code))
+}
@@ -0,0 +1,111 @@
+use std::net::SocketAddr;
+
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use crate::bank::{AsyncBank, Bank, BankError};
+use crate::currency::Currency;
+use crate::money::Money;
+use crate::store::{MemoryStore, Store};
+use crate::types::UserId;
+
+/// Messages and the service trait generated from `proto/bank.proto`.
+pub mod proto {
+    tonic::include_proto!("bank");
+}
+
+use proto::bank_server::{Bank as BankService, BankServer};
+use proto::{BalanceReply, BorrowReply, BorrowRequest, DepositRequest, GetBalanceRequest, WithdrawRequest};
+
+/// The `bank.Bank` gRPC service over an `AsyncBank`.
+///
+/// Errors come back as `NOT_FOUND` for unknown users, accounts and loans,
+/// `FAILED_PRECONDITION` when the bank refuses the operation, `INTERNAL` when
+/// it fails and `INVALID_ARGUMENT` for unknown currencies.
+pub struct Service<S: Store = MemoryStore> {
+    bank: AsyncBank<S>,
+}
+
+impl<S: Store + Send + 'static> Service<S> {
+    pub fn new(bank: AsyncBank<S>) -> Self {
+        Service { bank }
+    }
+
+    async fn balance(&self, id: UserId, currency: Currency) -> Result<Response<BalanceReply>, Status> {
+        let balance = self.bank.balance(id, currency).await.map_err(status)?;
+        Ok(Response::new(BalanceReply {
+            user: u32::from(id),
+            currency: currency.to_string(),
+            deposited_minor: balance.deposited.minor(),
+            withdrawn_minor: balance.withdrawn.minor(),
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl<S: Store + Send + 'static> BankService for Service<S> {
+    async fn deposit(&self, request: Request<DepositRequest>) -> Result<Response<BalanceReply>, Status> {
+        let request = request.into_inner();
+        let (id, currency) = (UserId::from(request.user), currency(&request.currency)?);
+        let amount = Money::from_minor(request.amount_minor);
+        self.bank.deposit(id, amount, currency, request.borrowable).await.map_err(status)?;
+        self.balance(id, currency).await
+    }
+
+    async fn withdraw(&self, request: Request<WithdrawRequest>) -> Result<Response<BalanceReply>, Status> {
+        let request = request.into_inner();
+        let (id, currency) = (UserId::from(request.user), currency(&request.currency)?);
+        let amount = Money::from_minor(request.amount_minor);
+        self.bank.withdraw(id, amount, currency).await.map_err(status)?;
+        self.balance(id, currency).await
+    }
+
+    async fn borrow(&self, request: Request<BorrowRequest>) -> Result<Response<BorrowReply>, Status> {
+        let request = request.into_inner();
+        let currency = currency(&request.currency)?;
+        let (borrower, lender) = (UserId::from(request.borrower), UserId::from(request.lender));
+        let amount = Money::from_minor(request.amount_minor);
+        let (loan, borrowed) = super::borrow(&self.bank, borrower, lender, amount, currency)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(BorrowReply {
+            loan: u32::from(loan),
+            borrowed_minor: borrowed.minor(),
+            currency: currency.to_string(),
+        }))
+    }
+
+    async fn get_balance(&self, request: Request<GetBalanceRequest>) -> Result<Response<BalanceReply>, Status> {
+        let request = request.into_inner();
+        self.balance(UserId::from(request.user), currency(&request.currency)?).await
+    }
+}
+
+/// Serve the gRPC API for `bank` on `addr` until interrupted with Ctrl-C.
+/// See `api::serve_with` for the store.
+pub fn serve<S: Store + Send + 'static>(bank: Bank<S>, addr: SocketAddr) -> Result<(), String> {
+    super::serve_with(bank, |bank| async move {
+        println!("Serving gRPC on {}; press Ctrl-C to stop.", addr);
+        Server::builder()
+            .add_service(BankServer::new(Service::new(bank)))
+            .serve_with_shutdown(addr, super::interrupted())
+            .await
+            .map_err(|err| format!("Server error: {}", err))
+    })
+}
+
+/// A currency code from a request, USD if it is empty.
+fn currency(code: &str) -> Result<Currency, Status> {
+    if code.is_empty() {
+        return Ok(Currency::Usd);
+    }
+    code.parse().map_err(Status::invalid_argument)
+}
+
+fn status(err: BankError) -> Status {
+    match err {
+        BankError::NotFound(message) => Status::not_found(message),
+        BankError::Rejected(message) => Status::failed_precondition(message),
+        BankError::Internal(message) => Status::internal(message),
+    }
+}
@@ -0,0 +1,314 @@
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::bank::{AsyncBank, Bank, BankError};
+use crate::currency::{Balance, Currency};
+use crate::ledger::{Transaction, TransactionKind};
+use crate::money::Money;
+use crate::store::Store;
+use crate::types::UserId;
+
+/// An error as the API reports it: a status code and a body of
+/// `{"error": "<message>"}`.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl From<BankError> for ApiError {
+    fn from(err: BankError) -> Self {
+        let status = match err {
+            BankError::NotFound(_) => StatusCode::NOT_FOUND,
+            BankError::Rejected(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            BankError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError {
+            status,
+            message: err.into(),
+        }
+    }
+}
+
+/// Values in the request that do not parse, such as unknown currencies.
+impl From<String> for ApiError {
+    fn from(message: String) -> Self {
+        ApiError {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            message,
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError {
+            status: rejection.status(),
+            message: rejection.body_text(),
+        }
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        ApiError {
+            status: rejection.status(),
+            message: rejection.body_text(),
+        }
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError {
+            status: rejection.status(),
+            message: rejection.body_text(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(serde_json::json!({ "error": self.message }))).into_response()
+    }
+}
+
+type Body<T> = Result<Json<T>, JsonRejection>;
+
+#[derive(Deserialize)]
+struct NewUser {
+    name: String,
+}
+
+/// Amounts are decimal strings such as `"12.50"` and currencies are codes
+/// such as `"EUR"`, defaulting to USD, as on the command line.
+#[derive(Deserialize)]
+struct Deposit {
+    #[serde(deserialize_with = "parsed")]
+    amount: Money,
+    #[serde(default = "usd", deserialize_with = "parsed")]
+    currency: Currency,
+    #[serde(default)]
+    borrowable: bool,
+}
+
+#[derive(Deserialize)]
+struct Withdrawal {
+    #[serde(deserialize_with = "parsed")]
+    amount: Money,
+    #[serde(default = "usd", deserialize_with = "parsed")]
+    currency: Currency,
+}
+
+#[derive(Deserialize)]
+struct Transfer {
+    from: UserId,
+    to: UserId,
+    #[serde(deserialize_with = "parsed")]
+    amount: Money,
+    #[serde(default = "usd", deserialize_with = "parsed")]
+    currency: Currency,
+}
+
+#[derive(Deserialize)]
+struct Borrow {
+    borrower: UserId,
+    lender: UserId,
+    #[serde(deserialize_with = "parsed")]
+    amount: Money,
+    #[serde(default = "usd", deserialize_with = "parsed")]
+    currency: Currency,
+}
+
+#[derive(Deserialize)]
+struct LedgerQuery {
+    tag: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BalanceView {
+    user: UserId,
+    currency: String,
+    deposited: String,
+    withdrawn: String,
+}
+
+impl BalanceView {
+    fn new(user: UserId, currency: Currency, balance: Balance) -> Self {
+        BalanceView {
+            user,
+            currency: currency.to_string(),
+            deposited: balance.deposited.to_string(),
+            withdrawn: balance.withdrawn.to_string(),
+        }
+    }
+}
+
+/// A ledger entry, with its time in seconds since the Unix epoch.
+#[derive(Serialize)]
+struct TransactionView {
+    id: u64,
+    timestamp: u64,
+    kind: TransactionKind,
+    amount: String,
+    currency: String,
+    fee: String,
+    counterparty: Option<UserId>,
+    tags: Vec<String>,
+}
+
+impl From<&Transaction> for TransactionView {
+    fn from(transaction: &Transaction) -> Self {
+        TransactionView {
+            id: transaction.id,
+            timestamp: transaction
+                .timestamp
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            kind: transaction.kind,
+            amount: transaction.amount.to_string(),
+            currency: transaction.currency.to_string(),
+            fee: transaction.fee.to_string(),
+            counterparty: transaction.counterparty,
+            tags: transaction.tags.iter().cloned().collect(),
+        }
+    }
+}
+
+/// The HTTP API over `bank`:
+///
+/// - `POST /users` with `{"name"}` registers a user;
+/// - `POST /users/{id}/deposits` with `{"amount", "currency", "borrowable"}`
+///   and `POST /users/{id}/withdrawals` with `{"amount", "currency"}` move
+///   money in and out of the user's primary account;
+/// - `POST /transfers` with `{"from", "to", "amount", "currency"}`;
+/// - `POST /loans` with `{"borrower", "lender", "amount", "currency"}`;
+/// - `GET /users/{id}/balances/{currency}`;
+/// - `GET /users/{id}/ledger`, optionally `?tag=`;
+/// - with the `graphql` feature, `/graphql` for `api::graphql::schema`.
+///
+/// Errors come back as 404 for unknown users, accounts and loans, 422 when
+/// the bank refuses the operation and 500 when it fails.
+pub fn router<S: Store + Send + 'static>(bank: AsyncBank<S>) -> Router {
+    let router = Router::new()
+        .route("/users", post(create_user::<S>))
+        .route("/users/{id}/deposits", post(deposit::<S>))
+        .route("/users/{id}/withdrawals", post(withdraw::<S>))
+        .route("/users/{id}/balances/{currency}", get(balance::<S>))
+        .route("/users/{id}/ledger", get(ledger::<S>))
+        .route("/transfers", post(transfer::<S>))
+        .route("/loans", post(borrow::<S>));
+    #[cfg(feature = "graphql")]
+    let router = router.route_service(
+        "/graphql",
+        async_graphql_axum::GraphQL::new(super::graphql::schema(bank.clone())),
+    );
+    router.with_state(bank)
+}
+
+/// Serve the HTTP API for `bank` on `addr` until interrupted with Ctrl-C.
+/// See `api::serve_with` for the store.
+pub fn serve<S: Store + Send + 'static>(bank: Bank<S>, addr: SocketAddr) -> Result<(), String> {
+    super::serve_with(bank, |bank| async move {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|err| format!("Cannot listen on {}: {}", addr, err))?;
+        println!("Serving the bank on http://{}; press Ctrl-C to stop.", addr);
+        axum::serve(listener, router(bank))
+            .with_graceful_shutdown(super::interrupted())
+            .await
+            .map_err(|err| format!("Server error: {}", err))
+    })
+}
+
+async fn create_user<S: Store + Send + 'static>(
+    State(bank): State<AsyncBank<S>>,
+    body: Body<NewUser>,
+) -> Result<(StatusCode, Json<serde_json::Value>), ApiError> {
+    let Json(body) = body?;
+    let id = bank.open_account(&body.name).await?;
+    Ok((StatusCode::CREATED, Json(serde_json::json!({ "id": id }))))
+}
+
+async fn deposit<S: Store + Send + 'static>(
+    State(bank): State<AsyncBank<S>>,
+    id: Result<Path<UserId>, PathRejection>,
+    body: Body<Deposit>,
+) -> Result<Json<BalanceView>, ApiError> {
+    let (Path(id), Json(body)) = (id?, body?);
+    bank.deposit(id, body.amount, body.currency, body.borrowable).await?;
+    let balance = bank.balance(id, body.currency).await?;
+    Ok(Json(BalanceView::new(id, body.currency, balance)))
+}
+
+async fn withdraw<S: Store + Send + 'static>(
+    State(bank): State<AsyncBank<S>>,
+    id: Result<Path<UserId>, PathRejection>,
+    body: Body<Withdrawal>,
+) -> Result<Json<BalanceView>, ApiError> {
+    let (Path(id), Json(body)) = (id?, body?);
+    bank.withdraw(id, body.amount, body.currency).await?;
+    let balance = bank.balance(id, body.currency).await?;
+    Ok(Json(BalanceView::new(id, body.currency, balance)))
+}
+
+async fn transfer<S: Store + Send + 'static>(
+    State(bank): State<AsyncBank<S>>,
+    body: Body<Transfer>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let Json(body) = body?;
+    let sent = bank.transfer(body.from, body.to, body.amount, body.currency).await?;
+    Ok(Json(serde_json::json!({ "sent": sent.to_string(), "currency": body.currency.to_string() })))
+}
+
+async fn borrow<S: Store + Send + 'static>(
+    State(bank): State<AsyncBank<S>>,
+    body: Body<Borrow>,
+) -> Result<(StatusCode, Json<serde_json::Value>), ApiError> {
+    let Json(body) = body?;
+    let (loan, borrowed) = super::borrow(&bank, body.borrower, body.lender, body.amount, body.currency).await?;
+    let loan = serde_json::json!({ "loan": loan, "borrowed": borrowed.to_string(), "currency": body.currency.to_string() });
+    Ok((StatusCode::CREATED, Json(loan)))
+}
+
+async fn balance<S: Store + Send + 'static>(
+    State(bank): State<AsyncBank<S>>,
+    path: Result<Path<(UserId, String)>, PathRejection>,
+) -> Result<Json<BalanceView>, ApiError> {
+    let Path((id, currency)) = path?;
+    let currency: Currency = currency.parse().map_err(ApiError::from)?;
+    let balance = bank.balance(id, currency).await?;
+    Ok(Json(BalanceView::new(id, currency, balance)))
+}
+
+async fn ledger<S: Store + Send + 'static>(
+    State(bank): State<AsyncBank<S>>,
+    id: Result<Path<UserId>, PathRejection>,
+    query: Result<Query<LedgerQuery>, QueryRejection>,
+) -> Result<Json<Vec<TransactionView>>, ApiError> {
+    let (Path(id), Query(query)) = (id?, query?);
+    let transactions = bank
+        .read(|bank| {
+            let history = bank.history(id, query.tag.as_deref())?;
+            Ok::<_, BankError>(history.into_iter().map(TransactionView::from).collect())
+        })
+        .await?;
+    Ok(Json(transactions))
+}
+
+fn parsed<'de, D: Deserializer<'de>, T: FromStr<Err = String>>(deserializer: D) -> Result<T, D::Error> {
+    String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+}
+
+fn usd() -> Currency {
+    Currency::Usd
+}
@@ -0,0 +1,62 @@
+#[cfg(any(feature = "server", feature = "grpc"))]
+use std::future::Future;
+
+#[cfg(any(feature = "server", feature = "grpc"))]
+use crate::bank::{AsyncBank, Bank, BankError};
+#[cfg(any(feature = "server", feature = "grpc"))]
+use crate::currency::Currency;
+#[cfg(any(feature = "server", feature = "grpc"))]
+use crate::money::Money;
+#[cfg(any(feature = "server", feature = "grpc"))]
+use crate::store::Store;
+#[cfg(any(feature = "server", feature = "grpc"))]
+use crate::types::{LoanId, UserId};
+
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "server")]
+pub mod http;
+
+/// Run `server` over `bank` on a multi-threaded runtime until it stops. The
+/// store must write changes through as they happen: nothing is saved when
+/// the server stops.
+#[cfg(any(feature = "server", feature = "grpc"))]
+fn serve_with<S: Store + Send + 'static, F: Future<Output = Result<(), String>>>(
+    bank: Bank<S>,
+    server: impl FnOnce(AsyncBank<S>) -> F,
+) -> Result<(), String> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|err| format!("Cannot start the server: {}", err))?;
+    runtime.block_on(server(AsyncBank::new(bank)))
+}
+
+/// Resolves once the process is interrupted with Ctrl-C.
+#[cfg(any(feature = "server", feature = "grpc"))]
+async fn interrupted() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// `Bank::borrow_between`, also returning the id of the new loan.
+#[cfg(any(feature = "server", feature = "grpc"))]
+async fn borrow<S: Store + Send + 'static>(
+    bank: &AsyncBank<S>,
+    borrower: UserId,
+    lender: UserId,
+    amount: Money,
+    currency: Currency,
+) -> Result<(LoanId, Money), BankError> {
+    bank.write(move |bank| {
+        let borrowed = bank.borrow_between(borrower, lender, amount, currency)?;
+        let loan = bank
+            .get_user(borrower)
+            .and_then(|user| user.debts().last())
+            .map(|loan| loan.id)
+            .ok_or_else(|| BankError::Internal(String::from("The new loan is missing")))?;
+        Ok((loan, borrowed))
+    })
+    .await
+}
@@ -0,0 +1,1543 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::account::{self, Account, AccountKind};
+use crate::advance;
+use crate::aggregates::Aggregates;
+use crate::currency::Currency;
+use crate::event::{BankEvent, EVENT_VERSION, NextIds, Observers, RecordedEvent};
+use crate::income::{self, RecurringIncome};
+use crate::installment::{self, InstallmentPlan};
+use crate::interest::InterestForecast;
+use crate::ledger::{self, Transaction};
+use crate::loan;
+use crate::metrics::{Metrics, Operation};
+use crate::money::Money;
+use crate::overdraft::OverdraftAgreement;
+use crate::payee::Payee;
+use crate::policy::{Policy, PolicyChange};
+use crate::sandbox::Seed;
+use crate::store::{MemoryStore, Store};
+use crate::time::{self, Clock, MockClock};
+use crate::types::{AccountId, LoanId, PlanId, UserId};
+use crate::user::{Treasury, User};
+
+#[cfg(feature = "async")]
+mod async_bank;
+mod concurrent;
+mod error;
+mod replay;
+
+#[cfg(any(feature = "server", feature = "grpc"))]
+pub use async_bank::AsyncBank;
+pub use error::BankError;
+
+/// Version of the layout written by `save_json`. Files saved before
+/// versioning was introduced hold a bare `Bank` and are read as version 1.
+/// Bump this and add a step to `migrate` for changes that `#[serde(default)]`
+/// cannot absorb.
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Serialize)]
+struct SavedBankRef<'a> {
+    version: u32,
+    bank: &'a Bank,
+}
+
+#[derive(Deserialize)]
+struct SavedBank {
+    version: u32,
+    bank: Value,
+}
+
+/// Whether a bank holds real or test money. Sandbox banks can mint funds
+/// through `Bank::faucet`, and the two are never mixed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Environment {
+    #[default]
+    Production,
+    Sandbox,
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Environment::Production => write!(f, "production"),
+            Environment::Sandbox => write!(f, "sandbox"),
+        }
+    }
+}
+
+/// Registry owning every `User` and the shared `Treasury`.
+/// Operations address users by id rather than by reference.
+///
+/// State is held in memory and written through to the `Store` after every
+/// successful operation, which is then reported to the observers registered
+/// with `on_event`. Interest accrues against `clock`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bank<S: Store = MemoryStore> {
+   pub treasury: Treasury,
+   users: HashMap<UserId, User>,
+   next_user_id: u32,
+   environment: Environment,
+   #[serde(skip)]
+   metrics: Metrics,
+   #[serde(skip)]
+   store: S,
+   #[serde(skip, default = "time::system_clock")]
+   clock: Arc<dyn Clock>,
+   #[serde(skip)]
+   observers: Observers,
+   /// The operation `tracked` last ran, until `record` commits it with its
+   /// events.
+   #[serde(skip)]
+   pending: Option<Pending>,
+}
+
+impl<S: Store + Default> Default for Bank<S> {
+    fn default() -> Self {
+        Bank {
+            treasury: Treasury::default(),
+            users: HashMap::new(),
+            next_user_id: 0,
+            environment: Environment::default(),
+            metrics: Metrics::default(),
+            store: S::default(),
+            clock: time::system_clock(),
+            observers: Observers::default(),
+            pending: None,
+        }
+    }
+}
+
+impl Bank {
+    /// An empty bank that lives only in memory.
+    pub fn new() -> Self {
+        Bank::default()
+    }
+
+    /// Load a bank previously written by `save_json`.
+    pub fn load_json(path: &Path) -> Result<Bank, BankError> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| BankError::Internal(format!("Cannot read {}: {}", path.display(), err)))?;
+        let invalid =
+            |err: serde_json::Error| BankError::Internal(format!("Invalid bank state in {}: {}", path.display(), err));
+        let value: Value = serde_json::from_str(&data).map_err(invalid)?;
+        let saved = if value.get("version").is_some() {
+            serde_json::from_value(value).map_err(invalid)?
+        } else {
+            SavedBank { version: 1, bank: value }
+        };
+        let bank = migrate(saved).map_err(BankError::Internal)?;
+        let mut bank: Bank = serde_json::from_value(bank).map_err(invalid)?;
+        bank.reserve_ids();
+        bank.treasury.aggregates = Aggregates::scan(bank.users.values());
+        Ok(bank)
+    }
+
+    /// Write the whole bank state to `path` as JSON.
+    pub fn save_json(&self, path: &Path) -> Result<(), BankError> {
+        let saved = SavedBankRef {
+            version: SCHEMA_VERSION,
+            bank: self,
+        };
+        let data = serde_json::to_string_pretty(&saved)
+            .map_err(|err| BankError::Internal(format!("Cannot serialize bank state: {}", err)))?;
+        fs::write(path, data).map_err(|err| BankError::Internal(format!("Cannot write {}: {}", path.display(), err)))
+    }
+}
+
+impl<S: Store> Bank<S> {
+    /// Load every user and the treasury from `store`, or rebuild them with
+    /// `Bank::replay` if the store keeps an event log instead.
+    pub fn open(store: S) -> Result<Bank<S>, BankError> {
+        if let Some(events) = store.load_events().map_err(BankError::Internal)? {
+            let replayed = Bank::replay(events).map_err(BankError::Internal)?;
+            return Ok(Bank {
+                treasury: replayed.treasury,
+                users: replayed.users,
+                next_user_id: replayed.next_user_id,
+                environment: replayed.environment,
+                metrics: Metrics::default(),
+                store,
+                clock: time::system_clock(),
+                observers: Observers::default(),
+                pending: None,
+            });
+        }
+        let users: HashMap<UserId, User> = store
+            .load_users()
+            .map_err(BankError::Internal)?
+            .into_iter()
+            .map(|user| (user.id, user))
+            .collect();
+        let mut bank = Bank {
+            treasury: store.load_treasury().map_err(BankError::Internal)?,
+            next_user_id: users.keys().map(|id| u32::from(*id)).max().unwrap_or(0),
+            environment: store.load_environment().map_err(BankError::Internal)?,
+            metrics: Metrics::default(),
+            users,
+            store,
+            clock: time::system_clock(),
+            observers: Observers::default(),
+            pending: None,
+        };
+        bank.reserve_ids();
+        bank.treasury.aggregates = Aggregates::scan(bank.users.values());
+        Ok(bank)
+    }
+
+    /// Latencies of the operations run since this bank was opened.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        &*self.clock
+    }
+
+    /// Accrue interest against `clock` from now on, e.g. a `MockClock`.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Call `observer` with every `BankEvent` from now on.
+    pub fn on_event(&mut self, observer: impl Fn(&BankEvent) + Send + Sync + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    pub fn environment(&self) -> Environment {
+        self.environment
+    }
+
+    /// Choose between real and test money. Only possible while the bank has
+    /// no users, so balances of one kind can never turn into the other.
+    pub fn set_environment(&mut self, environment: Environment) -> Result<(), BankError> {
+        if environment == self.environment {
+            return Ok(());
+        }
+        if !self.users.is_empty() {
+            return Err(BankError::Rejected(format!(
+                "This is a {} bank with existing accounts; it cannot become a {} bank",
+                self.environment, environment
+            )));
+        }
+        self.write_and_record(
+            |store| store.save_environment(environment),
+            [BankEvent::EnvironmentChanged { environment }],
+        )?;
+        self.environment = environment;
+        Ok(())
+    }
+
+    /// Mint `amount` of test money into the user's primary account. Sandbox only.
+    pub fn faucet(&mut self, id: UserId, amount: Money, currency: Currency) -> Result<Money, BankError> {
+        if self.environment != Environment::Sandbox {
+            return Err(BankError::Rejected(String::from("The faucet is only available in sandbox banks")));
+        }
+        let account = self.primary_account(id)?;
+        let credited = self.tracked(Operation::Faucet, &[id], |bank| {
+            let user = bank.users.get_mut(&id).ok_or_else(|| unknown_user(id))?;
+            Ok(user.receive_test_funds(account, amount, currency, &mut bank.treasury)?)
+        })?;
+        self.record([BankEvent::Minted { user: id, account, amount, currency }])?;
+        Ok(credited)
+    }
+
+    /// Register a new user with a savings account and return their id.
+    pub fn open_account(&mut self, name: &str) -> Result<UserId, BankError> {
+        self.open_account_of_kind(name, AccountKind::default())
+    }
+
+    /// Register a new user whose primary account is of `kind` and return their id.
+    pub fn open_account_of_kind(&mut self, name: &str, kind: AccountKind) -> Result<UserId, BankError> {
+        let id = UserId::from(self.next_user_id + 1);
+        let account = self.tracked(Operation::OpenAccount, &[id], |bank| {
+            bank.next_user_id += 1;
+            let account = Account::new(kind);
+            let account_id = account.id;
+            bank.users.insert(
+                id,
+                User {
+                    id,
+                    name: name.to_string(),
+                    accounts: vec![account],
+                    ..Default::default()
+                },
+            );
+            Ok(account_id)
+        })?;
+        self.record([BankEvent::UserRegistered {
+            user: id,
+            name: name.to_string(),
+            account,
+            kind,
+        }])?;
+        Ok(id)
+    }
+
+    /// Open another account of `kind` for an existing user.
+    pub fn add_account(&mut self, id: UserId, kind: AccountKind) -> Result<AccountId, BankError> {
+        let account = self.tracked(Operation::OpenAccount, &[id], |bank| {
+            let user = bank.users.get_mut(&id).ok_or_else(|| unknown_user(id))?;
+            let account = Account::new(kind);
+            let account_id = account.id;
+            user.accounts.push(account);
+            Ok(account_id)
+        })?;
+        self.record([BankEvent::AccountOpened { user: id, account, kind }])?;
+        Ok(account)
+    }
+
+    /// The account used for the user when none is named.
+    pub fn primary_account(&self, id: UserId) -> Result<AccountId, BankError> {
+        let user = self.users.get(&id).ok_or_else(|| unknown_user(id))?;
+        let account = user
+            .primary_account()
+            .ok_or_else(|| BankError::NotFound(format!("User {} has no accounts", id)))?;
+        Ok(account.id)
+    }
+
+    /// The user holding `account`.
+    pub fn owner_of(&self, account: AccountId) -> Result<UserId, BankError> {
+        self.users
+            .values()
+            .find(|user| user.account(account).is_ok())
+            .map(|user| user.id)
+            .ok_or_else(|| BankError::NotFound(format!("Unknown account {}", account)))
+    }
+
+    /// `account` if it belongs to the user, or the user's primary account if
+    /// none is given.
+    pub fn resolve_account(&self, id: UserId, account: Option<AccountId>) -> Result<AccountId, BankError> {
+        match account {
+            Some(account) => {
+                let user = self.users.get(&id).ok_or_else(|| unknown_user(id))?;
+                let account = user.account(account)?;
+                Ok(account.id)
+            }
+            None => self.primary_account(id),
+        }
+    }
+
+    /// Wipe every user and the treasury, then rebuild the state described by
+    /// `seed`. Sandbox only.
+    pub fn reset(&mut self, seed: Seed) -> Result<(), BankError> {
+        if self.environment != Environment::Sandbox {
+            return Err(BankError::Rejected(String::from("Only sandbox banks can be reset")));
+        }
+        let environment = self.environment;
+        self.write_and_record(
+            |store| store.clear().and_then(|()| store.save_environment(environment)),
+            [BankEvent::Reset],
+        )?;
+        self.wipe();
+        Ok(seed.populate(self)?)
+    }
+
+    /// Forget every user and the treasury, leaving the store alone.
+    fn wipe(&mut self) {
+        self.users.clear();
+        self.treasury = Treasury::default();
+        self.next_user_id = 0;
+    }
+
+    /// Compare the incrementally maintained treasury aggregates with a full
+    /// recomputation over every user. Returns one line per discrepancy.
+    pub fn check_integrity(&self) -> Vec<String> {
+        self.treasury.aggregates.mismatches(&Aggregates::scan(self.users.values()))
+    }
+
+    /// Account, loan and transaction ids are process-wide; keep them unique
+    /// after a load.
+    fn reserve_ids(&self) {
+        let users = self.users.values();
+        let transactions = users
+            .flat_map(|user| &user.transactions)
+            .chain(&self.treasury.transactions);
+        if let Some(id) = transactions.map(|tx| tx.id).max() {
+            ledger::reserve_ids_through(id);
+        }
+        let loans = self.users.values().flat_map(|user| &user.loans);
+        if let Some(id) = loans.map(|loan| loan.id).max() {
+            loan::reserve_ids_through(id);
+        }
+        let accounts = self.users.values().flat_map(|user| &user.accounts);
+        if let Some(id) = accounts.map(|account| account.id).max() {
+            account::reserve_ids_through(id);
+        }
+        let plans = self.users.values().flat_map(|user| &user.installment_plans);
+        if let Some(id) = plans.map(|plan| plan.id).max() {
+            installment::reserve_ids_through(id);
+        }
+    }
+
+    pub fn get_user(&self, id: UserId) -> Option<&User> {
+        self.users.get(&id)
+    }
+
+    /// Look a user up by name (ignoring ASCII case) or by numeric id.
+    pub fn find_user(&self, name_or_id: &str) -> Option<UserId> {
+        if let Ok(id) = name_or_id.trim_start_matches('#').parse::<u32>() {
+            let id = UserId::from(id);
+            return self.users.contains_key(&id).then_some(id);
+        }
+        self.users
+            .values()
+            .filter(|user| user.name.eq_ignore_ascii_case(name_or_id))
+            .map(|user| user.id)
+            .min()
+    }
+
+    pub fn users(&self) -> impl Iterator<Item = &User> {
+        self.users.values()
+    }
+
+    /// Deposit with the entry fee deducted into the user's primary account.
+    pub fn deposit(
+        &mut self,
+        id: UserId,
+        amount: Money,
+        currency: Currency,
+        is_borrowable: bool,
+    ) -> Result<(), BankError> {
+        let account = self.primary_account(id)?;
+        self.deposit_into(account, amount, currency, is_borrowable)
+    }
+
+    /// Deposit with the entry fee deducted into `account`.
+    pub fn deposit_into(
+        &mut self,
+        account: AccountId,
+        amount: Money,
+        currency: Currency,
+        is_borrowable: bool,
+    ) -> Result<(), BankError> {
+        let id = self.owner_of(account)?;
+        let fee = self.treasury.fees.entry_fee(amount);
+        self.tracked(Operation::Deposit, &[id], |bank| {
+            let now = bank.clock.now();
+            let user = bank.users.get_mut(&id).ok_or_else(|| unknown_user(id))?;
+            bank.treasury.charge_overdraft_interest(user, currency, now)?;
+            let fees = bank.treasury.fees;
+            user.deposit_with_fee(account, amount, currency, &mut bank.treasury, &fees, is_borrowable)?;
+            bank.settle_after_credit(id, account)
+        })?;
+        let deposited = BankEvent::Deposited {
+            user: id,
+            account,
+            amount,
+            currency,
+            borrowable: is_borrowable,
+        };
+        self.record([deposited].into_iter().chain(charged(id, account, fee, currency)))
+    }
+
+    /// Withdraw `amount` plus the exit fee from the user's primary account.
+    pub fn withdraw(&mut self, id: UserId, amount: Money, currency: Currency) -> Result<Money, BankError> {
+        let account = self.primary_account(id)?;
+        self.withdraw_from(account, amount, currency)
+    }
+
+    /// Withdraw `amount` plus the exit fee from `account`.
+    pub fn withdraw_from(&mut self, account: AccountId, amount: Money, currency: Currency) -> Result<Money, BankError> {
+        let id = self.owner_of(account)?;
+        let fee = self.treasury.fees.exit_fee(amount);
+        let withdrawn = self.tracked(Operation::Withdraw, &[id], |bank| {
+            let now = bank.clock.now();
+            let user = bank.users.get_mut(&id).ok_or_else(|| unknown_user(id))?;
+            user.start_debit(account, now)?;
+            bank.treasury.charge_overdraft_interest(user, currency, now)?;
+            let fees = bank.treasury.fees;
+            Ok(user.withdraw_with_fee(account, amount, currency, &mut bank.treasury, &fees)?)
+        })?;
+        let event = BankEvent::Withdrawn { user: id, account, amount, currency };
+        self.record([event].into_iter().chain(charged(id, account, fee, currency)))?;
+        Ok(withdrawn)
+    }
+
+    /// Exchange part of the `from` balance in the user's primary account into
+    /// `to`. See `User::convert`.
+    pub fn convert(
+        &mut self,
+        id: UserId,
+        amount: Money,
+        from: Currency,
+        to: Currency,
+        rate_bps: u32,
+    ) -> Result<Money, BankError> {
+        let account = self.primary_account(id)?;
+        let credited = self.tracked(Operation::Convert, &[id], |bank| {
+            let now = bank.clock.now();
+            let user = bank.users.get_mut(&id).ok_or_else(|| unknown_user(id))?;
+            user.account(account)?.ensure_unlocked(now)?;
+            Ok(user.convert(account, amount, from, to, rate_bps, &mut bank.treasury)?)
+        })?;
+        self.record([BankEvent::Converted {
+            user: id,
+            account,
+            amount,
+            from,
+            to,
+            rate_bps,
+            credited,
+        }])?;
+        Ok(credited)
+    }
+
+    /// Move `amount` from one user's primary account to another's. See
+    /// `User::transfer_to`.
+    pub fn transfer(
+        &mut self,
+        from: UserId,
+        to: UserId,
+        amount: Money,
+        currency: Currency,
+    ) -> Result<Money, BankError> {
+        if from == to {
+            return Err(BankError::Rejected(String::from("Cannot transfer to yourself")));
+        }
+        let (from, to) = (self.primary_account(from)?, self.primary_account(to)?);
+        self.transfer_between(from, to, amount, currency)
+    }
+
+    /// Move `amount` from account `from` to account `to`, which may belong to
+    /// the same user.
+    pub fn transfer_between(
+        &mut self,
+        from: AccountId,
+        to: AccountId,
+        amount: Money,
+        currency: Currency,
+    ) -> Result<Money, BankError> {
+        let (sender_id, receiver_id) = (self.owner_of(from)?, self.owner_of(to)?);
+        let sent = if sender_id == receiver_id {
+            self.tracked(Operation::Transfer, &[sender_id], |bank| {
+                let now = bank.clock.now();
+                let user = bank.users.get_mut(&sender_id).ok_or_else(|| unknown_user(sender_id))?;
+                user.start_debit(from, now)?;
+                Ok(user.move_between(from, to, amount, currency)?)
+            })?
+        } else {
+            self.tracked(Operation::Transfer, &[sender_id, receiver_id], |bank| {
+                let now = bank.clock.now();
+                let [sender, receiver] = bank.pair_mut(sender_id, receiver_id)?;
+                sender.start_debit(from, now)?;
+                let sent = sender.transfer_to(from, receiver, to, amount, currency)?;
+                bank.settle_after_credit(receiver_id, to)?;
+                Ok(sent)
+            })?
+        };
+        self.record([BankEvent::Transferred {
+            sender: sender_id,
+            account: from,
+            receiver: receiver_id,
+            receiver_account: to,
+            amount: sent,
+            currency,
+        }])?;
+        Ok(sent)
+    }
+
+    /// The salary-like income detected in the user's history, if any. See
+    /// `income::detect`.
+    pub fn recurring_income(&self, id: UserId) -> Result<Option<RecurringIncome>, BankError> {
+        let user = self.users.get(&id).ok_or_else(|| unknown_user(id))?;
+        Ok(income::detect(id, &user.transactions))
+    }
+
+    /// The most the user can be advanced now: `advance::ADVANCE_LIMIT_BPS` of
+    /// their expected salary. Fails if they have no recurring income, their
+    /// salary is overdue or an advance is still outstanding.
+    pub fn advance_limit(&self, id: UserId) -> Result<(Money, Currency), BankError> {
+        let user = self.users.get(&id).ok_or_else(|| unknown_user(id))?;
+        if user.salary_advance.is_some() {
+            return Err(BankError::Rejected(format!("User {} already has an outstanding salary advance", id)));
+        }
+        let income = income::detect(id, &user.transactions)
+            .ok_or_else(|| format!("User {} has no recurring income to advance against", id))?;
+        if income.is_overdue(self.clock.now()) {
+            return Err(BankError::Rejected(format!("The expected salary of user {} is overdue", id)));
+        }
+        if user.overdue_installments(self.clock.now()) > 0 {
+            return Err(BankError::Rejected(format!("User {} has overdue installments", id)));
+        }
+        Ok((income.amount.mul_bps(advance::ADVANCE_LIMIT_BPS), income.currency))
+    }
+
+    /// Advance `amount` of the user's next salary into `account`, for a flat
+    /// fee. It is repaid automatically from the next salary-like credit.
+    pub fn take_salary_advance(&mut self, account: AccountId, amount: Money) -> Result<Money, BankError> {
+        let id = self.owner_of(account)?;
+        let (limit, currency) = self.advance_limit(id)?;
+        if amount == Money::ZERO {
+            return Err(BankError::Rejected(String::from("Cannot advance nothing")));
+        }
+        if amount > limit {
+            return Err(BankError::Rejected(format!("Cannot advance more than {} {}", limit, currency)));
+        }
+        let advanced = self.tracked(Operation::Advance, &[id], |bank| {
+            let now = bank.clock.now();
+            let user = bank.users.get_mut(&id).ok_or_else(|| unknown_user(id))?;
+            Ok(user.take_salary_advance(account, amount, currency, now, &mut bank.treasury)?)
+        })?;
+        self.record([BankEvent::SalaryAdvanced { user: id, account, amount, currency }])?;
+        Ok(advanced)
+    }
+
+    /// Buy from the owner of `payee_account` for `price`, paid back to the
+    /// treasury in `installments` from `account` every
+    /// `installment::INSTALLMENT_INTERVAL`, the first one now. The payee is
+    /// paid in full at once, less the merchant fee. Payers with overdue
+    /// installments cannot start new plans.
+    pub fn buy_in_installments(
+        &mut self,
+        account: AccountId,
+        payee_account: AccountId,
+        price: Money,
+        currency: Currency,
+        installments: u32,
+    ) -> Result<PlanId, BankError> {
+        let (payer_id, payee_id) = (self.owner_of(account)?, self.owner_of(payee_account)?);
+        if payer_id == payee_id {
+            return Err(BankError::Rejected(String::from("Cannot buy from yourself")));
+        }
+        if !(2..=installment::MAX_INSTALLMENTS).contains(&installments) {
+            return Err(BankError::Rejected(format!(
+                "A purchase is split into 2 to {} installments",
+                installment::MAX_INSTALLMENTS
+            )));
+        }
+        if price == Money::ZERO {
+            return Err(BankError::Rejected(String::from("Cannot buy for nothing")));
+        }
+        let payer = self.users.get(&payer_id).ok_or_else(|| unknown_user(payer_id))?;
+        if payer.overdue_installments(self.clock.now()) > 0 {
+            return Err(BankError::Rejected(format!("User {} has overdue installments", payer_id)));
+        }
+        let id = self.tracked(Operation::Installments, &[payer_id, payee_id], |bank| {
+            let now = bank.clock.now();
+            let plan = InstallmentPlan::new(payer_id, payee_id, account, price, currency, installments, now);
+            let (id, fee) = (plan.id, plan.fee);
+            let payer = bank.users.get_mut(&payer_id).ok_or_else(|| unknown_user(payer_id))?;
+            payer.open_installment_plan(plan, now, &mut bank.treasury)?;
+            let payee = bank.users.get_mut(&payee_id).ok_or_else(|| unknown_user(payee_id))?;
+            payee.receive_installment_sale(payee_account, payer_id, price, fee, currency, &mut bank.treasury)?;
+            Ok(id)
+        })?;
+        self.record([BankEvent::BoughtInInstallments {
+            plan: id,
+            payer: payer_id,
+            account,
+            payee: payee_id,
+            payee_account,
+            price,
+            currency,
+            installments,
+        }])?;
+        Ok(id)
+    }
+
+    /// Collect the user's installments that have fallen due. Returns the
+    /// amount collected; see `User::collect_installments`.
+    pub fn collect_installments(&mut self, id: UserId) -> Result<Money, BankError> {
+        let collected = self.tracked(Operation::Installments, &[id], |bank| bank.collect_due_installments(id))?;
+        self.record([BankEvent::InstallmentsCollected { user: id, amount: collected }])?;
+        Ok(collected)
+    }
+
+    /// Have `borrower_id` borrow `amount` from `lender_id`, between their
+    /// primary accounts. See `User::borrow`.
+    pub fn borrow_between(
+        &mut self,
+        borrower_id: UserId,
+        lender_id: UserId,
+        amount: Money,
+        currency: Currency,
+    ) -> Result<Money, BankError> {
+        let account = self.primary_account(borrower_id)?;
+        let lender_account = self.primary_account(lender_id)?;
+        self.borrow_into(account, lender_account, amount, currency)
+    }
+
+    /// Borrow `amount` into `account` from the deposits in `lender_account`.
+    pub fn borrow_into(
+        &mut self,
+        account: AccountId,
+        lender_account: AccountId,
+        amount: Money,
+        currency: Currency,
+    ) -> Result<Money, BankError> {
+        let (borrower_id, lender_id) = (self.owner_of(account)?, self.owner_of(lender_account)?);
+        let (borrowed, loan) = self.tracked(Operation::Borrow, &[borrower_id, lender_id], |bank| {
+            let clock = Arc::clone(&bank.clock);
+            let [borrower, lender] = bank.pair_mut(borrower_id, lender_id)?;
+            lender.start_debit(lender_account, clock.now())?;
+            let borrowed = borrower.borrow(account, lender, lender_account, amount, currency, &*clock)?;
+            let loan = borrower.debts().last().map(|loan| loan.id).ok_or("The new loan is missing")?;
+            Ok((borrowed, loan))
+        })?;
+        self.record([BankEvent::Borrowed {
+            loan,
+            borrower: borrower_id,
+            account,
+            lender: lender_id,
+            lender_account,
+            amount: borrowed,
+            currency,
+        }])?;
+        Ok(borrowed)
+    }
+
+    /// Pay up to `amount` towards loan `loan_id` from the borrower's account
+    /// the loan was paid into to the lender's account it came from. Returns
+    /// the amount paid.
+    pub fn repay(&mut self, loan_id: LoanId, amount: Money) -> Result<Money, BankError> {
+        let loan = self
+            .users
+            .values()
+            .flat_map(|user| user.debts())
+            .find(|loan| loan.id == loan_id)
+            .ok_or_else(|| BankError::NotFound(format!("Unknown loan {}", loan_id)))?;
+        let (borrower_id, lender_id) = (loan.borrower, loan.lender);
+        let (account, lender_account) = (loan.account, loan.lender_account);
+        let account = self.resolve_account(borrower_id, account)?;
+        let lender_account = self.resolve_account(lender_id, lender_account)?;
+        let paid = self.tracked(Operation::Repay, &[borrower_id, lender_id], |bank| {
+            let clock = Arc::clone(&bank.clock);
+            let [borrower, lender] = bank.pair_mut(borrower_id, lender_id)?;
+            borrower.start_debit(account, clock.now())?;
+            Ok(borrower.repay(account, lender, lender_account, loan_id, amount, &*clock)?)
+        })?;
+        self.record([BankEvent::Repaid {
+            loan: loan_id,
+            borrower: borrower_id,
+            lender: lender_id,
+            amount: paid,
+        }])?;
+        Ok(paid)
+    }
+
+    /// Apply treasury interest to the user's deposit in `currency`.
+    pub fn apply_interest(&mut self, id: UserId, currency: Currency) -> Result<Money, BankError> {
+        let now = self.clock.now();
+        self.accrue_until(id, currency, now)
+    }
+
+    /// Apply treasury interest to the user's deposit in `currency` up to `until`.
+    /// See `Treasury::accrue_until`.
+    pub fn accrue_until(&mut self, id: UserId, currency: Currency, until: SystemTime) -> Result<Money, BankError> {
+        let amount = self.tracked(Operation::Interest, &[id], |bank| {
+            let user = bank.users.get_mut(&id).ok_or_else(|| unknown_user(id))?;
+            Ok(bank.treasury.accrue_until(user, currency, until)?)
+        })?;
+        self.record([BankEvent::InterestApplied { user: id, currency, amount, until }])?;
+        Ok(amount)
+    }
+
+    /// Let the user overdraw `currency` by up to `limit`, replacing any
+    /// existing agreement. An agreement in another currency can only be
+    /// replaced once it is paid back.
+    pub fn grant_overdraft(
+        &mut self,
+        id: UserId,
+        currency: Currency,
+        limit: Money,
+        rate_bps: u32,
+    ) -> Result<(), BankError> {
+        self.tracked(Operation::Overdraft, &[id], |bank| {
+            let user = bank.users.get_mut(&id).ok_or_else(|| unknown_user(id))?;
+            let overdrawn = match user.overdraft {
+                Some(current) if current.currency == currency => current.overdrawn,
+                Some(current) if current.overdrawn > Money::ZERO => {
+                    return Err(BankError::Rejected(format!(
+                        "User {} still owes {} on their {} overdraft",
+                        id, current.overdrawn, current.currency
+                    )));
+                }
+                _ => Money::ZERO,
+            };
+            let mut overdraft = OverdraftAgreement::new(currency, limit, rate_bps);
+            overdraft.overdrawn = overdrawn;
+            overdraft.interest_since = user.overdraft.and_then(|current| current.interest_since);
+            user.overdraft = Some(overdraft);
+            Ok(())
+        })?;
+        self.record([BankEvent::OverdraftGranted { user: id, currency, limit, rate_bps }])?;
+        Ok(())
+    }
+
+    /// Remove the user's overdraft, which must be fully paid back.
+    pub fn revoke_overdraft(&mut self, id: UserId) -> Result<(), BankError> {
+        self.tracked(Operation::Overdraft, &[id], |bank| {
+            let user = bank.users.get_mut(&id).ok_or_else(|| unknown_user(id))?;
+            match user.overdraft {
+                Some(current) if current.overdrawn > Money::ZERO => {
+                    Err(BankError::Rejected(format!("User {} still owes {} on their overdraft", id, current.overdrawn)))
+                }
+                _ => {
+                    user.overdraft = None;
+                    Ok(())
+                }
+            }
+        })?;
+        self.record([BankEvent::OverdraftRevoked { user: id }])?;
+        Ok(())
+    }
+
+    /// The user's transactions, oldest first, optionally only those tagged `tag`.
+    pub fn history(&self, id: UserId, tag: Option<&str>) -> Result<Vec<&Transaction>, BankError> {
+        let user = self.users.get(&id).ok_or_else(|| unknown_user(id))?;
+        match tag {
+            Some(tag) => {
+                let tag = ledger::normalize_tag(tag)?;
+                Ok(user.history().iter().filter(|transaction| transaction.has_tag(&tag)).collect())
+            }
+            None => Ok(user.history().iter().collect()),
+        }
+    }
+
+    /// Interest the user can expect to earn and pay in each currency they
+    /// deal in. See `Treasury::forecast`.
+    pub fn interest_forecast(&self, id: UserId) -> Result<Vec<InterestForecast>, BankError> {
+        let user = self.users.get(&id).ok_or_else(|| unknown_user(id))?;
+        let mut currencies: BTreeSet<Currency> = user.total_balance().into_keys().collect();
+        currencies.extend(user.debts().map(|loan| loan.currency));
+        currencies.extend(user.overdraft.map(|overdraft| overdraft.currency));
+        let now = self.clock.now();
+        currencies
+            .into_iter()
+            .map(|currency| self.treasury.forecast(user, currency, now).map_err(BankError::from))
+            .collect()
+    }
+
+    /// The user's history as statement lines, with counterparties shown by
+    /// payee name and category where known. Unless filtered by `tag`, it ends
+    /// with the interest forecast.
+    pub fn statement(&self, id: UserId, tag: Option<&str>) -> Result<Vec<String>, BankError> {
+        let user = self.users.get(&id).ok_or_else(|| unknown_user(id))?;
+        let label = |account: UserId| match self.treasury.payees.resolve(&user.payee_corrections, account) {
+            Some(payee) => payee.to_string(),
+            None => account.to_string(),
+        };
+        let mut lines = Vec::new();
+        for transaction in self.history(id, tag)? {
+            let mut line = String::new();
+            transaction.render(&mut line, label).map_err(|err| err.to_string())?;
+            lines.push(line);
+        }
+        if tag.is_none() {
+            lines.extend(self.interest_forecast(id)?.iter().map(InterestForecast::to_string));
+        }
+        Ok(lines)
+    }
+
+    /// Add `tag` to each of the user's transactions in `transaction_ids`.
+    /// Returns how many of them did not already carry it.
+    pub fn tag_transactions(&mut self, id: UserId, transaction_ids: &[u64], tag: &str) -> Result<usize, BankError> {
+        let tag = ledger::normalize_tag(tag)?;
+        self.retag(id, transaction_ids, &tag, true, |tags| tags.insert(tag.clone()))
+    }
+
+    /// Remove `tag` from each of the user's transactions in `transaction_ids`.
+    /// Returns how many of them carried it.
+    pub fn untag_transactions(&mut self, id: UserId, transaction_ids: &[u64], tag: &str) -> Result<usize, BankError> {
+        let tag = ledger::normalize_tag(tag)?;
+        self.retag(id, transaction_ids, &tag, false, |tags| tags.remove(&tag))
+    }
+
+    /// Apply `change` to the tags of the listed transactions, writing the ones
+    /// it changed to the store before updating them in memory. `tag` and
+    /// `added` describe the change for observers.
+    fn retag(
+        &mut self,
+        id: UserId,
+        transaction_ids: &[u64],
+        tag: &str,
+        added: bool,
+        change: impl Fn(&mut BTreeSet<String>) -> bool,
+    ) -> Result<usize, BankError> {
+        let user = self.users.get(&id).ok_or_else(|| unknown_user(id))?;
+        let mut wanted = transaction_ids.to_vec();
+        wanted.sort_unstable();
+        wanted.dedup();
+        let mut changed = Vec::new();
+        for transaction_id in wanted {
+            let transaction = user
+                .transactions
+                .iter()
+                .find(|transaction| transaction.id == transaction_id)
+                .ok_or_else(|| BankError::NotFound(format!("User {} has no transaction {}", id, transaction_id)))?;
+            let mut updated = transaction.clone();
+            if change(&mut updated.tags) {
+                changed.push(updated);
+            }
+        }
+        if changed.is_empty() {
+            return Ok(0);
+        }
+
+        let event = BankEvent::Retagged {
+            user: id,
+            transactions: changed.iter().map(|transaction| transaction.id).collect(),
+            tag: tag.to_string(),
+            added,
+        };
+        self.write_and_record(
+            |store| changed.iter().try_for_each(|transaction| store.save_tags(transaction)),
+            [event],
+        )?;
+        let user = self.users.get_mut(&id).ok_or_else(|| unknown_user(id))?;
+        for updated in &changed {
+            if let Some(transaction) = user.transactions.iter_mut().find(|transaction| transaction.id == updated.id) {
+                transaction.tags = updated.tags.clone();
+            }
+        }
+        Ok(changed.len())
+    }
+
+    /// List `account` in the payee directory as `payee`, replacing any entry
+    /// it already had.
+    pub fn register_payee(&mut self, account: UserId, payee: Payee) -> Result<(), BankError> {
+        if !self.users.contains_key(&account) {
+            return Err(unknown_user(account));
+        }
+        if payee.name.trim().is_empty() {
+            return Err(BankError::Rejected(String::from("Payee name cannot be empty")));
+        }
+        let event = BankEvent::PayeeRegistered { account, payee: payee.clone() };
+        self.write_and_record(|store| store.save_payee(account, Some(&payee)), [event])?;
+        self.treasury.payees.register(account, payee);
+        Ok(())
+    }
+
+    /// Drop `account` from the payee directory.
+    pub fn remove_payee(&mut self, account: UserId) -> Result<(), BankError> {
+        if self.treasury.payees.get(account).is_none() {
+            return Err(BankError::Rejected(format!("User {} is not a known payee", account)));
+        }
+        self.write_and_record(|store| store.save_payee(account, None), [BankEvent::PayeeRemoved { account }])?;
+        self.treasury.payees.remove(account);
+        Ok(())
+    }
+
+    /// Show `account` as `payee` on this user's statements instead of its
+    /// directory entry, or go back to the directory entry if `payee` is `None`.
+    pub fn correct_payee(&mut self, id: UserId, account: UserId, payee: Option<Payee>) -> Result<(), BankError> {
+        if !self.users.contains_key(&account) {
+            return Err(unknown_user(account));
+        }
+        if payee.as_ref().is_some_and(|payee| payee.name.trim().is_empty()) {
+            return Err(BankError::Rejected(String::from("Payee name cannot be empty")));
+        }
+        let event = BankEvent::PayeeCorrected { user: id, account, payee: payee.clone() };
+        self.tracked(Operation::Payee, &[id], |bank| {
+            let user = bank.users.get_mut(&id).ok_or_else(|| unknown_user(id))?;
+            match payee {
+                Some(payee) => user.payee_corrections.insert(account, payee),
+                None => user.payee_corrections.remove(&account),
+            };
+            Ok(())
+        })?;
+        self.record([event])?;
+        Ok(())
+    }
+
+    /// Move the fees collected in `currency` into the treasury's main pool.
+    pub fn sweep_fees(&mut self, currency: Currency) -> Result<Money, BankError> {
+        let amount = self.tracked(Operation::SweepFees, &[], |bank| Ok(bank.treasury.sweep_fees(currency)?))?;
+        self.record([BankEvent::FeesSwept { currency, amount }])?;
+        Ok(amount)
+    }
+
+    /// Put `policy` forward on behalf of `operator`. It replaces the current
+    /// interest, fees or facility terms only once another operator confirms
+    /// it with `confirm_policy` within `policy::CONFIRMATION_WINDOW`. Returns
+    /// the id of the change.
+    pub fn propose_policy(&mut self, policy: Policy, operator: &str) -> Result<u32, BankError> {
+        let change = self.update_policies(|treasury, now| treasury.policy_changes.propose(policy, operator, now))?;
+        let operator = operator.trim().to_string();
+        self.record([BankEvent::PolicyProposed { change, policy, operator }])?;
+        Ok(change)
+    }
+
+    /// Confirm pending change `change` on behalf of `operator`, who must not
+    /// be the one who proposed it, and start applying its policy.
+    pub fn confirm_policy(&mut self, change: u32, operator: &str) -> Result<Policy, BankError> {
+        self.policy_change(change)?;
+        let policy = self.update_policies(|treasury, now| {
+            let policy = treasury.policy_changes.confirm(change, operator, now)?;
+            treasury.set_policy(policy, now)?;
+            Ok(policy)
+        })?;
+        let operator = operator.trim().to_string();
+        self.record([BankEvent::PolicyConfirmed { change, operator }])?;
+        Ok(policy)
+    }
+
+    /// Withdraw pending change `change` on behalf of `operator`.
+    pub fn cancel_policy(&mut self, change: u32, operator: &str) -> Result<Policy, BankError> {
+        self.policy_change(change)?;
+        let policy = self.update_policies(|treasury, now| treasury.policy_changes.cancel(change, operator, now))?;
+        let operator = operator.trim().to_string();
+        self.record([BankEvent::PolicyCancelled { change, operator }])?;
+        Ok(policy)
+    }
+
+    fn policy_change(&self, change: u32) -> Result<&PolicyChange, BankError> {
+        self.treasury
+            .policy_changes
+            .iter()
+            .find(|proposed| proposed.id == change)
+            .ok_or_else(|| BankError::NotFound(format!("Unknown policy change #{}", change)))
+    }
+
+    /// Run `update` on the treasury policies at the clock's time as a
+    /// tracked operation on the treasury alone.
+    fn update_policies<T>(
+        &mut self,
+        update: impl FnOnce(&mut Treasury, SystemTime) -> Result<T, String>,
+    ) -> Result<T, BankError> {
+        self.tracked(Operation::Policy, &[], |bank| {
+            let now = bank.clock.now();
+            Ok(update(&mut bank.treasury, now)?)
+        })
+    }
+
+    /// Run `op` and, if it succeeds, pay the interest earned up to now on any
+    /// balances it changed, stamp the ledger entries it appended with the
+    /// bank clock's time, update the treasury aggregates for the users in
+    /// `ids`, start the interest clock on any balances it funded and write
+    /// those users, the treasury and the ledger entries `op` appended to the
+    /// store in one store transaction. The transaction is left open for
+    /// `record`, which must follow with the operation's events.
+    /// The clock stands still while `op` runs, so replaying its events at
+    /// that instant repeats it exactly.
+    /// The time taken is recorded under `operation`, whether or not it succeeds.
+    fn tracked<T>(
+        &mut self,
+        operation: Operation,
+        ids: &[UserId],
+        op: impl FnOnce(&mut Self) -> Result<T, BankError>,
+    ) -> Result<T, BankError> {
+        let start = Instant::now();
+        let (at, next_ids) = (self.clock.now(), NextIds::current());
+        let clock = std::mem::replace(&mut self.clock, Arc::new(MockClock::new(at)));
+        let result = self.apply_and_write(ids, op);
+        self.clock = clock;
+        self.metrics.record(operation, start.elapsed());
+        let (value, checkpoint) = result?;
+        self.pending = Some(Pending {
+            at,
+            ids: next_ids,
+            checkpoint,
+        });
+        Ok(value)
+    }
+
+    /// Append `events` for the operation that just ran to the store's event
+    /// log and commit the store transaction it left open, then report them to
+    /// the observers. If the log or the commit fails, the transaction is
+    /// rolled back and the operation undone in memory.
+    fn record(&mut self, events: impl IntoIterator<Item = BankEvent>) -> Result<(), BankError> {
+        let pending = self.pending.take();
+        let (at, ids) = pending
+            .as_ref()
+            .map_or_else(|| (self.clock.now(), NextIds::current()), |pending| (pending.at, pending.ids));
+        let events: Vec<RecordedEvent> = events
+            .into_iter()
+            .map(|event| RecordedEvent {
+                version: EVENT_VERSION,
+                at,
+                ids,
+                event,
+            })
+            .collect();
+        let written = events
+            .iter()
+            .try_for_each(|event| self.store.append_event(event))
+            .and_then(|()| self.store.commit());
+        if let Err(err) = written {
+            if let Some(pending) = pending {
+                self.restore(pending.checkpoint);
+            }
+            self.store.rollback().map_err(BankError::Internal)?;
+            return Err(BankError::Internal(err));
+        }
+        for event in &events {
+            self.observers.emit(&event.event);
+        }
+        Ok(())
+    }
+
+    /// `tracked` without the timing. Everything `op` changed is put back if
+    /// it or the store fails; otherwise the checkpoint to put back if
+    /// `record` fails is returned along with the value.
+    fn apply_and_write<T>(
+        &mut self,
+        ids: &[UserId],
+        op: impl FnOnce(&mut Self) -> Result<T, BankError>,
+    ) -> Result<(T, Checkpoint), BankError> {
+        let checkpoint = self.checkpoint(ids);
+        self.store.begin().map_err(BankError::Internal)?;
+        match self.apply_op(ids, &checkpoint, op) {
+            Ok(value) => Ok((value, checkpoint)),
+            Err(err) => {
+                self.restore(checkpoint);
+                self.store.rollback().map_err(BankError::Internal)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Make `write`'s changes to the store and append `events` to its event
+    /// log in one store transaction, for operations that only change the
+    /// bank in memory once the store has them.
+    fn write_and_record(
+        &mut self,
+        write: impl FnOnce(&mut S) -> Result<(), String>,
+        events: impl IntoIterator<Item = BankEvent>,
+    ) -> Result<(), BankError> {
+        self.store.begin().map_err(BankError::Internal)?;
+        if let Err(err) = write(&mut self.store) {
+            self.store.rollback().map_err(BankError::Internal)?;
+            return Err(BankError::Internal(err));
+        }
+        self.record(events)
+    }
+
+    fn apply_op<T>(
+        &mut self,
+        ids: &[UserId],
+        checkpoint: &Checkpoint,
+        op: impl FnOnce(&mut Self) -> Result<T, BankError>,
+    ) -> Result<T, BankError> {
+        let marks: Vec<usize> = checkpoint.users.iter().map(|(_, _, mark)| *mark).collect();
+        let treasury_mark = checkpoint.treasury_mark;
+        let before: Vec<Aggregates> = ids.iter().filter_map(|id| self.users.get(id)).map(Aggregates::of).collect();
+        let value = op(self)?;
+        let now = self.clock.now();
+        for (id, before, _) in &checkpoint.users {
+            if let (Some(user), Some(before)) = (self.users.get_mut(id), before) {
+                self.treasury.settle_interest(&checkpoint.treasury, before, user, now)?;
+            }
+        }
+        self.treasury.settle_facility(&checkpoint.treasury.facility, now)?;
+        for (id, &mark) in ids.iter().zip(&marks) {
+            if let Some(user) = self.users.get_mut(id) {
+                for transaction in &mut user.transactions[mark..] {
+                    transaction.timestamp = now;
+                }
+            }
+        }
+        for transaction in &mut self.treasury.transactions[treasury_mark..] {
+            transaction.timestamp = now;
+        }
+        for contribution in &before {
+            self.treasury.aggregates.subtract(contribution);
+        }
+        for user in ids.iter().filter_map(|id| self.users.get(id)) {
+            self.treasury.aggregates.add(&Aggregates::of(user));
+        }
+        for id in ids {
+            if let Some(user) = self.users.get_mut(id) {
+                user.mark_interest_start(now);
+            }
+        }
+        self.write_through(ids, &marks, treasury_mark).map_err(BankError::Internal)?;
+        Ok(value)
+    }
+
+    /// Save what an operation on the users in `ids` may change.
+    fn checkpoint(&mut self, ids: &[UserId]) -> Checkpoint {
+        let users = ids
+            .iter()
+            .map(|id| match self.users.get_mut(id) {
+                Some(user) => (*id, Some(user.checkpoint()), user.transactions.len()),
+                None => (*id, None, 0),
+            })
+            .collect();
+        Checkpoint {
+            users,
+            next_user_id: self.next_user_id,
+            treasury_mark: self.treasury.transactions.len(),
+            treasury: self.treasury.checkpoint(),
+        }
+    }
+
+    /// Put back what was saved in `checkpoint`, removing users registered
+    /// since.
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        for (id, saved, mark) in checkpoint.users {
+            match (self.users.get_mut(&id), saved) {
+                (Some(user), Some(saved)) => user.restore(saved, mark),
+                (_, None) => {
+                    self.users.remove(&id);
+                }
+                (None, Some(saved)) => {
+                    self.users.insert(id, saved);
+                }
+            }
+        }
+        self.next_user_id = checkpoint.next_user_id;
+        self.treasury.restore(checkpoint.treasury, checkpoint.treasury_mark);
+    }
+
+    /// See `User::settle_after_credit`.
+    fn settle_after_credit(&mut self, id: UserId, account: AccountId) -> Result<(), BankError> {
+        let now = self.clock.now();
+        let user = self.users.get_mut(&id).ok_or_else(|| unknown_user(id))?;
+        Ok(user.settle_after_credit(account, now, &mut self.treasury)?)
+    }
+
+    fn collect_due_installments(&mut self, id: UserId) -> Result<Money, BankError> {
+        let now = self.clock.now();
+        let user = self.users.get_mut(&id).ok_or_else(|| unknown_user(id))?;
+        Ok(user.collect_installments(now, &mut self.treasury)?)
+    }
+
+    fn write_through(&mut self, ids: &[UserId], marks: &[usize], treasury_mark: usize) -> Result<(), String> {
+        for (id, &mark) in ids.iter().zip(marks) {
+            let user = self.users.get(id).ok_or_else(|| unknown_user(*id))?;
+            self.store.save_user(user)?;
+            for transaction in &user.transactions[mark..] {
+                self.store.append_transaction(Some(user.id), transaction)?;
+            }
+        }
+        self.store.save_treasury(&self.treasury)?;
+        for transaction in &self.treasury.transactions[treasury_mark..] {
+            self.store.append_transaction(None, transaction)?;
+        }
+        Ok(())
+    }
+
+    /// Borrow two distinct users mutably at once.
+    fn pair_mut(&mut self, a: UserId, b: UserId) -> Result<[&mut User; 2], BankError> {
+        if a == b {
+            return Err(BankError::Rejected(String::from("Both sides of the operation are the same user")));
+        }
+        match self.users.get_disjoint_mut([&a, &b]) {
+            [Some(first), Some(second)] => Ok([first, second]),
+            [None, _] => Err(unknown_user(a)),
+            [_, None] => Err(unknown_user(b)),
+        }
+    }
+}
+
+/// An operation `tracked` ran whose store transaction is still open: when
+/// it started, the ids it allocated from and what to put back if its events
+/// cannot be recorded.
+#[derive(Debug)]
+struct Pending {
+    at: SystemTime,
+    ids: NextIds,
+    checkpoint: Checkpoint,
+}
+
+/// The users and treasury as they were before an operation, without the
+/// ledger entries; each ledger is cut back to its mark instead.
+#[derive(Debug)]
+struct Checkpoint {
+    users: Vec<(UserId, Option<User>, usize)>,
+    next_user_id: u32,
+    treasury: Treasury,
+    treasury_mark: usize,
+}
+
+/// Upgrade a saved bank to the current `SCHEMA_VERSION`, one version at a time.
+fn migrate(saved: SavedBank) -> Result<Value, String> {
+    if saved.version > SCHEMA_VERSION {
+        return Err(format!(
+            "Bank state has schema version {}, newer than the supported {}",
+            saved.version, SCHEMA_VERSION
+        ));
+    }
+    let mut bank = saved.bank;
+    for version in saved.version..SCHEMA_VERSION {
+        match version {
+            1 => split_out_accounts(&mut bank)?,
+            _ => unreachable!("no migration from schema version {}", version),
+        }
+    }
+    Ok(bank)
+}
+
+/// Version 2 gave users a list of accounts. Each user's balances, borrowing
+/// flag and account settings move into a single account sharing the user's id.
+fn split_out_accounts(bank: &mut Value) -> Result<(), String> {
+    let Some(users) = bank.get_mut("users").and_then(Value::as_object_mut) else {
+        return Ok(());
+    };
+    for user in users.values_mut() {
+        let user = user.as_object_mut().ok_or("Invalid user in saved bank")?;
+        let mut account = match user.remove("account") {
+            Some(Value::Object(account)) => account,
+            _ => serde_json::Map::new(),
+        };
+        if let Some(id) = user.get("id") {
+            account.insert(String::from("id"), id.clone());
+        }
+        for field in ["balances", "borrowable"] {
+            if let Some(value) = user.remove(field) {
+                account.insert(String::from(field), value);
+            }
+        }
+        user.insert(String::from("accounts"), Value::Array(vec![Value::Object(account)]));
+    }
+    Ok(())
+}
+
+/// The event for `fee` taken from `account`, unless there was none.
+fn charged(user: UserId, account: AccountId, fee: Money, currency: Currency) -> Option<BankEvent> {
+    (fee > Money::ZERO).then_some(BankEvent::FeeCharged { user, account, fee, currency })
+}
+
+fn unknown_user(id: UserId) -> BankError {
+    BankError::NotFound(format!("Unknown user {}", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use serde_json::Value;
+
+    use super::{advance, installment, Bank, BankError, SCHEMA_VERSION};
+    use crate::account::AccountKind;
+    use crate::currency::Currency;
+    use crate::money::Money;
+    use crate::time::{MockClock, SECONDS_PER_YEAR};
+    use crate::types::{AccountId, LoanId, UserId};
+    use crate::user::User;
+
+    fn not_found<T>(result: Result<T, BankError>) -> bool {
+        matches!(result, Err(BankError::NotFound(_)))
+    }
+
+    #[test]
+    fn lookups_of_missing_things_are_not_found() {
+        let mut bank = Bank::new();
+        let id = bank.open_account("Ada").unwrap();
+        let missing = UserId::from(999);
+        let account = bank.primary_account(id).unwrap();
+
+        assert!(not_found(bank.primary_account(missing)));
+        assert!(not_found(bank.resolve_account(id, Some(AccountId::from(999)))));
+        assert!(not_found(bank.deposit_into(AccountId::from(999), Money::from_major(1), Currency::Usd, false)));
+        assert!(not_found(bank.transfer(id, missing, Money::from_major(1), Currency::Usd)));
+        assert!(not_found(bank.repay(LoanId::from(999), Money::from_major(1))));
+        assert!(not_found(bank.tag_transactions(id, &[999], "rent")));
+        assert!(not_found(bank.confirm_policy(999, "Grace")));
+        assert!(bank.get_user(id).unwrap().account(account).is_ok());
+    }
+
+    #[test]
+    fn refusals_are_rejected() {
+        let mut bank = Bank::new();
+        let (a, b) = (bank.open_account("Ada").unwrap(), bank.open_account("Bob").unwrap());
+        let result = bank.transfer(a, b, Money::from_major(1), Currency::Usd);
+        assert!(matches!(result, Err(BankError::Rejected(_))), "{:?}", result);
+        assert!(matches!(bank.transfer(a, a, Money::from_major(1), Currency::Usd), Err(BankError::Rejected(_))));
+    }
+
+    #[test]
+    fn loans_are_repaid_between_their_accounts_until_closed() {
+        let mut bank = Bank::new();
+        let clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+        bank.set_clock(clock.clone());
+        let (lender, borrower) = (bank.open_account("Ada").unwrap(), bank.open_account("Bob").unwrap());
+        bank.deposit(lender, Money::from_major(1_000), Currency::Usd, true).unwrap();
+        let second = bank.add_account(borrower, AccountKind::Checking).unwrap();
+        let lender_account = bank.primary_account(lender).unwrap();
+        bank.borrow_into(second, lender_account, Money::from_major(90), Currency::Usd).unwrap();
+        let loan = bank.get_user(borrower).unwrap().debts().next().unwrap().id;
+
+        clock.advance(Duration::from_secs(SECONDS_PER_YEAR));
+        assert_eq!(bank.repay(loan, Money::from_major(50)).unwrap(), Money::from_major(50));
+        let owed = Money::from_minor(9_000 + 450 - 5_000);
+        assert_eq!(bank.get_user(borrower).unwrap().outstanding(loan, &*clock), Some(owed));
+        assert!(bank.repay(loan, Money::from_major(100)).unwrap_err().message().contains("Insufficient"));
+
+        bank.deposit_into(second, Money::from_major(100), Currency::Usd, false).unwrap();
+        assert_eq!(bank.repay(loan, Money::from_major(100)).unwrap(), owed);
+        let left = bank.get_user(borrower).unwrap().account(second).unwrap().balance(Currency::Usd).deposited;
+        assert_eq!(left, Money::from_minor(9_000 + 9_800 - 9_450));
+        assert!(bank.repay(loan, Money::from_major(1)).unwrap_err().message().contains("already repaid"));
+    }
+
+    #[test]
+    fn every_debit_counts_towards_the_savings_withdrawal_limit() {
+        let mut bank = Bank::new();
+        let (a, b) = (bank.open_account("Ada").unwrap(), bank.open_account("Bob").unwrap());
+        let usd = |major| Money::from_major(major);
+        bank.deposit(a, usd(1_000), Currency::Usd, true).unwrap();
+        bank.deposit(b, usd(1_000), Currency::Usd, true).unwrap();
+
+        assert!(bank.transfer(a, b, usd(1_000_000), Currency::Usd).is_err());
+        bank.borrow_between(b, a, usd(10), Currency::Usd).unwrap();
+        bank.withdraw(a, usd(10), Currency::Usd).unwrap();
+        for _ in 0..4 {
+            bank.transfer(a, b, usd(1), Currency::Usd).unwrap();
+        }
+        let refused = bank.transfer(a, b, usd(1), Currency::Usd).unwrap_err();
+        assert!(refused.message().contains("withdrawals per month"), "{}", refused);
+
+        let loan = bank.get_user(b).unwrap().debts().next().unwrap().id;
+        for _ in 0..6 {
+            bank.transfer(b, a, usd(1), Currency::Usd).unwrap();
+        }
+        assert!(bank.repay(loan, usd(1)).unwrap_err().message().contains("withdrawals per month"));
+    }
+
+    #[test]
+    fn salary_advances_are_limited_and_repaid_from_the_next_salary() {
+        let mut bank = Bank::new();
+        let clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+        bank.set_clock(clock.clone());
+        let a = bank.open_account_of_kind("Ada", AccountKind::Checking).unwrap();
+        let account = bank.primary_account(a).unwrap();
+        let month = Duration::from_secs(30 * 24 * 60 * 60);
+        let salary = Money::from_major(1_000);
+
+        for _ in 0..2 {
+            bank.deposit(a, salary, Currency::Usd, false).unwrap();
+            clock.advance(month);
+        }
+        let refused = bank.take_salary_advance(account, Money::from_major(1)).unwrap_err();
+        assert!(refused.message().contains("no recurring income"), "{}", refused);
+        bank.deposit(a, salary, Currency::Usd, false).unwrap();
+
+        let (limit, currency) = bank.advance_limit(a).unwrap();
+        let income = bank.recurring_income(a).unwrap().unwrap();
+        assert_eq!((limit, currency), (income.amount.mul_bps(advance::ADVANCE_LIMIT_BPS), Currency::Usd));
+        assert!(bank.take_salary_advance(account, Money::ZERO).is_err());
+        let over = limit.checked_add(Money::from_minor(1)).unwrap();
+        assert!(bank.take_salary_advance(account, over).unwrap_err().message().contains("more than"));
+        assert_eq!(bank.take_salary_advance(account, limit).unwrap(), limit);
+        let second = bank.take_salary_advance(account, Money::from_major(1)).unwrap_err();
+        assert!(second.message().contains("outstanding salary advance"), "{}", second);
+
+        clock.advance(month);
+        bank.deposit(a, salary, Currency::Usd, false).unwrap();
+        assert_eq!(bank.get_user(a).unwrap().salary_advance, None);
+
+        clock.advance(month * 2);
+        assert!(bank.advance_limit(a).unwrap_err().message().contains("overdue"));
+    }
+
+    #[test]
+    fn installments_are_collected_as_they_fall_due() {
+        let mut bank = Bank::new();
+        let clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+        bank.set_clock(clock.clone());
+        let a = bank.open_account_of_kind("Ada", AccountKind::Checking).unwrap();
+        let b = bank.open_account_of_kind("Bob", AccountKind::Checking).unwrap();
+        let (account, shop) = (bank.primary_account(a).unwrap(), bank.primary_account(b).unwrap());
+        let usd = |major| Money::from_major(major);
+        bank.deposit(a, usd(100), Currency::Usd, false).unwrap();
+
+        assert!(bank.buy_in_installments(account, account, usd(10), Currency::Usd, 2).is_err());
+        assert!(bank.buy_in_installments(account, shop, usd(10), Currency::Usd, 1).is_err());
+        assert!(bank.buy_in_installments(account, shop, usd(10), Currency::Usd, 13).is_err());
+        assert!(bank.buy_in_installments(account, shop, Money::ZERO, Currency::Usd, 2).is_err());
+        let short = bank.buy_in_installments(account, shop, usd(1_000), Currency::Usd, 2).unwrap_err();
+        assert!(short.message().contains("first installment"), "{}", short);
+
+        bank.buy_in_installments(account, shop, usd(150), Currency::Usd, 2).unwrap();
+        assert_eq!(bank.get_user(a).unwrap().balance(Currency::Usd).deposited, usd(23));
+        assert_eq!(bank.get_user(b).unwrap().balance(Currency::Usd).deposited, Money::from_minor(14_550));
+
+        clock.advance(installment::INSTALLMENT_INTERVAL);
+        assert_eq!(bank.collect_installments(a).unwrap(), Money::ZERO);
+        let overdue = bank.buy_in_installments(account, shop, usd(10), Currency::Usd, 2).unwrap_err();
+        assert!(overdue.message().contains("overdue installments"), "{}", overdue);
+
+        bank.deposit(a, usd(100), Currency::Usd, false).unwrap();
+        let ada = bank.get_user(a).unwrap();
+        assert!(ada.installment_plans.iter().all(|plan| plan.is_paid_off()));
+        assert_eq!(ada.balance(Currency::Usd).deposited, usd(46));
+    }
+
+    /// A path in the temporary directory for `name`, removed first.
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("banking-{}-{}.json", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn snapshot(bank: &Bank) -> Value {
+        let mut users: Vec<&User> = bank.users().collect();
+        users.sort_by_key(|user| user.id);
+        serde_json::to_value(users).unwrap()
+    }
+
+    fn saved_bank() -> Bank {
+        let mut bank = Bank::new();
+        let (a, b) = (bank.open_account("Ada").unwrap(), bank.open_account("Bob").unwrap());
+        bank.deposit(a, Money::from_major(1_000), Currency::Usd, true).unwrap();
+        bank.deposit(b, Money::from_major(300), Currency::Eur, false).unwrap();
+        bank.borrow_between(b, a, Money::from_major(50), Currency::Usd).unwrap();
+        bank
+    }
+
+    #[test]
+    fn json_banks_are_reloaded_as_they_were_saved() {
+        let path = temp_path("round-trip");
+        let bank = saved_bank();
+        bank.save_json(&path).unwrap();
+        let mut loaded = Bank::load_json(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(snapshot(&loaded), snapshot(&bank));
+        let newcomer = loaded.open_account("Cy").unwrap();
+        assert!(bank.get_user(newcomer).is_none(), "new users get fresh ids");
+    }
+
+    #[test]
+    fn unversioned_json_is_migrated_to_accounts() {
+        let path = temp_path("version-1");
+        let bank = saved_bank();
+        let mut state = serde_json::to_value(&bank).unwrap();
+        for user in state["users"].as_object_mut().unwrap().values_mut() {
+            let user = user.as_object_mut().unwrap();
+            let Some(Value::Array(mut accounts)) = user.remove("accounts") else { panic!("user without accounts") };
+            let mut account = accounts.remove(0);
+            let account = account.as_object_mut().unwrap();
+            account.remove("id");
+            for field in ["balances", "borrowable"] {
+                user.insert(String::from(field), account.remove(field).unwrap());
+            }
+            user.insert(String::from("account"), Value::Object(account.clone()));
+        }
+        fs::write(&path, state.to_string()).unwrap();
+        let loaded = Bank::load_json(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        for user in bank.users() {
+            let migrated = loaded.get_user(user.id).unwrap();
+            let [account] = migrated.accounts.as_slice() else { panic!("{} accounts", migrated.accounts.len()) };
+            assert_eq!(u32::from(account.id), u32::from(user.id));
+            assert_eq!(account.borrowable, user.accounts[0].borrowable);
+            assert_eq!(migrated.balance(Currency::Usd), user.balance(Currency::Usd));
+            assert_eq!(migrated.balance(Currency::Eur), user.balance(Currency::Eur));
+        }
+    }
+
+    #[test]
+    fn json_from_a_newer_schema_is_refused() {
+        let path = temp_path("newer");
+        fs::write(&path, format!(r#"{{"version": {}, "bank": {{}}}}"#, SCHEMA_VERSION + 1)).unwrap();
+        let refused = Bank::load_json(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(refused.message().contains("newer than the supported"), "{}", refused);
+    }
+}
@@ -0,0 +1,480 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use tokio::runtime::Handle;
+use tokio::sync::Mutex;
+use tokio::task;
+
+use super::{Bank, BankError, Environment, unknown_user};
+use crate::account::AccountKind;
+use crate::currency::{Balance, Currency};
+use crate::income::RecurringIncome;
+use crate::interest::InterestForecast;
+use crate::ledger::Transaction;
+use crate::money::Money;
+use crate::payee::Payee;
+use crate::policy::Policy;
+use crate::sandbox::Seed;
+use crate::store::async_store::{AsyncStore, BlockingStore};
+use crate::store::{MemoryStore, Store};
+use crate::types::{AccountId, LoanId, PlanId, UserId};
+
+/// A `Bank` for async code, built with the `async` feature.
+///
+/// Operations wait for the bank on a `tokio::sync::Mutex` instead of blocking
+/// the runtime. Writes go through to the `Store`, so they run on tokio's
+/// blocking thread pool while holding the lock; reads run in place. A bank
+/// over an `AsyncStore` is made with `AsyncBank::open`. Cloning gives another
+/// handle to the same bank.
+#[derive(Debug)]
+#[cfg_attr(not(any(feature = "server", feature = "grpc")), allow(dead_code))]
+pub struct AsyncBank<S: Store = MemoryStore> {
+    bank: Arc<Mutex<Bank<S>>>,
+}
+
+impl<S: Store> Clone for AsyncBank<S> {
+    fn clone(&self) -> Self {
+        AsyncBank {
+            bank: Arc::clone(&self.bank),
+        }
+    }
+}
+
+// No built-in store is async yet: this is how one would be opened.
+#[allow(dead_code)]
+impl<A: AsyncStore + Send + 'static> AsyncBank<BlockingStore<A>> {
+    /// Load every user and the treasury from `store` on the blocking pool,
+    /// as `Bank::open` does.
+    pub async fn open(store: A) -> Result<Self, BankError> {
+        let store = BlockingStore::new(store, Handle::current());
+        let bank = task::spawn_blocking(move || Bank::open(store))
+            .await
+            .map_err(|_| BankError::Internal(String::from("Loading the bank panicked")))??;
+        Ok(AsyncBank::new(bank))
+    }
+}
+
+// Each network API serves only some of these operations.
+#[allow(dead_code)]
+impl<S: Store + Send + 'static> AsyncBank<S> {
+    pub fn new(bank: Bank<S>) -> Self {
+        AsyncBank {
+            bank: Arc::new(Mutex::new(bank)),
+        }
+    }
+
+    /// Run `op` against the bank on the blocking pool, once it is free. If
+    /// `op` panics, the bank is put back as it was before it ran, while
+    /// still locked, and the panic is reported as `BankError::Internal`.
+    pub async fn write<T: Send + 'static>(
+        &self,
+        op: impl FnOnce(&mut Bank<S>) -> Result<T, BankError> + Send + 'static,
+    ) -> Result<T, BankError> {
+        let mut bank = Arc::clone(&self.bank).lock_owned().await;
+        task::spawn_blocking(move || bank.undoing_panics(op))
+            .await
+            .map_err(|_| BankError::Internal(String::from("The bank operation did not run")))?
+    }
+
+    /// Look at the bank once it is free. `op` should not block, nor call
+    /// into the store.
+    pub async fn read<T>(&self, op: impl FnOnce(&Bank<S>) -> T) -> T {
+        op(&*self.bank.lock().await)
+    }
+
+    /// See `Bank::set_environment`.
+    pub async fn set_environment(&self, environment: Environment) -> Result<(), BankError> {
+        self.write(move |bank| bank.set_environment(environment)).await
+    }
+
+    /// See `Bank::faucet`.
+    pub async fn faucet(&self, id: UserId, amount: Money, currency: Currency) -> Result<Money, BankError> {
+        self.write(move |bank| bank.faucet(id, amount, currency)).await
+    }
+
+    /// See `Bank::open_account`.
+    pub async fn open_account(&self, name: &str) -> Result<UserId, BankError> {
+        let name = name.to_string();
+        self.write(move |bank| bank.open_account(&name)).await
+    }
+
+    /// See `Bank::open_account_of_kind`.
+    pub async fn open_account_of_kind(&self, name: &str, kind: AccountKind) -> Result<UserId, BankError> {
+        let name = name.to_string();
+        self.write(move |bank| bank.open_account_of_kind(&name, kind)).await
+    }
+
+    /// See `Bank::add_account`.
+    pub async fn add_account(&self, id: UserId, kind: AccountKind) -> Result<AccountId, BankError> {
+        self.write(move |bank| bank.add_account(id, kind)).await
+    }
+
+    /// See `Bank::reset`.
+    pub async fn reset(&self, seed: Seed) -> Result<(), BankError> {
+        self.write(move |bank| bank.reset(seed)).await
+    }
+
+    /// See `Bank::deposit`.
+    pub async fn deposit(
+        &self,
+        id: UserId,
+        amount: Money,
+        currency: Currency,
+        is_borrowable: bool,
+    ) -> Result<(), BankError> {
+        self.write(move |bank| bank.deposit(id, amount, currency, is_borrowable)).await
+    }
+
+    /// See `Bank::deposit_into`.
+    pub async fn deposit_into(
+        &self,
+        account: AccountId,
+        amount: Money,
+        currency: Currency,
+        is_borrowable: bool,
+    ) -> Result<(), BankError> {
+        self.write(move |bank| bank.deposit_into(account, amount, currency, is_borrowable)).await
+    }
+
+    /// See `Bank::withdraw`.
+    pub async fn withdraw(&self, id: UserId, amount: Money, currency: Currency) -> Result<Money, BankError> {
+        self.write(move |bank| bank.withdraw(id, amount, currency)).await
+    }
+
+    /// See `Bank::withdraw_from`.
+    pub async fn withdraw_from(
+        &self,
+        account: AccountId,
+        amount: Money,
+        currency: Currency,
+    ) -> Result<Money, BankError> {
+        self.write(move |bank| bank.withdraw_from(account, amount, currency)).await
+    }
+
+    /// See `Bank::convert`.
+    pub async fn convert(
+        &self,
+        id: UserId,
+        amount: Money,
+        from: Currency,
+        to: Currency,
+        rate_bps: u32,
+    ) -> Result<Money, BankError> {
+        self.write(move |bank| bank.convert(id, amount, from, to, rate_bps)).await
+    }
+
+    /// See `Bank::transfer`.
+    pub async fn transfer(
+        &self,
+        from: UserId,
+        to: UserId,
+        amount: Money,
+        currency: Currency,
+    ) -> Result<Money, BankError> {
+        self.write(move |bank| bank.transfer(from, to, amount, currency)).await
+    }
+
+    /// See `Bank::transfer_between`.
+    pub async fn transfer_between(
+        &self,
+        from: AccountId,
+        to: AccountId,
+        amount: Money,
+        currency: Currency,
+    ) -> Result<Money, BankError> {
+        self.write(move |bank| bank.transfer_between(from, to, amount, currency)).await
+    }
+
+    /// See `Bank::take_salary_advance`.
+    pub async fn take_salary_advance(&self, account: AccountId, amount: Money) -> Result<Money, BankError> {
+        self.write(move |bank| bank.take_salary_advance(account, amount)).await
+    }
+
+    /// See `Bank::buy_in_installments`.
+    pub async fn buy_in_installments(
+        &self,
+        account: AccountId,
+        payee_account: AccountId,
+        price: Money,
+        currency: Currency,
+        installments: u32,
+    ) -> Result<PlanId, BankError> {
+        self.write(move |bank| bank.buy_in_installments(account, payee_account, price, currency, installments))
+            .await
+    }
+
+    /// See `Bank::collect_installments`.
+    pub async fn collect_installments(&self, id: UserId) -> Result<Money, BankError> {
+        self.write(move |bank| bank.collect_installments(id)).await
+    }
+
+    /// See `Bank::borrow_between`.
+    pub async fn borrow_between(
+        &self,
+        borrower_id: UserId,
+        lender_id: UserId,
+        amount: Money,
+        currency: Currency,
+    ) -> Result<Money, BankError> {
+        self.write(move |bank| bank.borrow_between(borrower_id, lender_id, amount, currency)).await
+    }
+
+    /// See `Bank::borrow_into`.
+    pub async fn borrow_into(
+        &self,
+        account: AccountId,
+        lender_account: AccountId,
+        amount: Money,
+        currency: Currency,
+    ) -> Result<Money, BankError> {
+        self.write(move |bank| bank.borrow_into(account, lender_account, amount, currency)).await
+    }
+
+    /// See `Bank::repay`.
+    pub async fn repay(&self, loan_id: LoanId, amount: Money) -> Result<Money, BankError> {
+        self.write(move |bank| bank.repay(loan_id, amount)).await
+    }
+
+    /// See `Bank::apply_interest`.
+    pub async fn apply_interest(&self, id: UserId, currency: Currency) -> Result<Money, BankError> {
+        self.write(move |bank| bank.apply_interest(id, currency)).await
+    }
+
+    /// See `Bank::accrue_until`.
+    pub async fn accrue_until(&self, id: UserId, currency: Currency, until: SystemTime) -> Result<Money, BankError> {
+        self.write(move |bank| bank.accrue_until(id, currency, until)).await
+    }
+
+    /// See `Bank::grant_overdraft`.
+    pub async fn grant_overdraft(
+        &self,
+        id: UserId,
+        currency: Currency,
+        limit: Money,
+        rate_bps: u32,
+    ) -> Result<(), BankError> {
+        self.write(move |bank| bank.grant_overdraft(id, currency, limit, rate_bps)).await
+    }
+
+    /// See `Bank::revoke_overdraft`.
+    pub async fn revoke_overdraft(&self, id: UserId) -> Result<(), BankError> {
+        self.write(move |bank| bank.revoke_overdraft(id)).await
+    }
+
+    /// See `Bank::tag_transactions`.
+    pub async fn tag_transactions(&self, id: UserId, transaction_ids: Vec<u64>, tag: &str) -> Result<usize, BankError> {
+        let tag = tag.to_string();
+        self.write(move |bank| bank.tag_transactions(id, &transaction_ids, &tag)).await
+    }
+
+    /// See `Bank::untag_transactions`.
+    pub async fn untag_transactions(
+        &self,
+        id: UserId,
+        transaction_ids: Vec<u64>,
+        tag: &str,
+    ) -> Result<usize, BankError> {
+        let tag = tag.to_string();
+        self.write(move |bank| bank.untag_transactions(id, &transaction_ids, &tag)).await
+    }
+
+    /// See `Bank::register_payee`.
+    pub async fn register_payee(&self, account: UserId, payee: Payee) -> Result<(), BankError> {
+        self.write(move |bank| bank.register_payee(account, payee)).await
+    }
+
+    /// See `Bank::remove_payee`.
+    pub async fn remove_payee(&self, account: UserId) -> Result<(), BankError> {
+        self.write(move |bank| bank.remove_payee(account)).await
+    }
+
+    /// See `Bank::correct_payee`.
+    pub async fn correct_payee(&self, id: UserId, account: UserId, payee: Option<Payee>) -> Result<(), BankError> {
+        self.write(move |bank| bank.correct_payee(id, account, payee)).await
+    }
+
+    /// See `Bank::sweep_fees`.
+    pub async fn sweep_fees(&self, currency: Currency) -> Result<Money, BankError> {
+        self.write(move |bank| bank.sweep_fees(currency)).await
+    }
+
+    /// See `Bank::propose_policy`.
+    pub async fn propose_policy(&self, policy: Policy, operator: &str) -> Result<u32, BankError> {
+        let operator = operator.to_string();
+        self.write(move |bank| bank.propose_policy(policy, &operator)).await
+    }
+
+    /// See `Bank::confirm_policy`.
+    pub async fn confirm_policy(&self, change: u32, operator: &str) -> Result<Policy, BankError> {
+        let operator = operator.to_string();
+        self.write(move |bank| bank.confirm_policy(change, &operator)).await
+    }
+
+    /// See `Bank::cancel_policy`.
+    pub async fn cancel_policy(&self, change: u32, operator: &str) -> Result<Policy, BankError> {
+        let operator = operator.to_string();
+        self.write(move |bank| bank.cancel_policy(change, &operator)).await
+    }
+
+    /// The user's balance in `currency` across all of their accounts.
+    pub async fn balance(&self, id: UserId, currency: Currency) -> Result<Balance, BankError> {
+        self.read(|bank| bank.get_user(id).map(|user| user.balance(currency)).ok_or_else(|| unknown_user(id)))
+            .await
+    }
+
+    /// See `Bank::history`.
+    pub async fn history(&self, id: UserId, tag: Option<&str>) -> Result<Vec<Transaction>, BankError> {
+        self.read(|bank| Ok(bank.history(id, tag)?.into_iter().cloned().collect())).await
+    }
+
+    /// See `Bank::statement`.
+    pub async fn statement(&self, id: UserId, tag: Option<&str>) -> Result<Vec<String>, BankError> {
+        self.read(|bank| bank.statement(id, tag)).await
+    }
+
+    /// See `Bank::interest_forecast`.
+    pub async fn interest_forecast(&self, id: UserId) -> Result<Vec<InterestForecast>, BankError> {
+        self.read(|bank| bank.interest_forecast(id)).await
+    }
+
+    /// See `Bank::recurring_income`.
+    pub async fn recurring_income(&self, id: UserId) -> Result<Option<RecurringIncome>, BankError> {
+        self.read(|bank| bank.recurring_income(id)).await
+    }
+
+    /// See `Bank::advance_limit`.
+    pub async fn advance_limit(&self, id: UserId) -> Result<(Money, Currency), BankError> {
+        self.read(|bank| bank.advance_limit(id)).await
+    }
+
+    /// See `Bank::check_integrity`.
+    pub async fn check_integrity(&self) -> Vec<String> {
+        self.read(|bank| bank.check_integrity()).await
+    }
+}
+
+impl<S: Store> Bank<S> {
+    /// Run `op`, putting every user, the treasury and the clock back as they
+    /// were if it panics, and rolling back any store transaction it left
+    /// open. This saves each user, less its ledger, before every call.
+    #[cfg_attr(not(any(feature = "server", feature = "grpc")), allow(dead_code))]
+    fn undoing_panics<T>(&mut self, op: impl FnOnce(&mut Self) -> Result<T, BankError>) -> Result<T, BankError> {
+        let ids: Vec<UserId> = self.users.keys().copied().collect();
+        let checkpoint = self.checkpoint(&ids);
+        let clock = Arc::clone(&self.clock);
+        match panic::catch_unwind(AssertUnwindSafe(|| op(self))) {
+            Ok(result) => result,
+            Err(_) => {
+                let last_user = checkpoint.next_user_id;
+                self.restore(checkpoint);
+                self.users.retain(|id, _| u32::from(*id) <= last_user);
+                self.clock = clock;
+                self.pending = None;
+                // The panic may have come before `begin` or after `commit`,
+                // leaving nothing to roll back.
+                let _ = self.store.rollback();
+                Err(BankError::Internal(String::from("The bank operation panicked and was undone")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio::runtime::{Builder, Runtime};
+
+    use super::*;
+    use crate::user::{Treasury, User};
+
+    fn runtime() -> Runtime {
+        Builder::new_current_thread().build().unwrap()
+    }
+
+    /// Keeps users and the treasury, but not their ledgers.
+    #[derive(Debug, Default)]
+    struct UserMap {
+        users: HashMap<UserId, User>,
+        treasury: Option<Treasury>,
+    }
+
+    impl AsyncStore for UserMap {
+        async fn load_users(&self) -> Result<Vec<User>, String> {
+            Ok(self.users.values().cloned().collect())
+        }
+
+        async fn save_user(&mut self, user: &User) -> Result<(), String> {
+            self.users.insert(user.id, user.clone());
+            Ok(())
+        }
+
+        async fn load_treasury(&self) -> Result<Treasury, String> {
+            Ok(self.treasury.clone().unwrap_or_default())
+        }
+
+        async fn save_treasury(&mut self, treasury: &Treasury) -> Result<(), String> {
+            self.treasury = Some(treasury.clone());
+            Ok(())
+        }
+
+        async fn append_transaction(
+            &mut self,
+            _owner: Option<UserId>,
+            _transaction: &Transaction,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        async fn save_tags(&mut self, _transaction: &Transaction) -> Result<(), String> {
+            Ok(())
+        }
+
+        async fn save_payee(&mut self, _account: UserId, _payee: Option<&Payee>) -> Result<(), String> {
+            Ok(())
+        }
+
+        async fn clear(&mut self) -> Result<(), String> {
+            *self = UserMap::default();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn banks_open_over_an_async_store() {
+        runtime().block_on(async {
+            let bank = AsyncBank::open(UserMap::default()).await.unwrap();
+            let id = bank.open_account("alice").await.unwrap();
+            bank.deposit(id, Money::from_major(100), Currency::Usd, false).await.unwrap();
+            let saved = bank.read(|bank| bank.store.get_ref().users[&id].balance(Currency::Usd)).await;
+            assert_eq!(saved, bank.balance(id, Currency::Usd).await.unwrap());
+            assert!(saved.deposited > Money::ZERO);
+        });
+    }
+
+    #[test]
+    fn a_panicking_operation_is_undone() {
+        runtime().block_on(async {
+            let bank = AsyncBank::new(Bank::new());
+            let id = bank.open_account("alice").await.unwrap();
+            bank.deposit(id, Money::from_major(100), Currency::Usd, false).await.unwrap();
+            let before = bank.balance(id, Currency::Usd).await.unwrap();
+
+            let panicked = bank
+                .write(move |bank| -> Result<(), BankError> {
+                    bank.open_account("bob")?;
+                    bank.withdraw(id, Money::from_major(10), Currency::Usd)?;
+                    panic!("bug in the operation")
+                })
+                .await;
+            assert!(matches!(panicked, Err(BankError::Internal(_))));
+            assert_eq!(bank.balance(id, Currency::Usd).await.unwrap(), before);
+            assert_eq!(bank.read(|bank| bank.users().count()).await, 1);
+            assert!(bank.read(|bank| bank.check_integrity()).await.is_empty());
+
+            bank.withdraw(id, Money::from_major(10), Currency::Usd).await.unwrap();
+            assert!(bank.balance(id, Currency::Usd).await.unwrap().deposited < before.deposited);
+        });
+    }
+}
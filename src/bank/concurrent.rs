@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockWriteGuard};
+use std::time::{Instant, SystemTime};
+
+use super::{Bank, charged, unknown_user};
+use crate::account::{Account, AccountKind};
+use crate::aggregates::Aggregates;
+use crate::currency::Currency;
+use crate::event::{BankEvent, Observers};
+use crate::metrics::{Metrics, Operation};
+use crate::money::Money;
+use crate::time::Clock;
+use crate::types::{AccountId, UserId};
+use crate::user::{Treasury, User};
+
+/// An in-memory `Bank` that can be shared between threads, e.g. behind an
+/// `Arc`.
+///
+/// Every user sits behind their own `RwLock`, so operations on different users
+/// run in parallel. An operation write-locks the users it touches in
+/// ascending id order and only then the treasury, so two-party operations
+/// such as `transfer` cannot deadlock each other. Transfers lock the treasury
+/// only when the receiver has a salary advance or installments to settle, or
+/// when either side is owed interest on a balance the transfer changes.
+///
+/// Users are shared copy-on-write, so reports that scan every account run on
+/// a `Snapshot` instead of holding locks while they scan.
+///
+/// Operations are reported to the observers of the `Bank` it was made from,
+/// from the thread that ran them.
+#[derive(Debug)]
+pub struct ConcurrentBank {
+    users: RwLock<HashMap<UserId, RwLock<Arc<User>>>>,
+    treasury: Mutex<Treasury>,
+    next_user_id: AtomicU32,
+    metrics: Mutex<Metrics>,
+    clock: Arc<dyn Clock>,
+    observers: Observers,
+}
+
+/// Every user and the bank-wide totals as of one moment, taken by
+/// `ConcurrentBank::snapshot`.
+///
+/// The users are shared with the bank until an operation next changes them,
+/// so taking a snapshot copies no accounts and holds the locks only long
+/// enough to clone one `Arc` per user.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    users: Vec<Arc<User>>,
+    aggregates: Aggregates,
+}
+
+impl Snapshot {
+    /// Every user, by id.
+    pub fn users(&self) -> impl Iterator<Item = &User> {
+        self.users.iter().map(|user| &**user)
+    }
+
+    /// The sum of every user's deposited balance in `currency`.
+    pub fn deposits(&self, currency: Currency) -> Money {
+        Aggregates::scan(self.users()).totals(currency).deposits
+    }
+
+    /// The tracked totals against a recomputation over every account, one
+    /// line per currency where they disagree. Empty when the books balance.
+    pub fn trial_balance(&self) -> Vec<String> {
+        self.aggregates.mismatches(&Aggregates::scan(self.users()))
+    }
+}
+
+/// The treasury as seen by one operation, locked the first time it is needed.
+struct TreasuryLock<'a> {
+    mutex: &'a Mutex<Treasury>,
+    guard: Option<MutexGuard<'a, Treasury>>,
+    mark: usize,
+    checkpoint: Option<Treasury>,
+}
+
+impl<'a> TreasuryLock<'a> {
+    fn get(&mut self) -> Result<&mut Treasury, String> {
+        if self.guard.is_none() {
+            let mut guard = self.mutex.lock().map_err(poisoned)?;
+            self.mark = guard.transactions.len();
+            self.checkpoint = Some(guard.checkpoint());
+            self.guard = Some(guard);
+        }
+        Ok(self.guard.as_mut().expect("treasury locked above"))
+    }
+
+    /// Pay the interest `user` is owed on balances changed since `before`,
+    /// as `Bank::apply_and_write` does, locking the treasury only if there
+    /// is some to pay.
+    fn settle_interest(&mut self, before: &User, user: &mut User, now: SystemTime) -> Result<(), String> {
+        if user.unsettled_balances(before, now).is_empty() {
+            return Ok(());
+        }
+        self.get()?;
+        let guard = self.guard.as_mut().expect("treasury locked above");
+        let previous = self.checkpoint.as_ref().expect("treasury saved when locked");
+        guard.settle_interest(previous, before, user, now)?;
+        Ok(())
+    }
+
+    /// Charge the facility interest owed if the operation drew on or repaid
+    /// the facility, which it can only have done with the treasury locked.
+    fn settle_facility(&mut self, now: SystemTime) -> Result<(), String> {
+        if let (Some(guard), Some(previous)) = (&mut self.guard, &self.checkpoint) {
+            guard.settle_facility(&previous.facility, now)?;
+        }
+        Ok(())
+    }
+
+    /// Put the treasury back as it was when it was locked, if it was.
+    fn restore(&mut self) {
+        if let (Some(guard), Some(checkpoint)) = (&mut self.guard, self.checkpoint.take()) {
+            guard.restore(checkpoint, self.mark);
+        }
+    }
+}
+
+impl Bank {
+    /// Hand the bank over to a `ConcurrentBank` for use from several threads.
+    pub fn into_concurrent(self) -> ConcurrentBank {
+        ConcurrentBank {
+            users: RwLock::new(self.users.into_iter().map(|(id, user)| (id, RwLock::new(Arc::new(user)))).collect()),
+            treasury: Mutex::new(self.treasury),
+            next_user_id: AtomicU32::new(self.next_user_id),
+            metrics: Mutex::new(self.metrics),
+            clock: self.clock,
+            observers: self.observers,
+        }
+    }
+}
+
+impl ConcurrentBank {
+    /// Register a new user with a default account and return their id.
+    pub fn open_account(&self, name: &str) -> Result<UserId, String> {
+        let start = Instant::now();
+        let id = UserId::from(self.next_user_id.fetch_add(1, Ordering::Relaxed) + 1);
+        let (account, kind) = (Account::new(AccountKind::default()), AccountKind::default());
+        let account_id = account.id;
+        let user = User {
+            id,
+            name: name.to_string(),
+            accounts: vec![account],
+            ..Default::default()
+        };
+        self.users.write().map_err(poisoned)?.insert(id, RwLock::new(Arc::new(user)));
+        self.record(Operation::OpenAccount, start);
+        self.observers.emit(&BankEvent::UserRegistered {
+            user: id,
+            name: name.to_string(),
+            account: account_id,
+            kind,
+        });
+        Ok(id)
+    }
+
+    /// Deposit with the entry fee deducted into the user's primary account.
+    /// See `Bank::deposit_into`.
+    pub fn deposit(&self, id: UserId, amount: Money, currency: Currency, is_borrowable: bool) -> Result<(), String> {
+        let (account, net) = self.tracked(Operation::Deposit, &[id], |users, treasury, now| {
+            let [user] = users else { unreachable!("one user locked") };
+            let account = user.primary_account().ok_or_else(|| unknown_user(id))?.id;
+            let treasury = treasury.get()?;
+            treasury.charge_overdraft_interest(user, currency, now)?;
+            let fees = treasury.fees;
+            let net = user.deposit_with_fee(account, amount, currency, treasury, &fees, is_borrowable)?;
+            user.settle_after_credit(account, now, treasury)?;
+            Ok((account, net))
+        })?;
+        self.observers.emit(&BankEvent::Deposited {
+            user: id,
+            account,
+            amount,
+            currency,
+            borrowable: is_borrowable,
+        });
+        self.charged(id, account, amount.checked_sub(net).unwrap_or(Money::ZERO), currency);
+        Ok(())
+    }
+
+    /// Move `amount` from one user's primary account to another's. See
+    /// `User::transfer_to`.
+    pub fn transfer(&self, from: UserId, to: UserId, amount: Money, currency: Currency) -> Result<Money, String> {
+        let (source, target, sent) = self.tracked(Operation::Transfer, &[from, to], |users, treasury, now| {
+            let [sender, receiver] = users else { unreachable!("two users locked") };
+            let source = sender.primary_account().ok_or_else(|| unknown_user(from))?.id;
+            let target = receiver.primary_account().ok_or_else(|| unknown_user(to))?.id;
+            sender.start_debit(source, now)?;
+            let sent = sender.transfer_to(source, receiver, target, amount, currency)?;
+            if receiver.salary_advance.is_some() || !receiver.installment_plans.is_empty() {
+                receiver.settle_after_credit(target, now, treasury.get()?)?;
+            }
+            Ok((source, target, sent))
+        })?;
+        self.observers.emit(&BankEvent::Transferred {
+            sender: from,
+            account: source,
+            receiver: to,
+            receiver_account: target,
+            amount: sent,
+            currency,
+        });
+        Ok(sent)
+    }
+
+    /// Have `borrower` borrow `amount` from `lender`, between their primary
+    /// accounts. See `User::borrow`.
+    pub fn borrow_between(
+        &self,
+        borrower_id: UserId,
+        lender_id: UserId,
+        amount: Money,
+        currency: Currency,
+    ) -> Result<Money, String> {
+        let clock = Arc::clone(&self.clock);
+        let (borrowed, event) = self.tracked(Operation::Borrow, &[borrower_id, lender_id], |users, _, now| {
+            let [borrower, lender] = users else { unreachable!("two users locked") };
+            let account = borrower.primary_account().ok_or_else(|| unknown_user(borrower_id))?.id;
+            let lender_account = lender.primary_account().ok_or_else(|| unknown_user(lender_id))?.id;
+            lender.start_debit(lender_account, now)?;
+            let borrowed = borrower.borrow(account, lender, lender_account, amount, currency, &*clock)?;
+            let loan = borrower.debts().last().map(|loan| loan.id).ok_or("The new loan is missing")?;
+            let event = BankEvent::Borrowed {
+                loan,
+                borrower: borrower_id,
+                account,
+                lender: lender_id,
+                lender_account,
+                amount: borrowed,
+                currency,
+            };
+            Ok((borrowed, event))
+        })?;
+        self.observers.emit(&event);
+        Ok(borrowed)
+    }
+
+    /// A consistent view of every user and the tracked totals. Every user
+    /// and then the treasury are locked, in the same order operations lock
+    /// them, so no operation is half applied in the snapshot; operations
+    /// carry on once the users' `Arc`s are cloned, and copy a user before
+    /// changing one the snapshot still shares.
+    pub fn snapshot(&self) -> Result<Snapshot, String> {
+        let users = self.users.read().map_err(poisoned)?;
+        let mut ids: Vec<&UserId> = users.keys().collect();
+        ids.sort();
+        let mut locked = Vec::with_capacity(ids.len());
+        for id in ids {
+            locked.push(users[id].read().map_err(poisoned)?);
+        }
+        let treasury = self.treasury.lock().map_err(poisoned)?;
+        Ok(Snapshot {
+            users: locked.iter().map(|user| Arc::clone(user)).collect(),
+            aggregates: treasury.aggregates.clone(),
+        })
+    }
+
+    /// Compare the tracked totals against a full recomputation over a
+    /// `snapshot`.
+    pub fn check_integrity(&self) -> Result<Vec<String>, String> {
+        Ok(self.snapshot()?.trial_balance())
+    }
+
+    /// Latencies of the operations run so far.
+    pub fn metrics(&self) -> Result<Metrics, String> {
+        Ok(self.metrics.lock().map_err(poisoned)?.clone())
+    }
+
+    fn tracked<T>(
+        &self,
+        operation: Operation,
+        ids: &[UserId],
+        op: impl FnOnce(&mut [&mut User], &mut TreasuryLock<'_>, SystemTime) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let start = Instant::now();
+        let result = self.apply(ids, op);
+        self.record(operation, start);
+        result
+    }
+
+    /// Lock the users in `ids` in ascending id order, run `op` on them in the
+    /// order given and keep the totals and timestamps current, like
+    /// `Bank::apply_and_write`. The users and the treasury are put back if
+    /// `op` fails.
+    fn apply<T>(
+        &self,
+        ids: &[UserId],
+        op: impl FnOnce(&mut [&mut User], &mut TreasuryLock<'_>, SystemTime) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut order: Vec<usize> = (0..ids.len()).collect();
+        order.sort_by_key(|&index| ids[index]);
+        if order.windows(2).any(|pair| ids[pair[0]] == ids[pair[1]]) {
+            return Err(String::from("Both sides of the operation are the same user"));
+        }
+        let users = self.users.read().map_err(poisoned)?;
+        let mut guards: Vec<Option<RwLockWriteGuard<'_, Arc<User>>>> = ids.iter().map(|_| None).collect();
+        for index in order {
+            let user = users.get(&ids[index]).ok_or_else(|| unknown_user(ids[index]))?;
+            guards[index] = Some(user.write().map_err(poisoned)?);
+        }
+        let mut touched: Vec<&mut User> = guards.iter_mut().flatten().map(|guard| Arc::make_mut(&mut **guard)).collect();
+        let marks: Vec<usize> = touched.iter().map(|user| user.transactions.len()).collect();
+        let saved: Vec<User> = touched.iter_mut().map(|user| user.checkpoint()).collect();
+        let mut treasury = TreasuryLock {
+            mutex: &self.treasury,
+            guard: None,
+            mark: 0,
+            checkpoint: None,
+        };
+        let result = self.apply_locked(&mut touched, &saved, &marks, &mut treasury, op);
+        if result.is_err() {
+            for ((user, saved), &mark) in touched.iter_mut().zip(saved).zip(&marks) {
+                user.restore(saved, mark);
+            }
+            treasury.restore();
+        }
+        result
+    }
+
+    fn apply_locked<T>(
+        &self,
+        touched: &mut [&mut User],
+        saved: &[User],
+        marks: &[usize],
+        treasury: &mut TreasuryLock<'_>,
+        op: impl FnOnce(&mut [&mut User], &mut TreasuryLock<'_>, SystemTime) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let before = contribution(touched);
+        let now = self.clock.now();
+        let value = op(touched, treasury, now)?;
+        for (user, before) in touched.iter_mut().zip(saved) {
+            treasury.settle_interest(before, user, now)?;
+        }
+        treasury.settle_facility(now)?;
+
+        for (user, &mark) in touched.iter_mut().zip(marks) {
+            for transaction in &mut user.transactions[mark..] {
+                transaction.timestamp = now;
+            }
+        }
+        let after = contribution(touched);
+        if after != before {
+            let treasury = treasury.get()?;
+            treasury.aggregates.subtract(&before);
+            treasury.aggregates.add(&after);
+        }
+        if let Some(guard) = &mut treasury.guard {
+            for transaction in &mut guard.transactions[treasury.mark..] {
+                transaction.timestamp = now;
+            }
+        }
+        for user in touched {
+            user.mark_interest_start(now);
+        }
+        Ok(value)
+    }
+
+    /// Report `fee` taken from `account`, unless there was none.
+    fn charged(&self, user: UserId, account: AccountId, fee: Money, currency: Currency) {
+        if let Some(event) = charged(user, account, fee, currency) {
+            self.observers.emit(&event);
+        }
+    }
+
+    fn record(&self, operation: Operation, start: Instant) {
+        if let Ok(mut metrics) = self.metrics.lock() {
+            metrics.record(operation, start.elapsed());
+        }
+    }
+}
+
+/// What `users` together contribute to the bank-wide totals.
+fn contribution(users: &[&mut User]) -> Aggregates {
+    let mut aggregates = Aggregates::default();
+    for user in users {
+        aggregates.add(&Aggregates::of(user));
+    }
+    aggregates
+}
+
+fn poisoned<T>(_: PoisonError<T>) -> String {
+    String::from("A bank lock was poisoned by a panicking operation")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    use super::ConcurrentBank;
+    use crate::account::AccountKind;
+    use crate::bank::Bank;
+    use crate::currency::Currency;
+    use crate::money::Money;
+    use crate::types::UserId;
+
+    const USERS: usize = 6;
+    const THREADS: usize = 8;
+    const TRANSFERS: usize = 400;
+
+    /// Users with checking accounts, so that the transfers are not held to
+    /// the savings withdrawal limit.
+    fn funded_bank() -> (ConcurrentBank, Vec<UserId>) {
+        let mut bank = Bank::new();
+        let ids = (1..=USERS)
+            .map(|n| {
+                let id = bank.open_account_of_kind(&format!("User {}", n), AccountKind::Checking).unwrap();
+                bank.deposit(id, Money::from_major(1000), Currency::Usd, true).unwrap();
+                id
+            })
+            .collect();
+        (bank.into_concurrent(), ids)
+    }
+
+    /// Every thread moves small amounts around all of the users, so pairs
+    /// overlap in both directions and lock the same users in turn.
+    fn transfer_around(bank: &ConcurrentBank, ids: &[UserId], worker: usize) {
+        for n in 0..TRANSFERS {
+            let sender = (worker + n) % ids.len();
+            let receiver = (sender + 1 + n % (ids.len() - 1)) % ids.len();
+            let amount = Money::from_minor(1 + (n % 50) as u64);
+            bank.transfer(ids[sender], ids[receiver], amount, Currency::Usd).unwrap();
+        }
+    }
+
+    #[test]
+    fn concurrent_transfers_conserve_funds() {
+        let (bank, ids) = funded_bank();
+        let before = bank.snapshot().unwrap().deposits(Currency::Usd);
+
+        thread::scope(|scope| {
+            for worker in 0..THREADS {
+                let (bank, ids) = (&bank, &ids);
+                scope.spawn(move || transfer_around(bank, ids, worker));
+            }
+        });
+
+        assert_eq!(bank.snapshot().unwrap().deposits(Currency::Usd), before);
+        assert_eq!(bank.check_integrity().unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn snapshots_during_transfers_balance() {
+        let (bank, ids) = funded_bank();
+        let before = bank.snapshot().unwrap().deposits(Currency::Usd);
+        let running = AtomicBool::new(true);
+
+        let snapshots = thread::scope(|scope| {
+            let reporter = scope.spawn(|| {
+                let mut snapshots = 0;
+                while running.load(Ordering::Relaxed) {
+                    let snapshot = bank.snapshot().unwrap();
+                    assert_eq!(snapshot.trial_balance(), Vec::<String>::new());
+                    assert_eq!(snapshot.deposits(Currency::Usd), before);
+                    snapshots += 1;
+                }
+                snapshots
+            });
+            let workers: Vec<_> = (0..THREADS)
+                .map(|worker| {
+                    let (bank, ids) = (&bank, &ids);
+                    scope.spawn(move || transfer_around(bank, ids, worker))
+                })
+                .collect();
+            for worker in workers {
+                worker.join().unwrap();
+            }
+            running.store(false, Ordering::Relaxed);
+            reporter.join().unwrap()
+        });
+
+        assert!(snapshots > 0);
+    }
+}
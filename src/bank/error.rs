@@ -0,0 +1,48 @@
+use std::fmt;
+
+/// Why a `Bank` operation failed, so that callers such as the network APIs
+/// can report it with the right status without reading the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BankError {
+    /// A user, account, loan or transaction that does not exist.
+    NotFound(String),
+    /// The bank refused the request, e.g. for lack of funds.
+    Rejected(String),
+    /// The bank could not carry out the request, e.g. because the store failed.
+    Internal(String),
+}
+
+impl BankError {
+    pub fn message(&self) -> &str {
+        match self {
+            BankError::NotFound(message) | BankError::Rejected(message) | BankError::Internal(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for BankError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+/// The domain types report refusals as plain messages.
+impl From<String> for BankError {
+    fn from(message: String) -> Self {
+        BankError::Rejected(message)
+    }
+}
+
+impl From<&str> for BankError {
+    fn from(message: &str) -> Self {
+        BankError::Rejected(message.to_string())
+    }
+}
+
+impl From<BankError> for String {
+    fn from(err: BankError) -> Self {
+        match err {
+            BankError::NotFound(message) | BankError::Rejected(message) | BankError::Internal(message) => message,
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::Bank;
+use crate::event::{BankEvent, NextIds, RecordedEvent};
+use crate::metrics::Metrics;
+use crate::time::{self, MockClock};
+
+impl Bank {
+    /// Rebuild a bank by running the operation behind each of `events` again,
+    /// in order, at the time it ran and allocating from the ids it did. The
+    /// result matches the bank that recorded the events, down to ledger ids
+    /// and timestamps.
+    ///
+    /// Fails if an operation no longer succeeds, or if the ids it needs were
+    /// already handed out in this process, e.g. to another bank. Ledger,
+    /// account, loan and plan ids come from counters shared by every bank in
+    /// the process, so a log can only be replayed before anything else has
+    /// allocated past the ids at its start, normally once at startup.
+    pub fn replay(events: impl IntoIterator<Item = RecordedEvent>) -> Result<Bank, String> {
+        let clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+        let mut bank = Bank::new();
+        bank.set_clock(clock.clone());
+        for (index, recorded) in events.into_iter().enumerate() {
+            // Reported as part of the deposit or withdrawal it follows.
+            if let BankEvent::FeeCharged { .. } = recorded.event {
+                continue;
+            }
+            let failed = |err: String| format!("Cannot replay event {} ({}): {}", index + 1, recorded.event, err);
+            recorded.ids.reserve();
+            if NextIds::current() != recorded.ids {
+                return Err(failed(String::from("the ids it used are already taken")));
+            }
+            clock.set(recorded.at);
+            bank.apply(&recorded.event).map_err(failed)?;
+        }
+        bank.set_clock(time::system_clock());
+        bank.metrics = Metrics::default();
+        Ok(bank)
+    }
+
+    /// Run the operation `event` describes.
+    fn apply(&mut self, event: &BankEvent) -> Result<(), String> {
+        match event.clone() {
+            BankEvent::UserRegistered { user, name, kind, .. } => {
+                let id = self.open_account_of_kind(&name, kind)?;
+                if id != user {
+                    return Err(format!("it registered user {} instead", id));
+                }
+            }
+            BankEvent::AccountOpened { user, kind, .. } => {
+                self.add_account(user, kind)?;
+            }
+            BankEvent::Deposited { account, amount, currency, borrowable, .. } => {
+                self.deposit_into(account, amount, currency, borrowable)?;
+            }
+            BankEvent::Withdrawn { account, amount, currency, .. } => {
+                self.withdraw_from(account, amount, currency)?;
+            }
+            BankEvent::FeeCharged { .. } => {}
+            BankEvent::Minted { user, amount, currency, .. } => {
+                self.faucet(user, amount, currency)?;
+            }
+            BankEvent::Converted { user, amount, from, to, rate_bps, .. } => {
+                self.convert(user, amount, from, to, rate_bps)?;
+            }
+            BankEvent::Transferred { account, receiver_account, amount, currency, .. } => {
+                self.transfer_between(account, receiver_account, amount, currency)?;
+            }
+            BankEvent::Borrowed { account, lender_account, amount, currency, .. } => {
+                self.borrow_into(account, lender_account, amount, currency)?;
+            }
+            BankEvent::Repaid { loan, amount, .. } => {
+                self.repay(loan, amount)?;
+            }
+            BankEvent::InterestApplied { user, currency, until, .. } => {
+                self.accrue_until(user, currency, until)?;
+            }
+            BankEvent::SalaryAdvanced { account, amount, .. } => {
+                self.take_salary_advance(account, amount)?;
+            }
+            BankEvent::BoughtInInstallments { account, payee_account, price, currency, installments, .. } => {
+                self.buy_in_installments(account, payee_account, price, currency, installments)?;
+            }
+            BankEvent::InstallmentsCollected { user, .. } => {
+                self.collect_installments(user)?;
+            }
+            BankEvent::OverdraftGranted { user, currency, limit, rate_bps } => {
+                self.grant_overdraft(user, currency, limit, rate_bps)?;
+            }
+            BankEvent::OverdraftRevoked { user } => self.revoke_overdraft(user)?,
+            BankEvent::Retagged { user, transactions, tag, added: true } => {
+                self.tag_transactions(user, &transactions, &tag)?;
+            }
+            BankEvent::Retagged { user, transactions, tag, added: false } => {
+                self.untag_transactions(user, &transactions, &tag)?;
+            }
+            BankEvent::PayeeRegistered { account, payee } => self.register_payee(account, payee)?,
+            BankEvent::PayeeRemoved { account } => self.remove_payee(account)?,
+            BankEvent::PayeeCorrected { user, account, payee } => self.correct_payee(user, account, payee)?,
+            BankEvent::FeesSwept { currency, .. } => {
+                self.sweep_fees(currency)?;
+            }
+            BankEvent::PolicyProposed { change, policy, operator } => {
+                let id = self.propose_policy(policy, &operator)?;
+                if id != change {
+                    return Err(format!("it proposed change #{} instead", id));
+                }
+            }
+            BankEvent::PolicyConfirmed { change, operator } => {
+                self.confirm_policy(change, &operator)?;
+            }
+            BankEvent::PolicyCancelled { change, operator } => {
+                self.cancel_policy(change, &operator)?;
+            }
+            BankEvent::EnvironmentChanged { environment } => self.set_environment(environment)?,
+            BankEvent::Reset => self.wipe(),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::bank::Bank;
+    use crate::currency::Currency;
+    use crate::money::Money;
+    use crate::store::event_log::EventLogStore;
+
+    #[test]
+    fn replay_fails_once_the_ids_of_the_log_are_taken() {
+        let path = std::env::temp_dir().join(format!("replay-ids-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let mut bank = Bank::open(EventLogStore::open(&path).unwrap()).unwrap();
+            let id = bank.open_account("Ada").unwrap();
+            bank.deposit(id, Money::from_major(100), Currency::Usd, true).unwrap();
+        }
+
+        // The bank that wrote the log already allocated its ids in this process.
+        let reopened = Bank::open(EventLogStore::open(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        let Err(err) = reopened else {
+            panic!("replay should fail");
+        };
+        assert!(err.message().contains("already taken"), "{}", err);
+    }
+}
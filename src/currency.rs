@@ -0,0 +1,51 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Currency {
+    Usd,
+    Eur,
+    Gbp,
+    Brl,
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            Currency::Usd => "USD",
+            Currency::Eur => "EUR",
+            Currency::Gbp => "GBP",
+            Currency::Brl => "BRL",
+        };
+        write!(f, "{}", code)
+    }
+}
+
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "USD" => Ok(Currency::Usd),
+            "EUR" => Ok(Currency::Eur),
+            "GBP" => Ok(Currency::Gbp),
+            "BRL" => Ok(Currency::Brl),
+            _ => Err(format!("Unknown currency '{}'", s)),
+        }
+    }
+}
+
+/// Running totals held in a single currency.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Balance {
+   pub deposited: Money,
+   pub withdrawn: Money,
+   /// When interest on `deposited` was last settled; `None` while it is zero.
+   pub interest_since: Option<SystemTime>,
+}
@@ -0,0 +1,429 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::account::{self, AccountKind};
+use crate::bank::Environment;
+use crate::currency::Currency;
+use crate::installment;
+use crate::ledger;
+use crate::loan;
+use crate::money::Money;
+use crate::payee::Payee;
+use crate::policy::Policy;
+use crate::types::{AccountId, LoanId, PlanId, UserId};
+
+/// Version of the layout of `RecordedEvent`. Events written at an older
+/// version are upcast by `decode`; newer ones are refused rather than misread.
+///
+/// Version 2 names the accounts of `Transferred` like those of `Borrowed`.
+pub const EVENT_VERSION: u32 = 2;
+
+/// A change to the bank, reported to the observers registered with
+/// `Bank::on_event` once the operation behind it has succeeded and been written
+/// to the store. Amounts are what the operation was asked to move; entry and
+/// exit fees are reported separately as `FeeCharged`.
+///
+/// Each event holds what is needed to run its operation again, so
+/// `Bank::replay` can rebuild a bank from its events. `FeeCharged` only
+/// reports part of the deposit or withdrawal before it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BankEvent {
+    UserRegistered {
+        user: UserId,
+        name: String,
+        account: AccountId,
+        kind: AccountKind,
+    },
+    AccountOpened {
+        user: UserId,
+        account: AccountId,
+        kind: AccountKind,
+    },
+    Deposited {
+        user: UserId,
+        account: AccountId,
+        amount: Money,
+        currency: Currency,
+        borrowable: bool,
+    },
+    Withdrawn {
+        user: UserId,
+        account: AccountId,
+        amount: Money,
+        currency: Currency,
+    },
+    /// An entry or exit fee taken by the treasury from `account`.
+    FeeCharged {
+        user: UserId,
+        account: AccountId,
+        fee: Money,
+        currency: Currency,
+    },
+    /// Sandbox money minted by `Bank::faucet`.
+    Minted {
+        user: UserId,
+        account: AccountId,
+        amount: Money,
+        currency: Currency,
+    },
+    Converted {
+        user: UserId,
+        account: AccountId,
+        amount: Money,
+        from: Currency,
+        to: Currency,
+        rate_bps: u32,
+        credited: Money,
+    },
+    Transferred {
+        sender: UserId,
+        account: AccountId,
+        receiver: UserId,
+        receiver_account: AccountId,
+        amount: Money,
+        currency: Currency,
+    },
+    Borrowed {
+        loan: LoanId,
+        borrower: UserId,
+        account: AccountId,
+        lender: UserId,
+        lender_account: AccountId,
+        amount: Money,
+        currency: Currency,
+    },
+    Repaid {
+        loan: LoanId,
+        borrower: UserId,
+        lender: UserId,
+        amount: Money,
+    },
+    /// Deposit interest credited up to `until`.
+    InterestApplied {
+        user: UserId,
+        currency: Currency,
+        amount: Money,
+        until: SystemTime,
+    },
+    SalaryAdvanced {
+        user: UserId,
+        account: AccountId,
+        amount: Money,
+        currency: Currency,
+    },
+    BoughtInInstallments {
+        plan: PlanId,
+        payer: UserId,
+        account: AccountId,
+        payee: UserId,
+        payee_account: AccountId,
+        price: Money,
+        currency: Currency,
+        installments: u32,
+    },
+    InstallmentsCollected {
+        user: UserId,
+        amount: Money,
+    },
+    OverdraftGranted {
+        user: UserId,
+        currency: Currency,
+        limit: Money,
+        rate_bps: u32,
+    },
+    OverdraftRevoked {
+        user: UserId,
+    },
+    /// `tag` added to (or, if not `added`, removed from) the user's
+    /// `transactions` that did not already have it that way.
+    Retagged {
+        user: UserId,
+        transactions: Vec<u64>,
+        tag: String,
+        added: bool,
+    },
+    PayeeRegistered {
+        account: UserId,
+        payee: Payee,
+    },
+    PayeeRemoved {
+        account: UserId,
+    },
+    /// The user's own name for `account`, or `None` to go back to the
+    /// payee directory.
+    PayeeCorrected {
+        user: UserId,
+        account: UserId,
+        payee: Option<Payee>,
+    },
+    FeesSwept {
+        currency: Currency,
+        amount: Money,
+    },
+    /// `operator` put `policy` forward as change `change`.
+    PolicyProposed {
+        change: u32,
+        policy: Policy,
+        operator: String,
+    },
+    /// `operator` confirmed change `change`, whose policy now applies.
+    PolicyConfirmed {
+        change: u32,
+        operator: String,
+    },
+    PolicyCancelled {
+        change: u32,
+        operator: String,
+    },
+    EnvironmentChanged {
+        environment: Environment,
+    },
+    /// Every user and the treasury were wiped by `Bank::reset`. The events
+    /// of rebuilding the seeded state follow.
+    Reset,
+}
+
+impl fmt::Display for BankEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BankEvent::UserRegistered { user, name, account, kind } => {
+                write!(f, "user {} ({}) registered with {} account {}", user, name, kind, account)
+            }
+            BankEvent::AccountOpened { user, account, kind } => {
+                write!(f, "user {} opened {} account {}", user, kind, account)
+            }
+            BankEvent::Deposited { user, account, amount, currency, .. } => {
+                write!(f, "user {} deposited {} {} into account {}", user, amount, currency, account)
+            }
+            BankEvent::Withdrawn { user, account, amount, currency } => {
+                write!(f, "user {} withdrew {} {} from account {}", user, amount, currency, account)
+            }
+            BankEvent::FeeCharged { user, account, fee, currency } => {
+                write!(f, "user {} charged a fee of {} {} on account {}", user, fee, currency, account)
+            }
+            BankEvent::Minted { user, account, amount, currency } => {
+                write!(f, "user {} received {} test {} in account {}", user, amount, currency, account)
+            }
+            BankEvent::Converted { user, account, amount, from, to, credited, .. } => write!(
+                f,
+                "user {} converted {} {} into {} {} in account {}",
+                user, amount, from, credited, to, account
+            ),
+            BankEvent::Transferred { sender, account, receiver, receiver_account, amount, currency } => write!(
+                f,
+                "user {} sent {} {} from account {} to user {} account {}",
+                sender, amount, currency, account, receiver, receiver_account
+            ),
+            BankEvent::Borrowed { loan, borrower, lender, amount, currency, .. } => write!(
+                f,
+                "user {} borrowed {} {} from user {} as loan {}",
+                borrower, amount, currency, lender, loan
+            ),
+            BankEvent::Repaid { loan, borrower, lender, amount } => {
+                write!(f, "user {} repaid {} to user {} on loan {}", borrower, amount, lender, loan)
+            }
+            BankEvent::InterestApplied { user, currency, amount, .. } => {
+                write!(f, "user {} earned {} {} interest", user, amount, currency)
+            }
+            BankEvent::SalaryAdvanced { user, account, amount, currency } => {
+                write!(f, "user {} was advanced {} {} into account {}", user, amount, currency, account)
+            }
+            BankEvent::BoughtInInstallments { plan, payer, payee, price, currency, installments, .. } => write!(
+                f,
+                "user {} bought from user {} for {} {} in {} installments (plan {})",
+                payer, payee, price, currency, installments, plan
+            ),
+            BankEvent::InstallmentsCollected { user, amount } => {
+                write!(f, "user {} paid {} in due installments", user, amount)
+            }
+            BankEvent::OverdraftGranted { user, currency, limit, rate_bps } => write!(
+                f,
+                "user {} may overdraw {} {} at {} bps",
+                user, limit, currency, rate_bps
+            ),
+            BankEvent::OverdraftRevoked { user } => write!(f, "user {} lost their overdraft", user),
+            BankEvent::Retagged { user, transactions, tag, added } => write!(
+                f,
+                "user {} {} tag {} on {} transaction(s)",
+                user,
+                if *added { "added" } else { "removed" },
+                tag,
+                transactions.len()
+            ),
+            BankEvent::PayeeRegistered { account, payee } => write!(f, "user {} listed as payee {}", account, payee),
+            BankEvent::PayeeRemoved { account } => write!(f, "user {} removed from the payee directory", account),
+            BankEvent::PayeeCorrected { user, account, payee } => match payee {
+                Some(payee) => write!(f, "user {} calls user {} {}", user, account, payee),
+                None => write!(f, "user {} calls user {} by its directory name", user, account),
+            },
+            BankEvent::FeesSwept { currency, amount } => write!(f, "swept {} {} of fees", amount, currency),
+            BankEvent::PolicyProposed { change, policy, operator } => {
+                write!(f, "operator {} proposed policy change #{}: {}", operator, change, policy)
+            }
+            BankEvent::PolicyConfirmed { change, operator } => {
+                write!(f, "operator {} confirmed policy change #{}", operator, change)
+            }
+            BankEvent::PolicyCancelled { change, operator } => {
+                write!(f, "operator {} cancelled policy change #{}", operator, change)
+            }
+            BankEvent::EnvironmentChanged { environment } => write!(f, "bank became a {} bank", environment),
+            BankEvent::Reset => write!(f, "bank reset"),
+        }
+    }
+}
+
+/// The ids that the next new transaction, account, loan and installment plan
+/// were due to get.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NextIds {
+   pub transaction: u64,
+   pub account: AccountId,
+   pub loan: LoanId,
+   pub plan: PlanId,
+}
+
+impl NextIds {
+    pub fn current() -> Self {
+        NextIds {
+            transaction: ledger::next_id(),
+            account: account::next_id(),
+            loan: loan::next_id(),
+            plan: installment::next_id(),
+        }
+    }
+
+    /// Skip ahead to these ids, e.g. past ids a failed operation used up.
+    /// Ids already handed out cannot be taken back.
+    pub fn reserve(&self) {
+        ledger::reserve_ids_through(self.transaction.saturating_sub(1));
+        account::reserve_ids_through(AccountId::from(u32::from(self.account).saturating_sub(1)));
+        loan::reserve_ids_through(LoanId::from(u32::from(self.loan).saturating_sub(1)));
+        installment::reserve_ids_through(PlanId::from(u32::from(self.plan).saturating_sub(1)));
+    }
+}
+
+/// An event as an event log keeps it: with the time its operation ran at and
+/// the ids it allocated from, so replaying it reproduces the operation
+/// exactly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+   pub version: u32,
+   pub at: SystemTime,
+   pub ids: NextIds,
+   pub event: BankEvent,
+}
+
+/// Read a `RecordedEvent` from JSON written at `EVENT_VERSION` or before.
+pub fn decode(json: &str) -> Result<RecordedEvent, String> {
+    let invalid = |err: serde_json::Error| format!("Invalid event: {}", err);
+    let mut record: Value = serde_json::from_str(json).map_err(invalid)?;
+    let version = record
+        .get("version")
+        .and_then(Value::as_u64)
+        .and_then(|version| u32::try_from(version).ok())
+        .ok_or("Event without a version")?;
+    if version == 0 || version > EVENT_VERSION {
+        return Err(format!(
+            "Event has version {}, but only versions up to {} are supported",
+            version, EVENT_VERSION
+        ));
+    }
+    for from in version..EVENT_VERSION {
+        upcast(from, &mut record)?;
+    }
+    record["version"] = Value::from(EVENT_VERSION);
+    serde_json::from_value(record).map_err(invalid)
+}
+
+/// Rewrite a record from version `from` to the layout of `from + 1`.
+fn upcast(from: u32, record: &mut Value) -> Result<(), String> {
+    match from {
+        1 => {
+            if let Some(transfer) = record.pointer_mut("/event/Transferred").and_then(Value::as_object_mut) {
+                for (old, new) in [("from", "account"), ("to", "receiver_account")] {
+                    let account = transfer.remove(old).ok_or_else(|| format!("Transfer without `{}`", old))?;
+                    transfer.insert(new.to_string(), account);
+                }
+            }
+            Ok(())
+        }
+        _ => Err(format!("No upcast from event version {}", from)),
+    }
+}
+
+/// A callback registered with `Bank::on_event`.
+pub type Observer = Box<dyn Fn(&BankEvent) + Send + Sync>;
+
+/// The observers of one bank, called in the order they were registered.
+#[derive(Default)]
+pub struct Observers {
+    observers: Vec<Observer>,
+}
+
+impl Observers {
+    pub fn push(&mut self, observer: Observer) {
+        self.observers.push(observer);
+    }
+
+    pub fn emit(&self, event: &BankEvent) {
+        for observer in &self.observers {
+            observer(event);
+        }
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} observer(s)", self.observers.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDS: &str = r#"{"transaction":7,"account":3,"loan":1,"plan":1}"#;
+    const AT: &str = r#"{"secs_since_epoch":1700000000,"nanos_since_epoch":0}"#;
+
+    fn transfer() -> BankEvent {
+        BankEvent::Transferred {
+            sender: UserId::from(1),
+            account: AccountId::from(1),
+            receiver: UserId::from(2),
+            receiver_account: AccountId::from(2),
+            amount: Money::from_major(5),
+            currency: Currency::Usd,
+        }
+    }
+
+    #[test]
+    fn version_1_transfers_are_upcast() {
+        let json = format!(
+            r#"{{"version":1,"at":{},"ids":{},"event":{{"Transferred":{{"sender":1,"from":1,"receiver":2,"to":2,"amount":500,"currency":"Usd"}}}}}}"#,
+            AT, IDS
+        );
+        let record = decode(&json).unwrap();
+        assert_eq!(record.version, EVENT_VERSION);
+        assert_eq!(record.event, transfer());
+    }
+
+    #[test]
+    fn current_events_round_trip() {
+        let record = RecordedEvent {
+            version: EVENT_VERSION,
+            at: SystemTime::UNIX_EPOCH,
+            ids: serde_json::from_str(IDS).unwrap(),
+            event: transfer(),
+        };
+        assert_eq!(decode(&serde_json::to_string(&record).unwrap()).unwrap(), record);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let json = format!(r#"{{"version":{},"at":{},"ids":{},"event":"Reset"}}"#, EVENT_VERSION + 1, AT, IDS);
+        assert!(decode(&json).unwrap_err().contains("only versions up to"));
+    }
+}
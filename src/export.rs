@@ -0,0 +1,255 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::bank::{Bank, SCHEMA_VERSION};
+use crate::currency::{Balance, Currency};
+use crate::ledger::Transaction;
+use crate::store::Store;
+use crate::user::User;
+
+/// Everything `bank` holds, as JSON laid out the same way every time so two
+/// exports can be diffed line by line or checked into golden files: object
+/// keys are sorted, users are listed by id and each ledger is in the order
+/// it was written. The policies the treasury applies are gathered under
+/// `policies`.
+pub fn export<S: Store>(bank: &Bank<S>) -> Result<Value, String> {
+    let mut users: Vec<&User> = bank.users().collect();
+    users.sort_by_key(|user| user.id);
+    let treasury = &bank.treasury;
+    let mut policies = Map::new();
+    policies.insert(String::from("interest"), value(&treasury.interest)?);
+    policies.insert(String::from("fees"), value(&treasury.fees)?);
+    policies.insert(String::from("facility"), value(&treasury.facility)?);
+    policies.insert(String::from("changes"), value(&treasury.policy_changes)?);
+    let mut treasury_state = Map::new();
+    treasury_state.insert(String::from("balances"), value(&treasury.balances)?);
+    treasury_state.insert(String::from("fees_collected"), value(&treasury.fees_collected)?);
+    treasury_state.insert(String::from("payees"), value(&treasury.payees)?);
+    treasury_state.insert(String::from("transactions"), value(&treasury.transactions)?);
+
+    let mut state = Map::new();
+    state.insert(String::from("version"), Value::from(SCHEMA_VERSION));
+    state.insert(String::from("environment"), value(&bank.environment())?);
+    state.insert(String::from("policies"), Value::Object(policies));
+    state.insert(String::from("treasury"), Value::Object(treasury_state));
+    state.insert(String::from("users"), value(&users)?);
+    Ok(sorted(Value::Object(state)))
+}
+
+/// What changed from bank `a` to bank `b`, one line per difference: policies
+/// first, then the treasury, then each user's accounts, debts and ledger.
+/// Ledger entries are compared without their timestamps, so two runs of the
+/// same operations match.
+pub fn diff<A: Store, B: Store>(a: &Bank<A>, b: &Bank<B>) -> Vec<String> {
+    let mut lines = Vec::new();
+    changed(&mut lines, "environment", a.environment(), b.environment());
+    let (ta, tb) = (&a.treasury, &b.treasury);
+    changed(&mut lines, "interest", ta.interest, tb.interest);
+    changed(&mut lines, "fees", ta.fees, tb.fees);
+    changed(&mut lines, "facility currency", ta.facility.currency, tb.facility.currency);
+    changed(&mut lines, "facility limit", ta.facility.limit, tb.facility.limit);
+    changed(&mut lines, "facility rate bps", ta.facility.rate_bps, tb.facility.rate_bps);
+    changed(&mut lines, "facility drawn", ta.facility.drawn, tb.facility.drawn);
+    changed(&mut lines, "facility interest expense", ta.facility.interest_expense, tb.facility.interest_expense);
+    if ta.policy_changes != tb.policy_changes {
+        lines.push(String::from("policy change history differs"));
+    }
+
+    balances(&mut lines, "treasury", &ta.balances, &tb.balances);
+    let currencies: BTreeSet<Currency> = ta.fees_collected.keys().chain(tb.fees_collected.keys()).copied().collect();
+    for currency in currencies {
+        let label = format!("treasury {} fees collected", currency);
+        changed(&mut lines, label, ta.fee_revenue(currency), tb.fee_revenue(currency));
+    }
+    if ta.payees != tb.payees {
+        lines.push(String::from("treasury payee directory differs"));
+    }
+    ledger(&mut lines, "treasury", &ta.transactions, &tb.transactions);
+
+    let users_a: BTreeMap<_, &User> = a.users().map(|user| (user.id, user)).collect();
+    let users_b: BTreeMap<_, &User> = b.users().map(|user| (user.id, user)).collect();
+    let ids: BTreeSet<_> = users_a.keys().chain(users_b.keys()).copied().collect();
+    for id in ids {
+        match (users_a.get(&id), users_b.get(&id)) {
+            (Some(user), None) => lines.push(format!("- user {} {}", user.id, user.name)),
+            (None, Some(user)) => lines.push(format!("+ user {} {}", user.id, user.name)),
+            (Some(ua), Some(ub)) => user(&mut lines, ua, ub),
+            (None, None) => {}
+        }
+    }
+    lines
+}
+
+fn user(lines: &mut Vec<String>, a: &User, b: &User) {
+    let who = format!("{} {}", b.id, b.name);
+    changed(lines, format!("{} name", a.id), &a.name, &b.name);
+    let accounts: BTreeSet<_> = a.accounts.iter().chain(&b.accounts).map(|account| account.id).collect();
+    for id in accounts {
+        match (a.account(id).ok(), b.account(id).ok()) {
+            (Some(account), None) => lines.push(format!("- {} account {} ({})", who, id, account.kind)),
+            (None, Some(account)) => lines.push(format!("+ {} account {} ({})", who, id, account.kind)),
+            (Some(aa), Some(ab)) => {
+                let label = format!("{} {}", who, id);
+                changed(lines, format!("{} kind", label), aa.kind, ab.kind);
+                changed(lines, format!("{} borrowable", label), aa.borrowable, ab.borrowable);
+                balances(lines, &label, &aa.balances, &ab.balances);
+            }
+            (None, None) => {}
+        }
+    }
+
+    let debts_a: BTreeMap<_, _> = a.debts().map(|loan| (loan.id, loan)).collect();
+    let debts_b: BTreeMap<_, _> = b.debts().map(|loan| (loan.id, loan)).collect();
+    let loans: BTreeSet<_> = debts_a.keys().chain(debts_b.keys()).copied().collect();
+    for id in loans {
+        match (debts_a.get(&id), debts_b.get(&id)) {
+            (Some(loan), None) => lines.push(format!("- {} loan {} from {}", who, id, loan.lender)),
+            (None, Some(loan)) => lines.push(format!(
+                "+ {} loan {} from {}: {} {}",
+                who, id, loan.lender, loan.remaining, loan.currency
+            )),
+            (Some(la), Some(lb)) => {
+                changed(lines, format!("{} loan {} remaining", who, id), la.remaining, lb.remaining);
+                changed(lines, format!("{} loan {} interest", who, id), la.accrued_interest, lb.accrued_interest);
+            }
+            (None, None) => {}
+        }
+    }
+
+    changed(lines, format!("{} overdraft", who), described(&a.overdraft), described(&b.overdraft));
+    changed(lines, format!("{} salary advance", who), described(&a.salary_advance), described(&b.salary_advance));
+    let plans = |user: &User| -> Vec<String> { user.installment_plans.iter().map(ToString::to_string).collect() };
+    if plans(a) != plans(b) {
+        lines.push(format!("{} installments: {} -> {}", who, plans(a).join("; "), plans(b).join("; ")));
+    }
+    ledger(lines, &who, &a.transactions, &b.transactions);
+}
+
+/// Deposited and withdrawn totals that differ, per currency.
+fn balances(lines: &mut Vec<String>, label: &str, a: &HashMap<Currency, Balance>, b: &HashMap<Currency, Balance>) {
+    let currencies: BTreeSet<Currency> = a.keys().chain(b.keys()).copied().collect();
+    for currency in currencies {
+        let (ba, bb) = (
+            a.get(&currency).copied().unwrap_or_default(),
+            b.get(&currency).copied().unwrap_or_default(),
+        );
+        changed(lines, format!("{} {} deposited", label, currency), ba.deposited, bb.deposited);
+        changed(lines, format!("{} {} withdrawn", label, currency), ba.withdrawn, bb.withdrawn);
+    }
+}
+
+/// The ledger entries after the point where `a` and `b` part ways.
+fn ledger(lines: &mut Vec<String>, label: &str, a: &[Transaction], b: &[Transaction]) {
+    let common = a.iter().zip(b).take_while(|(ta, tb)| same_entry(ta, tb)).count();
+    for transaction in &a[common..] {
+        lines.push(format!("- {} {}", label, transaction));
+    }
+    for transaction in &b[common..] {
+        lines.push(format!("+ {} {}", label, transaction));
+    }
+}
+
+fn same_entry(a: &Transaction, b: &Transaction) -> bool {
+    (a.id, a.kind, a.amount, a.currency, a.fee, a.counterparty, &a.tags)
+        == (b.id, b.kind, b.amount, b.currency, b.fee, b.counterparty, &b.tags)
+}
+
+fn changed<T: PartialEq + fmt::Display>(lines: &mut Vec<String>, what: impl fmt::Display, a: T, b: T) {
+    if a != b {
+        lines.push(format!("{}: {} -> {}", what, a, b));
+    }
+}
+
+fn described<T: fmt::Display>(item: &Option<T>) -> String {
+    item.as_ref().map_or_else(|| String::from("none"), ToString::to_string)
+}
+
+fn value(item: &impl Serialize) -> Result<Value, String> {
+    serde_json::to_value(item).map_err(|err| format!("Cannot serialize bank state: {}", err))
+}
+
+/// `value` with the keys of every object in sorted order.
+fn sorted(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let entries: BTreeMap<String, Value> = map.into_iter().map(|(key, value)| (key, sorted(value))).collect();
+            Value::Object(entries.into_iter().collect())
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(sorted).collect()),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{diff, export};
+    use crate::bank::Bank;
+    use crate::currency::Currency;
+    use crate::fees::FeeSchedule;
+    use crate::money::Money;
+    use crate::policy::Policy;
+
+    fn bank() -> Bank {
+        let mut bank = Bank::new();
+        let (a, b) = (bank.open_account("Ada").unwrap(), bank.open_account("Bob").unwrap());
+        bank.deposit(a, Money::from_major(1_000), Currency::Usd, true).unwrap();
+        bank.deposit(b, Money::from_major(100), Currency::Usd, false).unwrap();
+        bank
+    }
+
+    /// A copy of `bank` made by saving and loading it.
+    fn copy(bank: &Bank) -> Bank {
+        let path = std::env::temp_dir().join(format!("banking-{}-export.json", std::process::id()));
+        bank.save_json(&path).unwrap();
+        let copy = Bank::load_json(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        copy
+    }
+
+    #[test]
+    fn exports_have_sorted_keys_and_users() {
+        let mut bank = bank();
+        let state = export(&bank).unwrap();
+        let keys: Vec<&String> = state.as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["environment", "policies", "treasury", "users", "version"]);
+        let users = state["users"].as_array().unwrap();
+        let ids: Vec<u64> = users.iter().map(|user| user["id"].as_u64().unwrap()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", ids);
+        assert_eq!(export(&copy(&bank)).unwrap().to_string(), state.to_string());
+        bank.open_account("Cy").unwrap();
+        assert_ne!(export(&bank).unwrap(), state);
+    }
+
+    #[test]
+    fn identical_banks_have_no_differences() {
+        let bank = bank();
+        assert_eq!(diff(&bank, &copy(&bank)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn differences_cover_balances_policies_users_and_ledgers() {
+        let before = bank();
+        let mut after = copy(&before);
+        let ada = after.users().find(|user| user.name == "Ada").unwrap().id;
+        after.withdraw(ada, Money::from_major(10), Currency::Usd).unwrap();
+        let cy = after.open_account("Cy").unwrap();
+        let fees = FeeSchedule { entry_bps: 100, ..FeeSchedule::default() };
+        let change = after.propose_policy(Policy::Fees(fees), "alice").unwrap();
+        after.confirm_policy(change, "bob").unwrap();
+
+        let lines = diff(&before, &after);
+        let has = |needle: &str| lines.iter().any(|line| line.contains(needle));
+        assert!(has("fees: "), "{:#?}", lines);
+        assert!(has("policy change history differs"), "{:#?}", lines);
+        let account = after.primary_account(ada).unwrap();
+        assert!(has(&format!("Ada {} USD withdrawn: 0.00 -> 10.40", account)), "{:#?}", lines);
+        assert!(lines.contains(&format!("+ user {} Cy", cy)), "{:#?}", lines);
+        assert!(lines.iter().any(|line| line.starts_with(&format!("+ {} Ada ", ada))), "{:#?}", lines);
+        assert!(diff(&after, &before).contains(&format!("- user {} Cy", cy)));
+    }
+}
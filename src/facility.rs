@@ -0,0 +1,105 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::money::Money;
+use crate::time;
+
+/// An external credit line the Treasury can draw on to cover shortfalls in
+/// its `currency` reserves, charged at a policy rate of `rate_bps` a year.
+/// Interest is added to `drawn`, and counted in `interest_expense`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiquidityFacility {
+   pub currency: Currency,
+   pub limit: Money,
+   pub rate_bps: u32,
+   pub drawn: Money,
+   pub interest_expense: Money,
+   /// When interest on `drawn` was last charged; `None` while it is zero.
+   pub interest_since: Option<SystemTime>,
+}
+
+impl Default for LiquidityFacility {
+    fn default() -> Self {
+        LiquidityFacility::new(Currency::Usd, Money::ZERO, 0)
+    }
+}
+
+impl LiquidityFacility {
+    pub fn new(currency: Currency, limit: Money, rate_bps: u32) -> Self {
+        LiquidityFacility {
+            currency,
+            limit,
+            rate_bps,
+            drawn: Money::ZERO,
+            interest_expense: Money::ZERO,
+            interest_since: None,
+        }
+    }
+
+    /// Funds still available under the limit.
+    pub fn headroom(&self) -> Money {
+        self.limit.checked_sub(self.drawn).unwrap_or(Money::ZERO)
+    }
+
+    /// Borrow `amount` from the facility. Returns the total drawn.
+    pub fn draw(&mut self, amount: Money) -> Result<Money, String> {
+        if amount > self.headroom() {
+            return Err(format!(
+                "Facility limit exceeded. Available: {}",
+                self.headroom()
+            ));
+        }
+        self.drawn = self
+            .drawn
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow")?;
+        Ok(self.drawn)
+    }
+
+    /// Repay up to `amount` of the drawn balance. Returns the amount repaid.
+    pub fn repay(&mut self, amount: Money) -> Money {
+        let repaid = amount.min(self.drawn);
+        self.drawn = self.drawn.checked_sub(repaid).unwrap_or(Money::ZERO);
+        repaid
+    }
+
+    /// Interest owed on `drawn` from `interest_since` up to `until`.
+    pub fn interest_due(&self, until: SystemTime) -> Money {
+        let Some(since) = self.interest_since else {
+            return Money::ZERO;
+        };
+        let elapsed = until.duration_since(since).unwrap_or_default();
+        Money::from_minor(time::prorate(self.drawn.mul_bps(self.rate_bps).minor(), elapsed))
+    }
+
+    /// Add the interest due up to `until` to the drawn balance and the
+    /// interest expense, and restart the interest clock. Returns the interest
+    /// charged.
+    pub fn accrue_interest(&mut self, until: SystemTime) -> Result<Money, String> {
+        let interest = self.interest_due(until);
+        self.drawn = self
+            .drawn
+            .checked_add(interest)
+            .ok_or("Arithmetic overflow when accruing facility interest")?;
+        self.interest_expense = self
+            .interest_expense
+            .checked_add(interest)
+            .ok_or("Arithmetic overflow when accruing facility interest")?;
+        self.interest_since = (self.drawn > Money::ZERO).then_some(until);
+        Ok(interest)
+    }
+}
+
+impl fmt::Display for LiquidityFacility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "facility {}: drawn {} of {} at {} bps, interest expense {}",
+            self.currency, self.drawn, self.limit, self.rate_bps, self.interest_expense
+        )
+    }
+}
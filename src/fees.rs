@@ -0,0 +1,153 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+
+/// Fees charged on deposits and withdrawals: a percentage in basis points,
+/// raised to a flat minimum and then limited by an optional cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeeSchedule {
+   pub entry_bps: u32,
+   pub exit_bps: u32,
+   pub min_entry: Money,
+   pub min_exit: Money,
+   pub entry_cap: Option<Money>,
+   pub exit_cap: Option<Money>,
+}
+
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        FeeSchedule {
+            entry_bps: 200, // 2%
+            exit_bps: 400,  // 4%
+            min_entry: Money::ZERO,
+            min_exit: Money::ZERO,
+            entry_cap: None,
+            exit_cap: None,
+        }
+    }
+}
+
+impl FeeSchedule {
+    /// Fee charged on a deposit of `amount`.
+    pub fn entry_fee(&self, amount: Money) -> Money {
+        Self::fee(amount, self.entry_bps, self.min_entry, self.entry_cap)
+    }
+
+    /// Fee charged on top of a withdrawal of `amount`.
+    pub fn exit_fee(&self, amount: Money) -> Money {
+        Self::fee(amount, self.exit_bps, self.min_exit, self.exit_cap)
+    }
+
+    fn fee(amount: Money, bps: u32, minimum: Money, cap: Option<Money>) -> Money {
+        let fee = amount.mul_bps(bps).max(minimum);
+        cap.map_or(fee, |cap| fee.min(cap))
+    }
+}
+
+/// Fees the treasury has taken in one currency and not yet swept into its
+/// main pool, by fee type.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeesCollected {
+   pub entry: Money,
+   pub exit: Money,
+   /// Flat fees on salary advances.
+   pub advance: Money,
+   /// Merchant fees on installment purchases, as installments recover them.
+   pub merchant: Money,
+}
+
+impl FeesCollected {
+    pub fn total(&self) -> Money {
+        self.entry
+            .checked_add(self.exit)
+            .and_then(|total| total.checked_add(self.advance))
+            .and_then(|total| total.checked_add(self.merchant))
+            .unwrap_or(Money::from_minor(u64::MAX))
+    }
+}
+
+impl fmt::Display for FeeSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "entry {} bps", self.entry_bps)?;
+        write_limits(f, self.min_entry, self.entry_cap)?;
+        write!(f, ", exit {} bps", self.exit_bps)?;
+        write_limits(f, self.min_exit, self.exit_cap)
+    }
+}
+
+fn write_limits(f: &mut fmt::Formatter<'_>, minimum: Money, cap: Option<Money>) -> fmt::Result {
+    if minimum > Money::ZERO {
+        write!(f, " min {}", minimum)?;
+    }
+    if let Some(cap) = cap {
+        write!(f, " cap {}", cap)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(bps: u32, minimum: Money, cap: Option<Money>) -> FeeSchedule {
+        FeeSchedule {
+            entry_bps: bps,
+            exit_bps: bps,
+            min_entry: minimum,
+            min_exit: minimum,
+            entry_cap: cap,
+            exit_cap: cap,
+        }
+    }
+
+    #[test]
+    fn default_fees_are_two_and_four_percent() {
+        let fees = FeeSchedule::default();
+        assert_eq!(fees.entry_fee(Money::from_major(100)), Money::from_major(2));
+        assert_eq!(fees.exit_fee(Money::from_major(100)), Money::from_major(4));
+        assert_eq!(fees.exit_fee(Money::from_minor(24)), Money::ZERO);
+    }
+
+    #[test]
+    fn small_fees_are_raised_to_the_minimum() {
+        let fees = schedule(100, Money::from_minor(50), None);
+        assert_eq!(fees.entry_fee(Money::from_major(10)), Money::from_minor(50));
+        assert_eq!(fees.entry_fee(Money::from_major(100)), Money::from_major(1));
+        assert_eq!(fees.exit_fee(Money::ZERO), Money::from_minor(50));
+    }
+
+    #[test]
+    fn large_fees_are_limited_by_the_cap() {
+        let fees = schedule(100, Money::ZERO, Some(Money::from_major(5)));
+        assert_eq!(fees.exit_fee(Money::from_major(100)), Money::from_major(1));
+        assert_eq!(fees.exit_fee(Money::from_major(10_000)), Money::from_major(5));
+    }
+
+    #[test]
+    fn the_cap_wins_over_the_minimum() {
+        let fees = schedule(100, Money::from_major(2), Some(Money::from_major(1)));
+        assert_eq!(fees.entry_fee(Money::from_major(1)), Money::from_major(1));
+    }
+
+    #[test]
+    fn limits_appear_in_the_description() {
+        let fees = schedule(100, Money::from_minor(50), Some(Money::from_major(5)));
+        assert_eq!(fees.to_string(), "entry 100 bps min 0.50 cap 5.00, exit 100 bps min 0.50 cap 5.00");
+        assert_eq!(FeeSchedule::default().to_string(), "entry 200 bps, exit 400 bps");
+    }
+
+    #[test]
+    fn collected_fees_add_up_by_type() {
+        let collected = FeesCollected {
+            entry: Money::from_minor(1),
+            exit: Money::from_minor(2),
+            advance: Money::from_minor(3),
+            merchant: Money::from_minor(4),
+        };
+        assert_eq!(collected.total(), Money::from_minor(10));
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use crate::currency::Currency;
+use crate::ledger::{Transaction, TransactionKind};
+use crate::money::Money;
+use crate::types::UserId;
+
+/// Credits seen from one source before they count as recurring income.
+pub const MIN_PAYMENTS: usize = 3;
+
+/// How far amounts and gaps between payments may stray from the pattern, in
+/// basis points.
+const TOLERANCE_BPS: u32 = 2_000; // 20%
+
+/// Shortest pay cycle considered; anything more frequent is not salary-like.
+const MIN_INTERVAL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// A salary-like stream of credits: deposits, or transfers in from the same
+/// `source`, of a similar amount arriving at a regular `interval`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecurringIncome {
+   /// The paying user, or `None` for deposits.
+   pub source: Option<UserId>,
+   pub currency: Currency,
+   /// The smallest of the recent payments.
+   pub amount: Money,
+   pub interval: Duration,
+   pub last: SystemTime,
+}
+
+impl RecurringIncome {
+    /// When the next payment is due.
+    pub fn expected_next(&self) -> SystemTime {
+        self.last + self.interval
+    }
+
+    /// Whether the next payment is later than the pattern allows at `now`.
+    pub fn is_overdue(&self, now: SystemTime) -> bool {
+        now > self.expected_next() + self.slack()
+    }
+
+    /// Whether `transaction`, arriving at `now`, looks like the next payment.
+    /// Payments may arrive up to `TOLERANCE_BPS` of an interval early.
+    pub fn matches(&self, transaction: &Transaction, now: SystemTime) -> bool {
+        source_of(transaction) == Some(self.source)
+            && transaction.currency == self.currency
+            && transaction.amount >= self.amount.checked_sub(self.amount.mul_bps(TOLERANCE_BPS)).unwrap_or(Money::ZERO)
+            && now + self.slack() >= self.expected_next()
+    }
+
+    fn slack(&self) -> Duration {
+        self.interval.mul_f64(f64::from(TOLERANCE_BPS) / 10_000.0)
+    }
+}
+
+/// The most recent recurring income in `owner`'s `transactions`, if any: the
+/// source whose last `MIN_PAYMENTS` credits have similar amounts and evenly
+/// spaced timestamps.
+pub fn detect(owner: UserId, transactions: &[Transaction]) -> Option<RecurringIncome> {
+    let mut streams: BTreeMap<(Option<UserId>, Currency), Vec<&Transaction>> = BTreeMap::new();
+    for transaction in transactions {
+        // Moving money between one's own accounts is not income.
+        if let Some(source) = source_of(transaction).filter(|source| *source != Some(owner)) {
+            streams.entry((source, transaction.currency)).or_default().push(transaction);
+        }
+    }
+    streams
+        .into_iter()
+        .filter_map(|((source, currency), credits)| recurring(source, currency, &credits))
+        .max_by_key(|income| income.last)
+}
+
+/// Who a salary-like credit comes from: `Some(None)` for a deposit,
+/// `Some(Some(payer))` for a transfer from another user, `None` otherwise.
+fn source_of(transaction: &Transaction) -> Option<Option<UserId>> {
+    match transaction.kind {
+        TransactionKind::Deposit => Some(None),
+        TransactionKind::TransferIn => Some(transaction.counterparty),
+        _ => None,
+    }
+}
+
+fn recurring(source: Option<UserId>, currency: Currency, credits: &[&Transaction]) -> Option<RecurringIncome> {
+    let recent = credits.get(credits.len().checked_sub(MIN_PAYMENTS)?..)?;
+    let (first, last) = (recent.first()?.timestamp, recent.last()?.timestamp);
+    let interval = last.duration_since(first).ok()? / (MIN_PAYMENTS as u32 - 1);
+    if interval < MIN_INTERVAL {
+        return None;
+    }
+    let slack = interval.mul_f64(f64::from(TOLERANCE_BPS) / 10_000.0);
+    let evenly_spaced = recent.windows(2).all(|pair| {
+        let gap = pair[1].timestamp.duration_since(pair[0].timestamp).unwrap_or_default();
+        gap + slack >= interval && gap <= interval + slack
+    });
+    let amount = recent.iter().map(|credit| credit.amount).min()?;
+    let largest = recent.iter().map(|credit| credit.amount).max()?;
+    let similar = largest.checked_sub(amount)? <= largest.mul_bps(TOLERANCE_BPS);
+    (evenly_spaced && similar).then_some(RecurringIncome {
+        source,
+        currency,
+        amount,
+        interval,
+        last,
+    })
+}
@@ -0,0 +1,179 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::money::Money;
+use crate::types::{AccountId, PlanId, UserId};
+
+/// Most installments a purchase can be split into.
+pub const MAX_INSTALLMENTS: u32 = 12;
+
+/// Share of the price the treasury keeps from the payee, in basis points.
+pub const MERCHANT_FEE_BPS: u32 = 300; // 3%
+
+/// Time between installments; the first is due at purchase.
+pub const INSTALLMENT_INTERVAL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+static NEXT_PLAN_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Make sure new plans get ids above `id`, e.g. after loading existing plans
+/// from disk.
+pub fn reserve_ids_through(id: PlanId) {
+    NEXT_PLAN_ID.fetch_max(u32::from(id) + 1, Ordering::Relaxed);
+}
+
+/// The id the next new plan will get.
+pub fn next_id() -> PlanId {
+    PlanId::from(NEXT_PLAN_ID.load(Ordering::Relaxed))
+}
+
+/// A purchase from `payee` that `payer` pays back to the treasury in equal,
+/// interest-free installments. The treasury paid the payee up front, keeping
+/// the merchant `fee`, which the installments recover before the rest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallmentPlan {
+   pub id: PlanId,
+   pub payer: UserId,
+   pub payee: UserId,
+   /// The payer's account installments are collected from.
+   pub account: AccountId,
+   pub currency: Currency,
+   pub price: Money,
+   pub installments: u32,
+   pub paid: u32,
+   /// Part of the price still owed.
+   pub remaining: Money,
+   /// Part of the merchant fee still to be recovered.
+   pub fee: Money,
+   pub start: SystemTime,
+}
+
+impl InstallmentPlan {
+    /// Split `price` into `installments`, the first due at `start`. The
+    /// merchant fee is `MERCHANT_FEE_BPS` of the price.
+    pub fn new(
+        payer: UserId,
+        payee: UserId,
+        account: AccountId,
+        price: Money,
+        currency: Currency,
+        installments: u32,
+        start: SystemTime,
+    ) -> Self {
+        InstallmentPlan {
+            id: PlanId::from(NEXT_PLAN_ID.fetch_add(1, Ordering::Relaxed)),
+            payer,
+            payee,
+            account,
+            currency,
+            price,
+            installments,
+            paid: 0,
+            remaining: price,
+            fee: price.mul_bps(MERCHANT_FEE_BPS),
+            start,
+        }
+    }
+
+    /// The next installment: an equal share of the price, with the last one
+    /// taking the rounding remainder.
+    pub fn next_installment(&self) -> Money {
+        if self.paid + 1 >= self.installments {
+            return self.remaining;
+        }
+        Money::from_minor(self.price.minor() / u64::from(self.installments)).min(self.remaining)
+    }
+
+    /// How many installments have fallen due by `now`.
+    pub fn due(&self, now: SystemTime) -> u32 {
+        let elapsed = now.duration_since(self.start).unwrap_or_default();
+        let periods = elapsed.as_secs() / INSTALLMENT_INTERVAL.as_secs();
+        u32::try_from(periods).unwrap_or(u32::MAX).saturating_add(1).min(self.installments)
+    }
+
+    /// Installments that are due by `now` but not yet paid.
+    pub fn overdue(&self, now: SystemTime) -> u32 {
+        self.due(now).saturating_sub(self.paid)
+    }
+
+    /// Count the next installment as paid. Returns the parts of it that went
+    /// to the merchant fee and to the rest of the price.
+    pub fn pay_installment(&mut self) -> (Money, Money) {
+        let amount = self.next_installment();
+        let fee = amount.min(self.fee);
+        self.fee = self.fee.checked_sub(fee).unwrap_or(Money::ZERO);
+        self.remaining = self.remaining.checked_sub(amount).unwrap_or(Money::ZERO);
+        self.paid += 1;
+        (fee, amount.checked_sub(fee).unwrap_or(Money::ZERO))
+    }
+
+    /// The price still owed apart from the merchant fee.
+    pub fn financed(&self) -> Money {
+        self.remaining.checked_sub(self.fee).unwrap_or(Money::ZERO)
+    }
+
+    pub fn is_paid_off(&self) -> bool {
+        self.remaining == Money::ZERO
+    }
+}
+
+impl fmt::Display for InstallmentPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "installments {}: {} pays {} {} of {} {}, {} of {} paid",
+            self.id, self.payer, self.payee, self.remaining, self.price, self.currency, self.paid, self.installments
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::{InstallmentPlan, INSTALLMENT_INTERVAL};
+    use crate::currency::Currency;
+    use crate::money::Money;
+    use crate::types::{AccountId, UserId};
+
+    fn plan(price: u64, installments: u32) -> InstallmentPlan {
+        let (payer, payee) = (UserId::from(1), UserId::from(2));
+        let price = Money::from_minor(price);
+        let start = SystemTime::UNIX_EPOCH;
+        InstallmentPlan::new(payer, payee, AccountId::from(1), price, Currency::Usd, installments, start)
+    }
+
+    #[test]
+    fn the_last_installment_takes_the_rounding_remainder() {
+        let mut plan = plan(10_000, 3);
+        assert_eq!(plan.fee, Money::from_minor(300));
+        for expected in [3_333, 3_333, 3_334] {
+            assert_eq!(plan.next_installment(), Money::from_minor(expected));
+            plan.pay_installment();
+        }
+        assert!(plan.is_paid_off());
+        assert_eq!(plan.fee, Money::ZERO);
+    }
+
+    #[test]
+    fn installments_recover_the_merchant_fee_first() {
+        let mut plan = plan(10_000, 50);
+        assert_eq!(plan.pay_installment(), (Money::from_minor(200), Money::ZERO));
+        assert_eq!(plan.pay_installment(), (Money::from_minor(100), Money::from_minor(100)));
+        assert_eq!(plan.financed(), Money::from_minor(9_600));
+    }
+
+    #[test]
+    fn installments_fall_due_each_interval() {
+        let mut plan = plan(10_000, 3);
+        let start = SystemTime::UNIX_EPOCH;
+        assert_eq!(plan.overdue(start), 1);
+        plan.pay_installment();
+        assert_eq!(plan.overdue(start + INSTALLMENT_INTERVAL / 2), 0);
+        assert_eq!(plan.overdue(start + INSTALLMENT_INTERVAL), 1);
+        assert_eq!(plan.due(start + INSTALLMENT_INTERVAL * 10), 3);
+    }
+}
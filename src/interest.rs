@@ -0,0 +1,217 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::money::{MAX_BPS, Money};
+use crate::time::{self, SECONDS_PER_YEAR};
+
+/// Fixed-point scale used while compounding, so that per-period interest
+/// smaller than a cent still adds up.
+const SCALE: u128 = 1_000_000_000;
+
+/// How often deposit interest is added to the balance it is earned on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compounding {
+    Daily,
+    #[default]
+    Monthly,
+    Annual,
+}
+
+impl Compounding {
+    pub fn periods_per_year(self) -> u32 {
+        match self {
+            Compounding::Daily => 365,
+            Compounding::Monthly => 12,
+            Compounding::Annual => 1,
+        }
+    }
+
+    /// Length of one compounding period; every period is the same length.
+    pub fn period(self) -> Duration {
+        Duration::from_secs(SECONDS_PER_YEAR / u64::from(self.periods_per_year()))
+    }
+}
+
+impl fmt::Display for Compounding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compounding::Daily => write!(f, "daily"),
+            Compounding::Monthly => write!(f, "monthly"),
+            Compounding::Annual => write!(f, "annual"),
+        }
+    }
+}
+
+impl FromStr for Compounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "daily" => Ok(Compounding::Daily),
+            "monthly" => Ok(Compounding::Monthly),
+            "annual" => Ok(Compounding::Annual),
+            _ => Err(format!("Unknown compounding '{}'; expected daily, monthly or annual", s)),
+        }
+    }
+}
+
+/// Deposit interest of `rate_bps` a year, compounded `compounding`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterestSchedule {
+   pub rate_bps: u32,
+   pub compounding: Compounding,
+}
+
+impl Default for InterestSchedule {
+    fn default() -> Self {
+        InterestSchedule {
+            rate_bps: 200, // 2%
+            compounding: Compounding::default(),
+        }
+    }
+}
+
+impl InterestSchedule {
+    /// Interest earned on `principal` over the whole compounding periods
+    /// between `from` and `until`, along with the end of the last whole period.
+    /// Accrual should resume from that point so a partial period is not lost.
+    pub fn accrue(&self, principal: Money, from: SystemTime, until: SystemTime) -> (Money, SystemTime) {
+        let period = self.compounding.period();
+        let elapsed = until.duration_since(from).unwrap_or_default();
+        let periods = u32::try_from(elapsed.as_secs() / period.as_secs()).unwrap_or(u32::MAX);
+        let divisor = u128::from(MAX_BPS) * u128::from(self.compounding.periods_per_year());
+
+        let start = u128::from(principal.minor()) * SCALE;
+        let mut value = start;
+        for _ in 0..periods {
+            value = value.saturating_add(value.saturating_mul(u128::from(self.rate_bps)) / divisor);
+        }
+        let interest = u64::try_from((value - start) / SCALE).unwrap_or(u64::MAX);
+        (Money::from_minor(interest), from + period * periods)
+    }
+
+    /// Interest earned on `principal` from `from` right up to `until`: the
+    /// whole periods as in `accrue`, then the part of a period left over at
+    /// the yearly rate, prorated. Used to settle a balance before it changes.
+    pub fn settle(&self, principal: Money, from: SystemTime, until: SystemTime) -> Money {
+        let (interest, through) = self.accrue(principal, from, until);
+        let value = principal.minor().saturating_add(interest.minor());
+        let yearly = u128::from(value) * u128::from(self.rate_bps) / u128::from(MAX_BPS);
+        let remainder = time::prorate(
+            u64::try_from(yearly).unwrap_or(u64::MAX),
+            until.duration_since(through).unwrap_or_default(),
+        );
+        Money::from_minor(interest.minor().saturating_add(remainder))
+    }
+}
+
+/// How `Treasury` works out deposit interest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InterestStrategy {
+    Compound(InterestSchedule),
+    /// The original `Treasury::calculate_interest_rate` formula, taken as a
+    /// yearly amount and prorated over the time elapsed.
+    Legacy,
+}
+
+impl Default for InterestStrategy {
+    fn default() -> Self {
+        InterestStrategy::Compound(InterestSchedule::default())
+    }
+}
+
+impl fmt::Display for InterestStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterestStrategy::Compound(schedule) => {
+                write!(f, "{} bps compounded {}", schedule.rate_bps, schedule.compounding)
+            }
+            InterestStrategy::Legacy => write!(f, "legacy formula"),
+        }
+    }
+}
+
+/// Interest a user is expected to earn on deposits and pay on loans and
+/// overdrafts in one currency over the next `period`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterestForecast {
+   pub currency: Currency,
+   pub period: Duration,
+   pub earned: Money,
+   pub cost: Money,
+}
+
+impl fmt::Display for InterestForecast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "forecast {} over the next {} day(s): earns {} interest, pays {} interest",
+            self.currency,
+            self.period.as_secs() / (24 * 60 * 60),
+            self.earned,
+            self.cost
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YEAR: Duration = Duration::from_secs(SECONDS_PER_YEAR);
+
+    fn schedule(rate_bps: u32, compounding: Compounding) -> InterestSchedule {
+        InterestSchedule { rate_bps, compounding }
+    }
+
+    #[test]
+    fn interest_compounds_each_period() {
+        let start = SystemTime::UNIX_EPOCH;
+        let principal = Money::from_major(100);
+        let annual = schedule(1_000, Compounding::Annual);
+        assert_eq!(annual.accrue(principal, start, start + YEAR), (Money::from_major(10), start + YEAR));
+        let monthly = schedule(1_200, Compounding::Monthly);
+        assert_eq!(monthly.accrue(principal, start, start + YEAR).0, Money::from_minor(1268));
+    }
+
+    #[test]
+    fn accrual_stops_at_the_last_whole_period() {
+        let start = SystemTime::UNIX_EPOCH;
+        let monthly = schedule(1_200, Compounding::Monthly);
+        let period = Compounding::Monthly.period();
+        let (interest, through) = monthly.accrue(Money::from_major(100), start, start + period + period / 2);
+        assert_eq!((interest, through), (Money::from_major(1), start + period));
+        let (interest, through) = monthly.accrue(Money::from_major(100), start, start + period / 2);
+        assert_eq!((interest, through), (Money::ZERO, start));
+    }
+
+    #[test]
+    fn nothing_accrues_backwards_or_on_nothing() {
+        let start = SystemTime::UNIX_EPOCH + YEAR;
+        let annual = schedule(1_000, Compounding::Annual);
+        assert_eq!(annual.accrue(Money::from_major(100), start, start - YEAR), (Money::ZERO, start));
+        assert_eq!(annual.accrue(Money::ZERO, start, start + YEAR).0, Money::ZERO);
+        assert_eq!(annual.settle(Money::from_major(100), start, start - YEAR), Money::ZERO);
+    }
+
+    #[test]
+    fn settling_prorates_the_partial_period() {
+        let start = SystemTime::UNIX_EPOCH;
+        let annual = schedule(1_000, Compounding::Annual);
+        assert_eq!(annual.settle(Money::from_major(100), start, start + YEAR / 2), Money::from_major(5));
+        let settled = annual.settle(Money::from_major(100), start, start + YEAR + YEAR / 2);
+        assert_eq!(settled, Money::from_minor(1550));
+    }
+
+    #[test]
+    fn compounding_parses_case_insensitively() {
+        assert_eq!("Daily".parse(), Ok(Compounding::Daily));
+        assert_eq!("ANNUAL".parse(), Ok(Compounding::Annual));
+        assert!("weekly".parse::<Compounding>().is_err());
+        assert_eq!(Compounding::Daily.period() * 365, YEAR);
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::money::Money;
+use crate::types::UserId;
+
+static NEXT_TRANSACTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Make sure new transactions get ids above `id`, e.g. after loading an
+/// existing ledger from disk.
+pub fn reserve_ids_through(id: u64) {
+    NEXT_TRANSACTION_ID.fetch_max(id + 1, Ordering::Relaxed);
+}
+
+/// The id the next new transaction will get.
+pub fn next_id() -> u64 {
+    NEXT_TRANSACTION_ID.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionKind {
+    Deposit,
+    Withdrawal,
+    Borrow,
+    Lend,
+    Interest,
+    ExchangeOut,
+    ExchangeIn,
+    Faucet,
+    TransferOut,
+    TransferIn,
+    RepaymentOut,
+    RepaymentIn,
+    FeeSweep,
+    OverdraftInterest,
+    SalaryAdvance,
+    AdvanceRepayment,
+    InstallmentPurchase,
+    InstallmentSale,
+    Installment,
+    FacilityDraw,
+    FacilityRepayment,
+    FacilityInterest,
+}
+
+/// A single recorded operation.
+/// `amount` excludes `fee`; `counterparty` is the other user involved, if any.
+/// `tags` are free-form labels the user can add and remove later.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transaction {
+   pub id: u64,
+   pub timestamp: SystemTime,
+   pub kind: TransactionKind,
+   pub amount: Money,
+   pub currency: Currency,
+   pub fee: Money,
+   pub counterparty: Option<UserId>,
+   #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+   pub tags: BTreeSet<String>,
+}
+
+impl Transaction {
+    pub fn new(
+        kind: TransactionKind,
+        amount: Money,
+        currency: Currency,
+        fee: Money,
+        counterparty: Option<UserId>,
+    ) -> Self {
+        Transaction {
+            id: NEXT_TRANSACTION_ID.fetch_add(1, Ordering::Relaxed),
+            timestamp: SystemTime::now(),
+            kind,
+            amount,
+            currency,
+            fee,
+            counterparty,
+            tags: BTreeSet::new(),
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+}
+
+/// Tags are compared case-insensitively and may not contain whitespace.
+pub fn normalize_tag(tag: &str) -> Result<String, String> {
+    let tag = tag.trim();
+    if tag.is_empty() || tag.contains(char::is_whitespace) {
+        return Err(format!("Invalid tag '{}'", tag));
+    }
+    Ok(tag.to_lowercase())
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render(f, |counterparty| counterparty.to_string())
+    }
+}
+
+impl Transaction {
+    /// Write the transaction like `Display`, showing the counterparty as
+    /// `label` describes it, e.g. by its payee name.
+    pub fn render(&self, f: &mut impl fmt::Write, label: impl Fn(UserId) -> String) -> fmt::Result {
+        write!(f, "tx {}: {:?} {} {}", self.id, self.kind, self.amount, self.currency)?;
+        if self.fee > Money::ZERO {
+            write!(f, " (fee {})", self.fee)?;
+        }
+        if let Some(counterparty) = self.counterparty {
+            write!(f, " with {}", label(counterparty))?;
+        }
+        if !self.tags.is_empty() {
+            let tags: Vec<&str> = self.tags.iter().map(String::as_str).collect();
+            write!(f, " [{}]", tags.join(", "))?;
+        }
+        Ok(())
+    }
+}
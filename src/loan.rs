@@ -0,0 +1,179 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::money::Money;
+use crate::time::{self, Clock};
+use crate::types::{AccountId, LoanId, UserId};
+
+/// Interest rate applied to new loans, in basis points.
+pub const DEFAULT_RATE_BPS: u32 = 500; // 5%
+
+static NEXT_LOAN_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Make sure newly opened loans get ids above `id`, e.g. after loading
+/// existing loans from disk.
+pub fn reserve_ids_through(id: LoanId) {
+    NEXT_LOAN_ID.fetch_max(u32::from(id) + 1, Ordering::Relaxed);
+}
+
+/// The id the next new loan will get.
+pub fn next_id() -> LoanId {
+    LoanId::from(NEXT_LOAN_ID.load(Ordering::Relaxed))
+}
+
+/// A debt owed by `borrower` to `lender`. Interest accrues as simple annual
+/// interest on the `remaining` principal; repayments settle interest first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Loan {
+   pub id: LoanId,
+   pub borrower: UserId,
+   pub lender: UserId,
+   /// The borrower's account the loan was paid into and is repaid from;
+   /// `None` for loans made before loans recorded their accounts, which are
+   /// repaid between primary accounts.
+   #[serde(default)]
+   pub account: Option<AccountId>,
+   /// The lender's account the loan was drawn from and is repaid into.
+   #[serde(default)]
+   pub lender_account: Option<AccountId>,
+   pub principal: Money,
+   pub currency: Currency,
+   pub rate_bps: u32,
+   pub start: SystemTime,
+   #[serde(alias = "outstanding")]
+   pub remaining: Money,
+   #[serde(default)]
+   pub accrued_interest: Money,
+   /// When interest was last moved into `accrued_interest`; `None` means never.
+   #[serde(default)]
+   pub last_accrued: Option<SystemTime>,
+}
+
+impl Loan {
+    /// Open a new loan of `principal` at `DEFAULT_RATE_BPS` from the lender's
+    /// `lender_account` into the borrower's `account`, starting at `start`,
+    /// with nothing repaid yet.
+    pub fn new(
+        borrower: UserId,
+        account: AccountId,
+        lender: UserId,
+        lender_account: AccountId,
+        principal: Money,
+        currency: Currency,
+        start: SystemTime,
+    ) -> Self {
+        Loan {
+            id: LoanId::from(NEXT_LOAN_ID.fetch_add(1, Ordering::Relaxed)),
+            borrower,
+            lender,
+            account: Some(account),
+            lender_account: Some(lender_account),
+            principal,
+            currency,
+            rate_bps: DEFAULT_RATE_BPS,
+            start,
+            remaining: principal,
+            accrued_interest: Money::ZERO,
+            last_accrued: None,
+        }
+    }
+
+    /// Interest owed as of `now`, including any already accrued.
+    pub fn interest_due(&self, now: SystemTime) -> Money {
+        let since = self.last_accrued.unwrap_or(self.start);
+        let elapsed = now.duration_since(since).unwrap_or(Duration::ZERO);
+        let fresh = Money::from_minor(time::prorate(self.remaining.mul_bps(self.rate_bps).minor(), elapsed));
+        self.accrued_interest.checked_add(fresh).unwrap_or(Money::from_minor(u64::MAX))
+    }
+
+    /// Move the interest earned up to `now` into `accrued_interest`.
+    pub fn accrue(&mut self, now: SystemTime) {
+        self.accrued_interest = self.interest_due(now);
+        self.last_accrued = Some(now);
+    }
+
+    /// Total owed as of `clock`'s now: remaining principal plus interest due.
+    pub fn outstanding(&self, clock: &dyn Clock) -> Money {
+        self.remaining
+            .checked_add(self.interest_due(clock.now()))
+            .unwrap_or(Money::from_minor(u64::MAX))
+    }
+
+    /// Whether the loan has been paid off in full.
+    pub fn is_closed(&self) -> bool {
+        self.remaining == Money::ZERO && self.accrued_interest == Money::ZERO
+    }
+
+    /// Apply a payment of up to `amount`, accrued interest first and then
+    /// principal. Returns the amount actually applied, which is capped at what
+    /// is owed.
+    pub fn apply_payment(&mut self, amount: Money, now: SystemTime) -> Money {
+        self.accrue(now);
+        let to_interest = amount.min(self.accrued_interest);
+        let to_principal = amount
+            .checked_sub(to_interest)
+            .unwrap_or(Money::ZERO)
+            .min(self.remaining);
+        self.accrued_interest = self.accrued_interest.checked_sub(to_interest).unwrap_or(Money::ZERO);
+        self.remaining = self.remaining.checked_sub(to_principal).unwrap_or(Money::ZERO);
+        to_interest.checked_add(to_principal).unwrap_or(amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::{MockClock, SECONDS_PER_YEAR};
+
+    const YEAR: Duration = Duration::from_secs(SECONDS_PER_YEAR);
+
+    fn loan(principal: u64) -> Loan {
+        let (borrower, lender) = (UserId::from(1), UserId::from(2));
+        let (account, lender_account) = (AccountId::from(1), AccountId::from(2));
+        let principal = Money::from_major(principal);
+        Loan::new(borrower, account, lender, lender_account, principal, Currency::Usd, SystemTime::UNIX_EPOCH)
+    }
+
+    #[test]
+    fn interest_is_simple_and_prorated() {
+        let loan = loan(1_000);
+        let start = loan.start;
+        assert_eq!(loan.interest_due(start), Money::ZERO);
+        assert_eq!(loan.interest_due(start + YEAR), Money::from_major(50));
+        assert_eq!(loan.interest_due(start + YEAR * 2), Money::from_major(100));
+        assert_eq!(loan.interest_due(start + YEAR / 2), Money::from_major(25));
+        assert_eq!(loan.outstanding(&MockClock::new(start + YEAR)), Money::from_major(1_050));
+    }
+
+    #[test]
+    fn payments_settle_interest_before_principal() {
+        let mut loan = loan(1_000);
+        let later = loan.start + YEAR;
+        assert_eq!(loan.apply_payment(Money::from_major(30), later), Money::from_major(30));
+        assert_eq!((loan.accrued_interest, loan.remaining), (Money::from_major(20), Money::from_major(1_000)));
+        assert_eq!(loan.apply_payment(Money::from_major(120), later), Money::from_major(120));
+        assert_eq!((loan.accrued_interest, loan.remaining), (Money::ZERO, Money::from_major(900)));
+        assert!(!loan.is_closed());
+    }
+
+    #[test]
+    fn interest_accrues_on_what_remains() {
+        let mut loan = loan(1_000);
+        let half = loan.start + YEAR / 2;
+        loan.apply_payment(Money::from_major(525), half);
+        assert_eq!(loan.remaining, Money::from_major(500));
+        assert_eq!(loan.interest_due(half + YEAR / 2), Money::from_minor(1_250));
+    }
+
+    #[test]
+    fn overpayments_are_capped_at_what_is_owed() {
+        let mut loan = loan(100);
+        let later = loan.start + YEAR;
+        assert_eq!(loan.apply_payment(Money::from_major(1_000), later), Money::from_major(105));
+        assert!(loan.is_closed());
+        assert_eq!(loan.apply_payment(Money::from_major(1), later + YEAR), Money::ZERO);
+    }
+}
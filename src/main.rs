@@ -1,41 +1,829 @@
+pub(crate) mod account;
+pub(crate) mod advance;
+pub(crate) mod aggregates;
+pub(crate) mod api;
+pub(crate) mod bank;
+pub(crate) mod currency;
+pub(crate) mod event;
+pub(crate) mod export;
+pub(crate) mod facility;
+pub(crate) mod fees;
+pub(crate) mod income;
+pub(crate) mod installment;
+pub(crate) mod interest;
+pub(crate) mod ledger;
+pub(crate) mod loan;
+pub(crate) mod metrics;
+pub(crate) mod money;
+pub(crate) mod overdraft;
+pub(crate) mod payee;
+pub(crate) mod policy;
+pub(crate) mod repl;
+pub(crate) mod sandbox;
+pub(crate) mod scenario;
+pub(crate) mod script;
+pub(crate) mod store;
+pub(crate) mod time;
+pub(crate) mod types;
 pub(crate) mod user;
 
-use user::{User, Treasury};
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use clap::{Parser, Subcommand};
+
+use account::AccountKind;
+use bank::{Bank, Environment};
+use currency::Currency;
+use fees::FeeSchedule;
+use interest::{Compounding, InterestSchedule, InterestStrategy};
+use money::Money;
+use payee::Payee;
+use policy::Policy;
+use sandbox::Seed;
+use scenario::Scenario;
+use script::Script;
+use store::Store;
+use types::{AccountId, LoanId, UserId};
+use user::User;
+
+/// Run banking operations against a bank state file.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Bank state file, created on first use. A `.log` path keeps an
+    /// append-only event log that the state is replayed from instead of JSON.
+    /// With the `sqlite` feature, a `.db` or `.sqlite` path is stored as an
+    /// SQLite database.
+    #[arg(long, global = true, default_value = "bank.json")]
+    state: PathBuf,
+
+    /// Use test money. Required for sandbox state files and rejected for
+    /// production ones; a new state file takes the mode given here.
+    #[arg(long, global = true)]
+    sandbox: bool,
+
+    /// Print every change the command makes to the bank on stderr.
+    #[arg(long, global = true)]
+    log_events: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Open an account and print the new user id.
+    CreateUser {
+        name: String,
+        /// Account kind: checking or savings.
+        #[arg(long, default_value = "savings")]
+        kind: AccountKind,
+        /// Open a term deposit maturing after this many days instead.
+        #[arg(long, conflicts_with = "kind", value_parser = clap::value_parser!(u64).range(..=account::MAX_TERM_DAYS))]
+        term_days: Option<u64>,
+    },
+    /// Open another account for an existing user and print its id.
+    OpenAccount {
+        user: u32,
+        /// Account kind: checking or savings.
+        #[arg(long, default_value = "savings")]
+        kind: AccountKind,
+        /// Open a term deposit maturing after this many days instead.
+        #[arg(long, conflicts_with = "kind", value_parser = clap::value_parser!(u64).range(..=account::MAX_TERM_DAYS))]
+        term_days: Option<u64>,
+    },
+    /// Deposit funds, with the entry fee deducted.
+    Deposit {
+        user: u32,
+        amount: Money,
+        #[arg(long, default_value = "USD")]
+        currency: Currency,
+        /// Allow other users to borrow against this deposit.
+        #[arg(long)]
+        borrowable: bool,
+        /// Use this account instead of the user's primary one.
+        #[arg(long)]
+        account: Option<u32>,
+    },
+    /// Withdraw funds, with the exit fee added.
+    Withdraw {
+        user: u32,
+        amount: Money,
+        #[arg(long, default_value = "USD")]
+        currency: Currency,
+        /// Use this account instead of the user's primary one.
+        #[arg(long)]
+        account: Option<u32>,
+    },
+    /// Send funds directly to another user, or between a user's own accounts.
+    Transfer {
+        from: u32,
+        to: u32,
+        amount: Money,
+        #[arg(long, default_value = "USD")]
+        currency: Currency,
+        /// Send from this account instead of the sender's primary one.
+        #[arg(long)]
+        from_account: Option<u32>,
+        /// Send to this account instead of the receiver's primary one.
+        #[arg(long)]
+        to_account: Option<u32>,
+    },
+    /// Borrow from another user's deposit.
+    Borrow {
+        borrower: u32,
+        lender: u32,
+        amount: Money,
+        #[arg(long, default_value = "USD")]
+        currency: Currency,
+        /// Receive the loan in this account instead of the borrower's primary one.
+        #[arg(long)]
+        account: Option<u32>,
+        /// Borrow against this account instead of the lender's primary one.
+        #[arg(long)]
+        lender_account: Option<u32>,
+    },
+    /// Buy from another user and pay the treasury back in installments, the
+    /// first one now.
+    BuyInInstallments {
+        payer: u32,
+        payee: u32,
+        price: Money,
+        installments: u32,
+        #[arg(long, default_value = "USD")]
+        currency: Currency,
+        /// Collect installments from this account instead of the payer's primary one.
+        #[arg(long)]
+        account: Option<u32>,
+        /// Pay this account instead of the payee's primary one.
+        #[arg(long)]
+        payee_account: Option<u32>,
+    },
+    /// Collect a user's installments that have fallen due.
+    CollectInstallments { user: u32 },
+    /// Pay towards a loan; interest is settled before principal.
+    Repay {
+        loan: u32,
+        amount: Money,
+    },
+    /// Mint test money into a user's account (sandbox only).
+    Faucet {
+        user: u32,
+        amount: Money,
+        #[arg(long, default_value = "USD")]
+        currency: Currency,
+    },
+    /// Wipe the bank and rebuild a seed scenario: empty, lending or funded
+    /// (sandbox only).
+    Reset {
+        #[arg(default_value = "empty")]
+        seed: Seed,
+    },
+    /// Apply treasury interest to a user's deposit.
+    ApplyInterest {
+        user: u32,
+        #[arg(long, default_value = "USD")]
+        currency: Currency,
+    },
+    /// Propose how deposit interest is calculated. It applies once another
+    /// operator confirms it with `confirm-policy`.
+    SetInterest {
+        /// Yearly rate in basis points.
+        #[arg(required_unless_present = "legacy")]
+        rate_bps: Option<u32>,
+        #[arg(long, default_value = "monthly")]
+        compounding: Compounding,
+        /// Use the original treasury-ratio formula instead of a schedule.
+        #[arg(long, conflicts_with = "rate_bps")]
+        legacy: bool,
+        /// Name of the operator proposing the change.
+        #[arg(long)]
+        operator: String,
+    },
+    /// Propose new deposit and withdrawal fees, applied once another
+    /// operator confirms them; unset options keep their current value.
+    SetFees {
+        #[arg(long)]
+        entry_bps: Option<u32>,
+        #[arg(long)]
+        exit_bps: Option<u32>,
+        /// Flat minimum entry fee.
+        #[arg(long)]
+        min_entry: Option<Money>,
+        /// Flat minimum exit fee.
+        #[arg(long)]
+        min_exit: Option<Money>,
+        /// Largest entry fee charged; 0 removes the cap.
+        #[arg(long)]
+        entry_cap: Option<Money>,
+        /// Largest exit fee charged; 0 removes the cap.
+        #[arg(long)]
+        exit_cap: Option<Money>,
+        /// Name of the operator proposing the change.
+        #[arg(long)]
+        operator: String,
+    },
+    /// Propose the currency, limit and yearly rate of the liquidity facility
+    /// the treasury draws on when its reserves run short, applied once
+    /// another operator confirms them.
+    SetFacility {
+        /// Most that can be drawn; 0 stops further draws.
+        limit: Money,
+        /// Yearly rate in basis points.
+        rate_bps: u32,
+        #[arg(long, default_value = "USD")]
+        currency: Currency,
+        /// Name of the operator proposing the change.
+        #[arg(long)]
+        operator: String,
+    },
+    /// List the interest, fee and facility changes waiting for a second
+    /// operator.
+    PolicyChanges {
+        /// Also list confirmed, cancelled and expired changes.
+        #[arg(long)]
+        all: bool,
+    },
+    /// Confirm another operator's pending policy change and apply it.
+    ConfirmPolicy {
+        change: u32,
+        #[arg(long)]
+        operator: String,
+    },
+    /// Withdraw a pending policy change.
+    CancelPolicy {
+        change: u32,
+        #[arg(long)]
+        operator: String,
+    },
+    /// Let a user withdraw past zero, up to `limit`.
+    GrantOverdraft {
+        user: u32,
+        limit: Money,
+        #[arg(long, default_value_t = overdraft::DEFAULT_OVERDRAFT_RATE_BPS)]
+        rate_bps: u32,
+        #[arg(long, default_value = "USD")]
+        currency: Currency,
+    },
+    /// Remove a user's fully repaid overdraft.
+    RevokeOverdraft { user: u32 },
+    /// Show the recurring income detected for a user and how much of it can
+    /// be advanced.
+    Income { user: u32 },
+    /// Advance part of a user's next salary, repaid from that salary.
+    Advance {
+        user: u32,
+        amount: Money,
+        /// Use this account instead of the user's primary one.
+        #[arg(long)]
+        account: Option<u32>,
+    },
+    /// Add a tag to some of a user's transactions.
+    Tag {
+        user: u32,
+        tag: String,
+        #[arg(required = true)]
+        transactions: Vec<u64>,
+    },
+    /// Remove a tag from some of a user's transactions.
+    Untag {
+        user: u32,
+        tag: String,
+        #[arg(required = true)]
+        transactions: Vec<u64>,
+    },
+    /// List a user's transactions, optionally only those with a tag.
+    History {
+        user: u32,
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// List a user in the payee directory so statements show them by name.
+    RegisterPayee {
+        user: u32,
+        name: String,
+        #[arg(long)]
+        category: Option<String>,
+        /// Logo or website of the payee.
+        #[arg(long)]
+        uri: Option<String>,
+    },
+    /// Remove a user from the payee directory.
+    RemovePayee { user: u32 },
+    /// Change how a payee appears on one user's statements; without a name,
+    /// go back to the directory entry.
+    CorrectPayee {
+        user: u32,
+        payee: u32,
+        name: Option<String>,
+        #[arg(long, requires = "name")]
+        category: Option<String>,
+    },
+    /// Move the fees collected in a currency into the treasury's main pool.
+    SweepFees {
+        #[arg(long, default_value = "USD")]
+        currency: Currency,
+    },
+    /// Verify the bank-wide totals against a full recomputation.
+    Check,
+    /// Print the whole bank state as canonical JSON, for diffing between runs
+    /// and for golden files.
+    Export,
+    /// List the policy, balance, loan and ledger differences between two JSON
+    /// state files.
+    Diff { a: PathBuf, b: PathBuf },
+    /// Show one user, or every user and the treasury.
+    Show { user: Option<u32> },
+    /// Read commands interactively against an in-memory bank.
+    Repl,
+    /// Run a narrated walkthrough on a fresh in-memory bank without touching
+    /// the state file: lending, bank-run or fees. Lists them if none is named.
+    Demo { scenario: Option<Scenario> },
+    /// Run a scenario file of given/when/then steps on a fresh in-memory bank
+    /// without touching the state file; see `script::Script` for the format.
+    RunScenario { file: PathBuf },
+    /// Hammer a fresh in-memory bank with transfers and loans from many
+    /// threads while another reports on snapshots, then check that no money
+    /// was created or lost and that every report balanced.
+    Stress {
+        #[arg(long, default_value_t = 16)]
+        users: u32,
+        #[arg(long, default_value_t = 8)]
+        threads: u32,
+        /// Operations run by each thread.
+        #[arg(long, default_value_t = 10_000)]
+        operations: u32,
+    },
+    /// Serve the bank over HTTP until interrupted; see `api::http::router`
+    /// for the endpoints. Needs a `.log` or SQLite state file.
+    #[cfg(feature = "server")]
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:3000")]
+        addr: std::net::SocketAddr,
+    },
+    /// Serve the `bank.Bank` gRPC service from `proto/bank.proto` until
+    /// interrupted. Needs a `.log` or SQLite state file.
+    #[cfg(feature = "grpc")]
+    ServeGrpc {
+        #[arg(long, default_value = "127.0.0.1:50051")]
+        addr: std::net::SocketAddr,
+    },
+}
 
 fn main() {
-    let mut treasury = Treasury::default();
+    let cli = Cli::parse();
+    if let Err(err) = run(cli) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
 
-    // Create two users: Alice (lender) and Bob (borrower)
-    let mut alice = User {
-        id: 1,
-        name: String::from("Alice"),
-        ..Default::default()
-    };
-    let mut bob = User {
-        id: 2,
-        name: String::from("Bob"),
-        ..Default::default()
+fn run(cli: Cli) -> Result<(), String> {
+    match cli.command {
+        Command::Demo { scenario: Some(scenario) } => return scenario.run(),
+        Command::Demo { scenario: None } => {
+            for scenario in Scenario::ALL {
+                println!("{:<10} {}", scenario.to_string(), scenario.summary());
+            }
+            return Ok(());
+        }
+        Command::RunScenario { file } => {
+            let text = std::fs::read_to_string(&file)
+                .map_err(|err| format!("Cannot read {}: {}", file.display(), err))?;
+            let checks = text.parse::<Script>()?.run()?;
+            println!("{} check(s) passed.", checks);
+            return Ok(());
+        }
+        Command::Repl => {
+            return repl::run(io::stdin().lock(), &mut io::stdout())
+                .map_err(|err| format!("I/O error: {}", err));
+        }
+        Command::Stress { users, threads, operations } => return stress(users, threads, operations),
+        Command::Diff { a, b } => {
+            let differences = export::diff(&Bank::load_json(&a)?, &Bank::load_json(&b)?);
+            for difference in &differences {
+                println!("{}", difference);
+            }
+            println!("{} difference(s).", differences.len());
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let extension = cli.state.extension().and_then(|ext| ext.to_str());
+    if extension == Some("log") {
+        let bank = Bank::open(store::event_log::EventLogStore::open(&cli.state)?)?;
+        return run_stored(bank, cli);
+    }
+    #[cfg(feature = "sqlite")]
+    if matches!(extension, Some("db" | "sqlite")) {
+        let bank = Bank::open(store::sqlite::SqliteStore::open(&cli.state)?)?;
+        return run_stored(bank, cli);
+    }
+
+    #[cfg(feature = "server")]
+    if let Command::Serve { .. } = cli.command {
+        return Err(not_written_through(&cli.state));
+    }
+    #[cfg(feature = "grpc")]
+    if let Command::ServeGrpc { .. } = cli.command {
+        return Err(not_written_through(&cli.state));
+    }
+    let mut bank = if cli.state.exists() {
+        Bank::load_json(&cli.state)?
+    } else {
+        Bank::new()
     };
+    if cli.log_events {
+        bank.on_event(|event| eprintln!("event: {}", event));
+    }
+    check_environment(&mut bank, cli.sandbox)?;
+    if execute(&mut bank, cli.command)? {
+        bank.save_json(&cli.state)?;
+    }
+    Ok(())
+}
+
+/// Run `cli.command` against a bank that writes its changes through to its
+/// store as they happen.
+fn run_stored<S: Store + Send + 'static>(mut bank: Bank<S>, cli: Cli) -> Result<(), String> {
+    if cli.log_events {
+        bank.on_event(|event| eprintln!("event: {}", event));
+    }
+    check_environment(&mut bank, cli.sandbox)?;
+    #[cfg(feature = "server")]
+    if let Command::Serve { addr } = cli.command {
+        return api::http::serve(bank, addr);
+    }
+    #[cfg(feature = "grpc")]
+    if let Command::ServeGrpc { addr } = cli.command {
+        return api::grpc::serve(bank, addr);
+    }
+    execute(&mut bank, cli.command).map(|_| ())
+}
+
+/// The error for serving a JSON state file, which is only written once a
+/// command finishes: a server that crashed or was killed would lose every
+/// change made while it ran.
+#[cfg(any(feature = "server", feature = "grpc"))]
+fn not_written_through(state: &std::path::Path) -> String {
+    format!(
+        "Cannot serve {}: servers need a state file written as it changes, a .log event log{}",
+        state.display(),
+        if cfg!(feature = "sqlite") { " or a .db SQLite file" } else { "" }
+    )
+}
+
+/// Refuse to mix test and real money: the `--sandbox` flag must match the
+/// state file, unless the bank is still empty and can take either mode.
+fn check_environment<S: Store>(bank: &mut Bank<S>, sandbox: bool) -> Result<(), String> {
+    let wanted = if sandbox { Environment::Sandbox } else { Environment::Production };
+    if bank.users().next().is_none() {
+        return bank.set_environment(wanted).map_err(String::from);
+    }
+    if bank.environment() != wanted {
+        return Err(format!(
+            "The state file holds a {} bank; {} the --sandbox flag",
+            bank.environment(),
+            if sandbox { "drop" } else { "pass" }
+        ));
+    }
+    Ok(())
+}
+
+/// Run a state-file command, returning whether the bank changed.
+fn execute<S: Store>(bank: &mut Bank<S>, command: Command) -> Result<bool, String> {
+    match command {
+        Command::CreateUser { name, kind, term_days } => {
+            let kind = match term_days {
+                Some(days) => AccountKind::term_deposit(bank.clock().now(), days)?,
+                None => kind,
+            };
+            let id = bank.open_account_of_kind(&name, kind)?;
+            println!("Created user {} ({}).", id, name);
+        }
+        Command::OpenAccount { user, kind, term_days } => {
+            let kind = match term_days {
+                Some(days) => AccountKind::term_deposit(bank.clock().now(), days)?,
+                None => kind,
+            };
+            let account = bank.add_account(UserId::from(user), kind)?;
+            println!("Opened {} account {} for user #{}.", kind, account, user);
+        }
+        Command::Deposit { user, amount, currency, borrowable, account } => {
+            let account = bank.resolve_account(UserId::from(user), account.map(AccountId::from))?;
+            bank.deposit_into(account, amount, currency, borrowable)?;
+            println!("Deposited {} {} into account {} of user #{}.", amount, currency, account, user);
+        }
+        Command::Withdraw { user, amount, currency, account } => {
+            let account = bank.resolve_account(UserId::from(user), account.map(AccountId::from))?;
+            bank.withdraw_from(account, amount, currency)?;
+            println!("Withdrew {} {} from account {} of user #{}.", amount, currency, account, user);
+        }
+        Command::Transfer { from, to, amount, currency, from_account, to_account } => {
+            let source = bank.resolve_account(UserId::from(from), from_account.map(AccountId::from))?;
+            let target = bank.resolve_account(UserId::from(to), to_account.map(AccountId::from))?;
+            bank.transfer_between(source, target, amount, currency)?;
+            println!("Transferred {} {} from account {} to account {}.", amount, currency, source, target);
+        }
+        Command::Borrow { borrower, lender, amount, currency, account, lender_account } => {
+            let account = bank.resolve_account(UserId::from(borrower), account.map(AccountId::from))?;
+            let lender_account = bank.resolve_account(UserId::from(lender), lender_account.map(AccountId::from))?;
+            let borrowed = bank.borrow_into(account, lender_account, amount, currency)?;
+            println!("User #{} borrowed {} {} from user #{}.", borrower, borrowed, currency, lender);
+        }
+        Command::BuyInInstallments { payer, payee, price, installments, currency, account, payee_account } => {
+            let account = bank.resolve_account(UserId::from(payer), account.map(AccountId::from))?;
+            let payee_account = bank.resolve_account(UserId::from(payee), payee_account.map(AccountId::from))?;
+            let plan = bank.buy_in_installments(account, payee_account, price, currency, installments)?;
+            println!(
+                "User #{} bought from user #{} for {} {} in {} installments under plan {}.",
+                payer, payee, price, currency, installments, plan
+            );
+        }
+        Command::CollectInstallments { user } => {
+            let collected = bank.collect_installments(UserId::from(user))?;
+            println!("Collected {} in installments from user #{}.", collected, user);
+        }
+        Command::Repay { loan, amount } => {
+            let loan = LoanId::from(loan);
+            let paid = bank.repay(loan, amount)?;
+            println!("Repaid {} towards loan {}.", paid, loan);
+        }
+        Command::Faucet { user, amount, currency } => {
+            bank.faucet(UserId::from(user), amount, currency)?;
+            println!("Minted {} {} of test money for user #{}.", amount, currency, user);
+        }
+        Command::Reset { seed } => {
+            bank.reset(seed)?;
+            println!("Reset the sandbox bank to the '{}' seed.", seed);
+        }
+        Command::ApplyInterest { user, currency } => {
+            let interest = bank.apply_interest(UserId::from(user), currency)?;
+            println!("Applied {} {} interest to user #{}.", interest, currency, user);
+        }
+        Command::SetInterest { rate_bps, compounding, legacy, operator } => {
+            let interest = match rate_bps {
+                Some(rate_bps) if !legacy => InterestStrategy::Compound(InterestSchedule { rate_bps, compounding }),
+                _ => InterestStrategy::Legacy,
+            };
+            let change = bank.propose_policy(Policy::Interest(interest), &operator)?;
+            print_proposed(change);
+        }
+        Command::SetFees { entry_bps, exit_bps, min_entry, min_exit, entry_cap, exit_cap, operator } => {
+            let current = bank.treasury.fees;
+            let fees = FeeSchedule {
+                entry_bps: entry_bps.unwrap_or(current.entry_bps),
+                exit_bps: exit_bps.unwrap_or(current.exit_bps),
+                min_entry: min_entry.unwrap_or(current.min_entry),
+                min_exit: min_exit.unwrap_or(current.min_exit),
+                entry_cap: entry_cap.map_or(current.entry_cap, |cap| (cap > Money::ZERO).then_some(cap)),
+                exit_cap: exit_cap.map_or(current.exit_cap, |cap| (cap > Money::ZERO).then_some(cap)),
+            };
+            let change = bank.propose_policy(Policy::Fees(fees), &operator)?;
+            print_proposed(change);
+        }
+        Command::SetFacility { limit, rate_bps, currency, operator } => {
+            let change = bank.propose_policy(Policy::Facility { currency, limit, rate_bps }, &operator)?;
+            print_proposed(change);
+        }
+        Command::PolicyChanges { all } => {
+            let now = bank.clock().now();
+            let policy_changes = &bank.treasury.policy_changes;
+            let changes: Vec<_> =
+                if all { policy_changes.iter().collect() } else { policy_changes.pending(now).collect() };
+            if changes.is_empty() {
+                println!("No policy changes.");
+            }
+            for change in changes {
+                println!("{}", change.describe(now));
+            }
+            return Ok(false);
+        }
+        Command::ConfirmPolicy { change, operator } => {
+            let policy = bank.confirm_policy(change, &operator)?;
+            println!("Confirmed policy change #{}; the treasury now applies {}.", change, policy);
+        }
+        Command::CancelPolicy { change, operator } => {
+            let policy = bank.cancel_policy(change, &operator)?;
+            println!("Cancelled policy change #{} ({}).", change, policy);
+        }
+        Command::GrantOverdraft { user, limit, rate_bps, currency } => {
+            bank.grant_overdraft(UserId::from(user), currency, limit, rate_bps)?;
+            println!("User #{} may now overdraw {} {} at {} bps.", user, limit, currency, rate_bps);
+        }
+        Command::RevokeOverdraft { user } => {
+            bank.revoke_overdraft(UserId::from(user))?;
+            println!("Revoked the overdraft of user #{}.", user);
+        }
+        Command::Income { user } => {
+            let id = UserId::from(user);
+            match bank.recurring_income(id)? {
+                Some(income) => println!(
+                    "User #{} receives about {} {} every {} day(s).",
+                    user,
+                    income.amount,
+                    income.currency,
+                    income.interval.as_secs() / (24 * 60 * 60)
+                ),
+                None => println!("User #{} has no recurring income.", user),
+            }
+            match bank.advance_limit(id) {
+                Ok((limit, currency)) => println!("Up to {} {} can be advanced.", limit, currency),
+                Err(reason) => println!("No advance available: {}.", reason),
+            }
+            return Ok(false);
+        }
+        Command::Advance { user, amount, account } => {
+            let account = bank.resolve_account(UserId::from(user), account.map(AccountId::from))?;
+            bank.take_salary_advance(account, amount)?;
+            println!("Advanced {} into account {} of user #{}.", amount, account, user);
+        }
+        Command::Tag { user, tag, transactions } => {
+            let tagged = bank.tag_transactions(UserId::from(user), &transactions, &tag)?;
+            println!("Tagged {} transaction(s) of user #{} with '{}'.", tagged, user, tag);
+        }
+        Command::Untag { user, tag, transactions } => {
+            let untagged = bank.untag_transactions(UserId::from(user), &transactions, &tag)?;
+            println!("Removed '{}' from {} transaction(s) of user #{}.", tag, untagged, user);
+        }
+        Command::History { user, tag } => {
+            for line in bank.statement(UserId::from(user), tag.as_deref())? {
+                println!("{}", line);
+            }
+            return Ok(false);
+        }
+        Command::RegisterPayee { user, name, category, uri } => {
+            let payee = Payee { name, category, uri };
+            let shown = payee.to_string();
+            bank.register_payee(UserId::from(user), payee)?;
+            println!("User #{} now shows as {}.", user, shown);
+        }
+        Command::RemovePayee { user } => {
+            bank.remove_payee(UserId::from(user))?;
+            println!("Removed user #{} from the payee directory.", user);
+        }
+        Command::CorrectPayee { user, payee, name, category } => {
+            let correction = name.map(|name| Payee { name, category, uri: None });
+            bank.correct_payee(UserId::from(user), UserId::from(payee), correction.clone())?;
+            match correction {
+                Some(correction) => println!("User #{} now sees #{} as {}.", user, payee, correction),
+                None => println!("User #{} now sees #{} from the payee directory.", user, payee),
+            }
+        }
+        Command::SweepFees { currency } => {
+            let swept = bank.sweep_fees(currency)?;
+            println!("Swept {} {} of fees into the treasury.", swept, currency);
+        }
+        Command::Check => {
+            println!("{}", bank.treasury.aggregates);
+            let mismatches = bank.check_integrity();
+            if !mismatches.is_empty() {
+                return Err(format!("Integrity check failed:\n  {}", mismatches.join("\n  ")));
+            }
+            println!("Integrity check passed.");
+            return Ok(false);
+        }
+        Command::Export => {
+            let state = serde_json::to_string_pretty(&export::export(bank)?)
+                .map_err(|err| format!("Cannot serialize bank state: {}", err))?;
+            println!("{}", state);
+            return Ok(false);
+        }
+        Command::Show { user: Some(user) } => {
+            let id = UserId::from(user);
+            let user = bank.get_user(id).ok_or_else(|| format!("Unknown user {}", id))?;
+            println!("{}", user);
+            return Ok(false);
+        }
+        Command::Show { user: None } => {
+            let mut users: Vec<&User> = bank.users().collect();
+            users.sort_by_key(|user| user.id);
+            for user in users {
+                println!("{}", user);
+            }
+            println!("{}", bank.treasury);
+            return Ok(false);
+        }
+        Command::Demo { .. }
+        | Command::RunScenario { .. }
+        | Command::Repl
+        | Command::Stress { .. }
+        | Command::Diff { .. } => {
+            unreachable!("handled by run")
+        }
+        #[cfg(feature = "server")]
+        Command::Serve { .. } => unreachable!("handled by run"),
+        #[cfg(feature = "grpc")]
+        Command::ServeGrpc { .. } => unreachable!("handled by run"),
+    }
+    Ok(true)
+}
 
-    // Alice deposits 1000 with fees deducted and enables borrowing.
-    alice.deposit_with_fee(1000, &mut treasury, true);
-    println!("After Alice's deposit:");
-    println!("Alice: {:#?}", alice);
-    println!("Treasury: {:#?}", treasury);
+fn print_proposed(change: u32) {
+    println!(
+        "Proposed policy change #{}; another operator has {} hours to confirm it with `confirm-policy {}`.",
+        change,
+        policy::CONFIRMATION_WINDOW.as_secs() / (60 * 60),
+        change
+    );
+}
 
-    // Bob attempts to borrow 100 from Alice.
-    match bob.borrow(&mut alice, 100) {
-        Ok(borrowed) => println!("Bob borrowed {} from Alice.", borrowed),
-        Err(err) => println!("Borrow failed: {}", err),
+fn stress(users: u32, threads: u32, operations: u32) -> Result<(), String> {
+    if users < 2 {
+        return Err(String::from("The stress run needs at least 2 users"));
+    }
+    let bank = Bank::new().into_concurrent();
+    let mut ids = Vec::new();
+    for n in 1..=users {
+        let id = bank.open_account(&format!("User {}", n))?;
+        bank.deposit(id, Money::from_major(1000), Currency::Usd, true)?;
+        ids.push(id);
     }
-    println!("\nAfter borrowing:");
-    println!("Alice: {:#?}", alice);
-    println!("Bob: {:#?}", bob);
+    let before = bank.snapshot()?.deposits(Currency::Usd);
+    let running = AtomicBool::new(true);
+
+    let (succeeded, reports, unbalanced, first_unbalanced) = thread::scope(|scope| {
+        // Reports over snapshots while the transfers run: every one must see
+        // the books balanced and all of the funds in place.
+        let reporter = scope.spawn(|| -> Result<(u32, u32, Vec<String>), String> {
+            let (mut reports, mut unbalanced, mut first_unbalanced) = (0, 0, Vec::new());
+            while running.load(Ordering::Relaxed) {
+                let snapshot = bank.snapshot()?;
+                let mut problems = snapshot.trial_balance();
+                let deposits = snapshot.deposits(Currency::Usd);
+                if deposits != before {
+                    problems.push(format!("A report saw funds of {}", deposits));
+                }
+                reports += 1;
+                if !problems.is_empty() {
+                    unbalanced += 1;
+                    if first_unbalanced.is_empty() {
+                        first_unbalanced = problems;
+                    }
+                }
+            }
+            Ok((reports, unbalanced, first_unbalanced))
+        });
+        let workers: Vec<_> = (0..threads)
+            .map(|worker| {
+                let (bank, ids) = (&bank, &ids);
+                scope.spawn(move || {
+                    // xorshift, seeded per thread so runs are repeatable.
+                    let mut state = (u64::from(worker) + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+                    let mut next = move |bound: usize| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        (state % bound as u64) as usize
+                    };
+                    let mut succeeded = 0;
+                    for operation in 0..operations {
+                        let sender = next(ids.len());
+                        let receiver = (sender + 1 + next(ids.len() - 1)) % ids.len();
+                        let (from, to) = (ids[sender], ids[receiver]);
+                        let amount = Money::from_minor(1 + next(5_000) as u64);
+                        let result = if operation % 16 == 0 {
+                            bank.borrow_between(from, to, amount, Currency::Usd)
+                        } else {
+                            bank.transfer(from, to, amount, Currency::Usd)
+                        };
+                        succeeded += u32::from(result.is_ok());
+                    }
+                    succeeded
+                })
+            })
+            .collect();
+        let succeeded: u32 = workers.into_iter().map(|worker| worker.join().expect("stress thread panicked")).sum();
+        running.store(false, Ordering::Relaxed);
+        let (reports, unbalanced, first_unbalanced) = reporter.join().expect("stress reporter panicked")?;
+        Ok::<_, String>((succeeded, reports, unbalanced, first_unbalanced))
+    })?;
 
-    // Apply interest to Alice's deposit via Treasury.
-    match treasury.apply_interest(&mut alice) {
-        Ok(interest) => println!("Applied {} interest to Alice's deposit.", interest),
-        Err(err) => println!("Interest application failed: {}", err),
+    let after = bank.snapshot()?.deposits(Currency::Usd);
+    let mismatches = bank.check_integrity()?;
+    println!(
+        "{} of {} operations succeeded across {} threads; funds {} before, {} after.",
+        succeeded,
+        u64::from(threads) * u64::from(operations),
+        threads,
+        before,
+        after
+    );
+    for mismatch in mismatches.iter().chain(&first_unbalanced) {
+        println!("{}", mismatch);
+    }
+    if before != after || !mismatches.is_empty() {
+        return Err(String::from("Funds were not conserved"));
+    }
+    if unbalanced > 0 {
+        return Err(format!("{} of {} reports during the run did not balance", unbalanced, reports));
     }
-    println!("\nFinal Treasury state: {:#?}", treasury);
+    println!("Funds conserved; {} reports during the run all balanced.", reports);
+    println!("{}", bank.metrics()?);
+    Ok(())
 }
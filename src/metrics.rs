@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+/// Bank operations timed by `Metrics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Operation {
+    OpenAccount,
+    Deposit,
+    Withdraw,
+    Convert,
+    Transfer,
+    Borrow,
+    Repay,
+    Interest,
+    Faucet,
+    SweepFees,
+    Overdraft,
+    Payee,
+    Advance,
+    Installments,
+    Policy,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::OpenAccount => "open_account",
+            Operation::Deposit => "deposit",
+            Operation::Withdraw => "withdraw",
+            Operation::Convert => "convert",
+            Operation::Transfer => "transfer",
+            Operation::Borrow => "borrow",
+            Operation::Repay => "repay",
+            Operation::Interest => "interest",
+            Operation::Faucet => "faucet",
+            Operation::SweepFees => "sweep_fees",
+            Operation::Overdraft => "overdraft",
+            Operation::Payee => "payee",
+            Operation::Advance => "advance",
+            Operation::Installments => "installments",
+            Operation::Policy => "policy",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Upper bounds of the histogram buckets, in microseconds. Anything slower
+/// lands in a final overflow bucket.
+pub const BUCKET_BOUNDS_US: [u64; 6] = [10, 100, 1_000, 10_000, 100_000, 1_000_000];
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+   pub buckets: [u64; BUCKET_BOUNDS_US.len() + 1],
+   pub count: u64,
+   pub total: Duration,
+   pub max: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, elapsed: Duration) {
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        let bucket = BUCKET_BOUNDS_US
+            .iter()
+            .position(|&bound| micros <= bound)
+            .unwrap_or(BUCKET_BOUNDS_US.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+
+    pub fn mean(&self) -> Duration {
+        match u32::try_from(self.count) {
+            Ok(0) => Duration::ZERO,
+            Ok(count) => self.total / count,
+            Err(_) => self.total / u32::MAX,
+        }
+    }
+}
+
+/// Latency histograms per operation, covering both the in-memory change and
+/// the write to the store.
+#[derive(Debug, Default, Clone)]
+pub struct Metrics {
+    latencies: BTreeMap<Operation, LatencyHistogram>,
+}
+
+impl Metrics {
+    pub fn record(&mut self, operation: Operation, elapsed: Duration) {
+        self.latencies.entry(operation).or_default().record(elapsed);
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.latencies.is_empty() {
+            return write!(f, "no operations recorded");
+        }
+        let mut first = true;
+        for (operation, histogram) in &self.latencies {
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            write!(
+                f,
+                "{}: count {}, mean {:?}, max {:?}, buckets",
+                operation,
+                histogram.count,
+                histogram.mean(),
+                histogram.max
+            )?;
+            for (bound, hits) in BUCKET_BOUNDS_US.iter().zip(&histogram.buckets) {
+                write!(f, " <={}us:{}", bound, hits)?;
+            }
+            write!(f, " more:{}", histogram.buckets[BUCKET_BOUNDS_US.len()])?;
+        }
+        Ok(())
+    }
+}
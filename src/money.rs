@@ -0,0 +1,119 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+const MINOR_PER_MAJOR: u64 = 100;
+/// Basis points in 100%.
+pub const MAX_BPS: u64 = 10_000;
+
+/// An amount of funds in minor units (cents), so percentage fees on small
+/// amounts no longer round away to zero.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Money(u64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub const fn from_minor(minor: u64) -> Self {
+        Money(minor)
+    }
+
+    /// `major` whole units, e.g. `from_major(5)` is 5.00.
+    pub const fn from_major(major: u64) -> Self {
+        Money(major * MINOR_PER_MAJOR)
+    }
+
+    pub const fn minor(self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Money) -> Option<Money> {
+        self.0.checked_add(other.0).map(Money)
+    }
+
+    pub fn checked_sub(self, other: Money) -> Option<Money> {
+        self.0.checked_sub(other.0).map(Money)
+    }
+
+    /// Take `bps` basis points of this amount, rounding down to the cent.
+    pub fn mul_bps(self, bps: u32) -> Money {
+        self.checked_mul_bps(bps).unwrap_or(Money(u64::MAX))
+    }
+
+    /// Like `mul_bps`, returning `None` if the result does not fit.
+    pub fn checked_mul_bps(self, bps: u32) -> Option<Money> {
+        let scaled = u128::from(self.0) * u128::from(bps) / u128::from(MAX_BPS);
+        u64::try_from(scaled).ok().map(Money)
+    }
+}
+
+impl FromStr for Money {
+    type Err = String;
+
+    /// Parse a decimal amount with at most two fractional digits, e.g. `12.5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid amount '{}'", s);
+        let (major, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if major.is_empty() || fraction.len() > 2 || !digits(major) || !digits(fraction) {
+            return Err(invalid());
+        }
+        let major: u64 = major.parse().map_err(|_| invalid())?;
+        let minor: u64 = if fraction.is_empty() {
+            0
+        } else {
+            format!("{:0<2}", fraction).parse().map_err(|_| invalid())?
+        };
+        major
+            .checked_mul(MINOR_PER_MAJOR)
+            .and_then(|m| m.checked_add(minor))
+            .map(Money)
+            .ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:02}", self.0 / MINOR_PER_MAJOR, self.0 % MINOR_PER_MAJOR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts_parse_to_minor_units() {
+        assert_eq!("12".parse(), Ok(Money::from_minor(1200)));
+        assert_eq!("12.5".parse(), Ok(Money::from_minor(1250)));
+        assert_eq!("12.05".parse(), Ok(Money::from_minor(1205)));
+        assert_eq!("0.01".parse(), Ok(Money::from_minor(1)));
+        assert_eq!("007".parse(), Ok(Money::from_major(7)));
+    }
+
+    #[test]
+    fn only_plain_digits_are_amounts() {
+        for invalid in ["", ".5", "+5", "-5", "1.+5", "1.-5", "1.005", " 1", "1 ", "1,5", "1.5.0", "1e3", "٣"] {
+            assert!(invalid.parse::<Money>().is_err(), "{:?} parsed", invalid);
+        }
+        assert!(format!("{}.00", u64::MAX).parse::<Money>().is_err());
+    }
+
+    #[test]
+    fn amounts_display_with_two_decimals() {
+        assert_eq!(Money::from_minor(5).to_string(), "0.05");
+        assert_eq!(Money::from_minor(123_456).to_string(), "1234.56");
+        assert_eq!(Money::ZERO.to_string(), "0.00");
+    }
+
+    #[test]
+    fn basis_points_round_down() {
+        assert_eq!(Money::from_minor(99).mul_bps(100), Money::ZERO);
+        assert_eq!(Money::from_minor(150).mul_bps(100), Money::from_minor(1));
+        assert_eq!(Money::from_major(10).mul_bps(250), Money::from_minor(25));
+        assert_eq!(Money::from_minor(u64::MAX).checked_mul_bps(20_000), None);
+        assert_eq!(Money::from_minor(u64::MAX).mul_bps(20_000), Money::from_minor(u64::MAX));
+        assert_eq!(Money::from_minor(u64::MAX).checked_mul_bps(MAX_BPS as u32), Some(Money::from_minor(u64::MAX)));
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::money::Money;
+use crate::time;
+
+/// Interest charged on overdrawn balances unless agreed otherwise, in basis points.
+pub const DEFAULT_OVERDRAFT_RATE_BPS: u32 = 1_500; // 15%
+
+/// Lets a user withdraw past a zero balance in one currency, up to `limit`.
+/// The amount owed is tracked in `overdrawn` rather than as a negative balance
+/// and accrues interest at `rate_bps` a year until paid back by deposits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverdraftAgreement {
+   pub currency: Currency,
+   pub limit: Money,
+   pub rate_bps: u32,
+   #[serde(default)]
+   pub overdrawn: Money,
+   /// When interest on `overdrawn` was last charged; `None` while it is zero.
+   #[serde(default)]
+   pub interest_since: Option<SystemTime>,
+}
+
+impl OverdraftAgreement {
+    pub fn new(currency: Currency, limit: Money, rate_bps: u32) -> Self {
+        OverdraftAgreement {
+            currency,
+            limit,
+            rate_bps,
+            overdrawn: Money::ZERO,
+            interest_since: None,
+        }
+    }
+
+    /// How much more can be overdrawn.
+    pub fn headroom(&self) -> Money {
+        self.limit.checked_sub(self.overdrawn).unwrap_or(Money::ZERO)
+    }
+
+    /// Pay back up to `amount` of the overdrawn balance. Returns the amount
+    /// applied.
+    pub fn repay(&mut self, amount: Money) -> Money {
+        let repaid = amount.min(self.overdrawn);
+        self.overdrawn = self.overdrawn.checked_sub(repaid).unwrap_or(Money::ZERO);
+        repaid
+    }
+
+    /// Interest owed on `overdrawn` from `interest_since` up to `until`.
+    pub fn interest_due(&self, until: SystemTime) -> Money {
+        let Some(since) = self.interest_since else {
+            return Money::ZERO;
+        };
+        let elapsed = until.duration_since(since).unwrap_or_default();
+        Money::from_minor(time::prorate(self.overdrawn.mul_bps(self.rate_bps).minor(), elapsed))
+    }
+}
+
+impl fmt::Display for OverdraftAgreement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "overdraft {}: overdrawn {} of {} at {} bps",
+            self.currency, self.overdrawn, self.limit, self.rate_bps
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::time::SECONDS_PER_YEAR;
+
+    fn overdrawn(amount: Money) -> OverdraftAgreement {
+        let mut overdraft = OverdraftAgreement::new(Currency::Usd, Money::from_major(100), 1_000);
+        overdraft.overdrawn = amount;
+        overdraft.interest_since = Some(SystemTime::UNIX_EPOCH);
+        overdraft
+    }
+
+    #[test]
+    fn headroom_is_what_is_left_of_the_limit() {
+        assert_eq!(overdrawn(Money::from_major(30)).headroom(), Money::from_major(70));
+        assert_eq!(overdrawn(Money::from_major(130)).headroom(), Money::ZERO);
+    }
+
+    #[test]
+    fn repayments_are_capped_at_what_is_overdrawn() {
+        let mut overdraft = overdrawn(Money::from_major(30));
+        assert_eq!(overdraft.repay(Money::from_major(10)), Money::from_major(10));
+        assert_eq!(overdraft.repay(Money::from_major(50)), Money::from_major(20));
+        assert_eq!(overdraft.overdrawn, Money::ZERO);
+    }
+
+    #[test]
+    fn interest_is_prorated_from_when_it_was_last_charged() {
+        let year = Duration::from_secs(SECONDS_PER_YEAR);
+        let overdraft = overdrawn(Money::from_major(100));
+        assert_eq!(overdraft.interest_due(SystemTime::UNIX_EPOCH + year / 4), Money::from_minor(250));
+        assert_eq!(overdraft.interest_due(SystemTime::UNIX_EPOCH), Money::ZERO);
+        let unused = OverdraftAgreement::new(Currency::Usd, Money::from_major(100), 1_000);
+        assert_eq!(unused.interest_due(SystemTime::UNIX_EPOCH + year), Money::ZERO);
+    }
+}
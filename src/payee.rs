@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::UserId;
+
+/// How an account is shown when it appears as a counterparty.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Payee {
+   pub name: String,
+   pub category: Option<String>,
+   /// Logo or website of the payee.
+   pub uri: Option<String>,
+}
+
+impl Payee {
+    pub fn new(name: &str, category: Option<&str>) -> Self {
+        Payee {
+            name: name.to_string(),
+            category: category.map(str::to_string),
+            uri: None,
+        }
+    }
+}
+
+impl fmt::Display for Payee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(category) = &self.category {
+            write!(f, " — {}", category)?;
+        }
+        Ok(())
+    }
+}
+
+/// Bank-wide names for accounts that others pay, such as merchants.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PayeeDirectory {
+    payees: BTreeMap<UserId, Payee>,
+}
+
+impl PayeeDirectory {
+    pub fn get(&self, account: UserId) -> Option<&Payee> {
+        self.payees.get(&account)
+    }
+
+    pub fn register(&mut self, account: UserId, payee: Payee) {
+        self.payees.insert(account, payee);
+    }
+
+    pub fn remove(&mut self, account: UserId) -> Option<Payee> {
+        self.payees.remove(&account)
+    }
+
+    /// The payee to show for `account`, preferring a user's own `corrections`
+    /// over the directory entry.
+    pub fn resolve<'a>(&'a self, corrections: &'a BTreeMap<UserId, Payee>, account: UserId) -> Option<&'a Payee> {
+        corrections.get(&account).or_else(|| self.get(account))
+    }
+}
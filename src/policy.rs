@@ -0,0 +1,259 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::fees::FeeSchedule;
+use crate::interest::InterestStrategy;
+use crate::money::{MAX_BPS, Money};
+
+/// How long a proposed policy change waits for a second operator.
+pub const CONFIRMATION_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Highest yearly interest or facility rate a policy may set: 100% a year.
+pub const MAX_RATE_BPS: u32 = 10_000;
+
+/// A treasury policy that only changes once two operators agree on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Policy {
+    Interest(InterestStrategy),
+    Fees(FeeSchedule),
+    /// The liquidity facility's currency, limit and yearly rate.
+    Facility { currency: Currency, limit: Money, rate_bps: u32 },
+}
+
+impl Policy {
+    /// Refuse fees above 100% or with a minimum over their cap, and rates
+    /// above `MAX_RATE_BPS`.
+    pub fn check(&self) -> Result<(), String> {
+        match self {
+            Policy::Interest(InterestStrategy::Compound(schedule)) => check_rate("Interest", schedule.rate_bps),
+            Policy::Interest(InterestStrategy::Legacy) => Ok(()),
+            Policy::Fees(fees) => {
+                check_fee("Entry", fees.entry_bps, fees.min_entry, fees.entry_cap)?;
+                check_fee("Exit", fees.exit_bps, fees.min_exit, fees.exit_cap)
+            }
+            Policy::Facility { rate_bps, .. } => check_rate("Facility", *rate_bps),
+        }
+    }
+}
+
+fn check_rate(name: &str, rate_bps: u32) -> Result<(), String> {
+    if rate_bps > MAX_RATE_BPS {
+        return Err(format!("{} rate of {} bps is over the limit of {} bps a year", name, rate_bps, MAX_RATE_BPS));
+    }
+    Ok(())
+}
+
+fn check_fee(name: &str, bps: u32, minimum: Money, cap: Option<Money>) -> Result<(), String> {
+    if u64::from(bps) > MAX_BPS {
+        return Err(format!("{} fee of {} bps is over {} bps", name, bps, MAX_BPS));
+    }
+    match cap {
+        Some(cap) if minimum > cap => Err(format!("{} fee minimum of {} is over its cap of {}", name, minimum, cap)),
+        _ => Ok(()),
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Policy::Interest(interest) => write!(f, "interest {}", interest),
+            Policy::Fees(fees) => write!(f, "fees {}", fees),
+            Policy::Facility { currency, limit, rate_bps } => {
+                write!(f, "facility of {} {} at {} bps", limit, currency, rate_bps)
+            }
+        }
+    }
+}
+
+/// What became of a proposed change. A change left `Pending` past its
+/// window has expired.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeStatus {
+    Pending,
+    Confirmed { by: String, at: SystemTime },
+    Cancelled { by: String, at: SystemTime },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyChange {
+   pub id: u32,
+   pub policy: Policy,
+   pub proposed_by: String,
+   pub proposed_at: SystemTime,
+   pub status: ChangeStatus,
+}
+
+impl PolicyChange {
+    /// When the change can no longer be confirmed.
+    pub fn expires_at(&self) -> SystemTime {
+        self.proposed_at + CONFIRMATION_WINDOW
+    }
+
+    /// Whether the change is still waiting for a second operator at `now`.
+    pub fn is_pending(&self, now: SystemTime) -> bool {
+        self.status == ChangeStatus::Pending && now < self.expires_at()
+    }
+
+    /// One line for listings, with the state of the change at `now`.
+    pub fn describe(&self, now: SystemTime) -> String {
+        let status = match &self.status {
+            ChangeStatus::Confirmed { by, .. } => format!("confirmed by {}", by),
+            ChangeStatus::Cancelled { by, .. } => format!("cancelled by {}", by),
+            ChangeStatus::Pending => match self.expires_at().duration_since(now) {
+                Ok(left) if !left.is_zero() => {
+                    let minutes = left.as_secs().div_ceil(60);
+                    format!("pending, {}h {:02}m left to confirm", minutes / 60, minutes % 60)
+                }
+                _ => String::from("expired unconfirmed"),
+            },
+        };
+        format!("#{} {}, proposed by {}: {}", self.id, self.policy, self.proposed_by, status)
+    }
+}
+
+/// Every policy change proposed to the treasury, oldest first. Decided and
+/// expired changes are kept as the audit trail of who proposed, confirmed
+/// or cancelled what.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PolicyChanges {
+    changes: Vec<PolicyChange>,
+}
+
+impl PolicyChanges {
+    pub fn iter(&self) -> impl Iterator<Item = &PolicyChange> {
+        self.changes.iter()
+    }
+
+    /// The changes still waiting for a second operator at `now`.
+    pub fn pending(&self, now: SystemTime) -> impl Iterator<Item = &PolicyChange> {
+        self.changes.iter().filter(move |change| change.is_pending(now))
+    }
+
+    /// Put `policy` forward on behalf of `operator`, unless `Policy::check`
+    /// refuses it. Returns the id of the change.
+    pub fn propose(&mut self, policy: Policy, operator: &str, now: SystemTime) -> Result<u32, String> {
+        let operator = operator_name(operator)?;
+        policy.check()?;
+        let id = self.changes.last().map_or(1, |change| change.id + 1);
+        self.changes.push(PolicyChange {
+            id,
+            policy,
+            proposed_by: operator.to_string(),
+            proposed_at: now,
+            status: ChangeStatus::Pending,
+        });
+        Ok(id)
+    }
+
+    /// Confirm change `id` on behalf of `operator`, who must not be the one
+    /// who proposed it. Returns the policy to apply.
+    pub fn confirm(&mut self, id: u32, operator: &str, now: SystemTime) -> Result<Policy, String> {
+        let operator = operator_name(operator)?;
+        let change = self.pending_mut(id, now)?;
+        if change.proposed_by.eq_ignore_ascii_case(operator) {
+            return Err(format!(
+                "Policy change #{} was proposed by {} and must be confirmed by another operator",
+                id, change.proposed_by
+            ));
+        }
+        change.status = ChangeStatus::Confirmed { by: operator.to_string(), at: now };
+        Ok(change.policy)
+    }
+
+    /// Withdraw change `id` on behalf of `operator`, who may be the one who
+    /// proposed it.
+    pub fn cancel(&mut self, id: u32, operator: &str, now: SystemTime) -> Result<Policy, String> {
+        let operator = operator_name(operator)?;
+        let change = self.pending_mut(id, now)?;
+        change.status = ChangeStatus::Cancelled { by: operator.to_string(), at: now };
+        Ok(change.policy)
+    }
+
+    fn pending_mut(&mut self, id: u32, now: SystemTime) -> Result<&mut PolicyChange, String> {
+        let change = self
+            .changes
+            .iter_mut()
+            .find(|change| change.id == id)
+            .ok_or_else(|| format!("Unknown policy change #{}", id))?;
+        match &change.status {
+            ChangeStatus::Confirmed { by, .. } => Err(format!("Policy change #{} was already confirmed by {}", id, by)),
+            ChangeStatus::Cancelled { by, .. } => Err(format!("Policy change #{} was cancelled by {}", id, by)),
+            ChangeStatus::Pending if !change.is_pending(now) => {
+                Err(format!("Policy change #{} expired unconfirmed; propose it again", id))
+            }
+            ChangeStatus::Pending => Ok(change),
+        }
+    }
+}
+
+fn operator_name(operator: &str) -> Result<&str, String> {
+    let operator = operator.trim();
+    if operator.is_empty() {
+        return Err(String::from("Policy changes need the name of the operator making them"));
+    }
+    Ok(operator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interest::{Compounding, InterestSchedule};
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn fees(entry_bps: u32, min_entry: Money, entry_cap: Option<Money>) -> Policy {
+        Policy::Fees(FeeSchedule { entry_bps, min_entry, entry_cap, ..FeeSchedule::default() })
+    }
+
+    #[test]
+    fn unreasonable_proposals_are_refused() {
+        let mut changes = PolicyChanges::default();
+        let refused = [
+            fees(MAX_BPS as u32 + 1, Money::ZERO, None),
+            fees(100, Money::from_major(5), Some(Money::from_major(1))),
+            Policy::Interest(InterestStrategy::Compound(InterestSchedule {
+                rate_bps: MAX_RATE_BPS + 1,
+                compounding: Compounding::Daily,
+            })),
+            Policy::Facility { currency: Currency::Usd, limit: Money::from_major(100), rate_bps: MAX_RATE_BPS + 1 },
+        ];
+        for policy in refused {
+            assert!(changes.propose(policy, "alice", at(0)).is_err(), "{} was accepted", policy);
+        }
+        assert_eq!(changes.iter().count(), 0);
+        let at_the_limits = fees(MAX_BPS as u32, Money::from_major(1), Some(Money::from_major(1)));
+        assert!(changes.propose(at_the_limits, "alice", at(0)).is_ok());
+    }
+
+    #[test]
+    fn changes_need_a_second_operator_within_the_window() {
+        let mut changes = PolicyChanges::default();
+        let policy = fees(100, Money::ZERO, None);
+        let id = changes.propose(policy, "alice", at(0)).unwrap();
+        assert!(changes.confirm(id, " ALICE ", at(1)).is_err());
+        assert_eq!(changes.confirm(id, "bob", at(1)), Ok(policy));
+        assert!(changes.confirm(id, "carol", at(2)).unwrap_err().contains("already confirmed by bob"));
+
+        let late = changes.propose(policy, "alice", at(10)).unwrap();
+        assert_eq!(late, id + 1);
+        let expired = at(10) + CONFIRMATION_WINDOW;
+        assert!(changes.confirm(late, "bob", expired).unwrap_err().contains("expired"));
+        assert_eq!(changes.pending(expired).count(), 0);
+    }
+
+    #[test]
+    fn cancelled_changes_cannot_be_confirmed() {
+        let mut changes = PolicyChanges::default();
+        let id = changes.propose(fees(100, Money::ZERO, None), "alice", at(0)).unwrap();
+        assert!(changes.propose(fees(100, Money::ZERO, None), "  ", at(0)).is_err());
+        changes.cancel(id, "alice", at(1)).unwrap();
+        assert!(changes.confirm(id, "bob", at(2)).unwrap_err().contains("cancelled by alice"));
+        assert!(changes.confirm(id + 1, "bob", at(2)).unwrap_err().contains("Unknown"));
+    }
+}
@@ -0,0 +1,293 @@
+use std::io::{self, BufRead, Write};
+
+use crate::account::AccountKind;
+use crate::bank::Bank;
+use crate::currency::Currency;
+use crate::money::Money;
+use crate::overdraft;
+use crate::payee::Payee;
+use crate::types::{AccountId, LoanId, UserId};
+use crate::user::User;
+
+const HELP: &str = "\
+commands:
+  create <name> [checking | savings | term <days>]
+  open <user> [checking | savings | term <days>]
+  deposit <user> <amount> [currency] [borrowable]
+  withdraw <user> <amount> [currency]
+  transfer <from> <to> <amount> [currency]
+  borrow <borrower> <lender> <amount> [currency]
+  repay <loan> <amount>
+  installments <payer> <payee> <price> <count> [currency]
+  collect <user>
+  interest <user> [currency]
+  show <user> | show treasury | show all
+  overdraft <user> <limit> [currency]
+  income <user>
+  advance <user> <amount>
+  tag <user> <tag> <transaction>...
+  untag <user> <tag> <transaction>...
+  history <user> [tag]
+  payee <user> <category> <name>...
+  correct <user> <payee> [<category> <name>...]
+  sweep [currency]
+  metrics
+  check
+  help
+  quit
+users can be given by name or id; deposit, withdraw, transfer, borrow,
+installments and advance also take an account id such as A3 in place of a user's primary account;
+currency defaults to USD";
+
+/// Read commands line by line from `input` against a fresh in-memory bank,
+/// writing results to `output`, until end of input or `quit`.
+pub fn run(input: impl BufRead, output: &mut impl Write) -> io::Result<()> {
+    let mut bank = Bank::new();
+    write!(output, "> ")?;
+    output.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["quit" | "exit"] => return Ok(()),
+            ["help"] => writeln!(output, "{}", HELP)?,
+            words => match execute(&mut bank, words) {
+                Ok(message) => writeln!(output, "{}", message)?,
+                Err(err) => writeln!(output, "error: {}", err)?,
+            },
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+    writeln!(output)
+}
+
+/// Run one command, given as its words, returning what to print.
+pub(crate) fn execute(bank: &mut Bank, words: &[&str]) -> Result<String, String> {
+    match words {
+        ["create", name, rest @ ..] => {
+            let kind = account_kind(bank, rest)
+                .ok_or("usage: create <name> [checking | savings | term <days>]")??;
+            let id = bank.open_account_of_kind(name, kind)?;
+            Ok(format!("Created user {} ({}).", id, name))
+        }
+        ["open", user, rest @ ..] => {
+            let id = lookup(bank, user)?;
+            let kind = account_kind(bank, rest)
+                .ok_or("usage: open <user> [checking | savings | term <days>]")??;
+            let account = bank.add_account(id, kind)?;
+            Ok(format!("Opened {} account {} for {}.", kind, account, user))
+        }
+        ["deposit", user, amount, rest @ ..] => {
+            let account = lookup_account(bank, user)?;
+            let amount: Money = amount.parse()?;
+            let (currency, rest) = currency_arg(rest)?;
+            let borrowable = match rest {
+                [] => false,
+                ["borrowable"] => true,
+                _ => return Err(String::from("usage: deposit <user> <amount> [currency] [borrowable]")),
+            };
+            bank.deposit_into(account, amount, currency, borrowable)?;
+            Ok(format!("Deposited {} {} for {}.", amount, currency, user))
+        }
+        ["withdraw", user, amount, rest @ ..] => {
+            let account = lookup_account(bank, user)?;
+            let amount: Money = amount.parse()?;
+            let currency = only_currency_arg(rest)?;
+            bank.withdraw_from(account, amount, currency)?;
+            Ok(format!("Withdrew {} {} for {}.", amount, currency, user))
+        }
+        ["transfer", from, to, amount, rest @ ..] => {
+            let source = lookup_account(bank, from)?;
+            let target = lookup_account(bank, to)?;
+            let amount: Money = amount.parse()?;
+            let currency = only_currency_arg(rest)?;
+            bank.transfer_between(source, target, amount, currency)?;
+            Ok(format!("Transferred {} {} from {} to {}.", amount, currency, from, to))
+        }
+        ["borrow", borrower, lender, amount, rest @ ..] => {
+            let account = lookup_account(bank, borrower)?;
+            let lender_account = lookup_account(bank, lender)?;
+            let amount: Money = amount.parse()?;
+            let currency = only_currency_arg(rest)?;
+            let borrowed = bank.borrow_into(account, lender_account, amount, currency)?;
+            Ok(format!("{} borrowed {} {} from {}.", borrower, borrowed, currency, lender))
+        }
+        ["installments", payer, payee, price, count, rest @ ..] => {
+            let account = lookup_account(bank, payer)?;
+            let payee_account = lookup_account(bank, payee)?;
+            let price: Money = price.parse()?;
+            let count: u32 = count.parse().map_err(|_| format!("Invalid installment count '{}'", count))?;
+            let currency = only_currency_arg(rest)?;
+            let plan = bank.buy_in_installments(account, payee_account, price, currency, count)?;
+            Ok(format!("{} bought from {} for {} {} under plan {}.", payer, payee, price, currency, plan))
+        }
+        ["collect", user] => {
+            let id = lookup(bank, user)?;
+            let collected = bank.collect_installments(id)?;
+            Ok(format!("Collected {} in installments from {}.", collected, user))
+        }
+        ["repay", loan, amount] => {
+            let loan = loan
+                .trim_start_matches('L')
+                .parse::<u32>()
+                .map(LoanId::from)
+                .map_err(|_| format!("Invalid loan id '{}'", loan))?;
+            let amount: Money = amount.parse()?;
+            let paid = bank.repay(loan, amount)?;
+            Ok(format!("Repaid {} towards loan {}.", paid, loan))
+        }
+        ["interest", user, rest @ ..] => {
+            let id = lookup(bank, user)?;
+            let currency = only_currency_arg(rest)?;
+            let interest = bank.apply_interest(id, currency)?;
+            Ok(format!("Applied {} {} interest to {}.", interest, currency, user))
+        }
+        ["overdraft", user, limit, rest @ ..] => {
+            let id = lookup(bank, user)?;
+            let limit: Money = limit.parse()?;
+            let currency = only_currency_arg(rest)?;
+            bank.grant_overdraft(id, currency, limit, overdraft::DEFAULT_OVERDRAFT_RATE_BPS)?;
+            Ok(format!("{} may now overdraw {} {}.", user, limit, currency))
+        }
+        ["income", user] => {
+            let id = lookup(bank, user)?;
+            let income = bank
+                .recurring_income(id)?
+                .ok_or_else(|| format!("{} has no recurring income", user))?;
+            let (limit, currency) = bank.advance_limit(id)?;
+            Ok(format!(
+                "{} receives about {} {} every {} day(s); up to {} {} can be advanced.",
+                user,
+                income.amount,
+                income.currency,
+                income.interval.as_secs() / (24 * 60 * 60),
+                limit,
+                currency
+            ))
+        }
+        ["advance", user, amount] => {
+            let account = lookup_account(bank, user)?;
+            let amount: Money = amount.parse()?;
+            bank.take_salary_advance(account, amount)?;
+            Ok(format!("Advanced {} to {}.", amount, user))
+        }
+        ["tag", user, tag, transactions @ ..] if !transactions.is_empty() => {
+            let id = lookup(bank, user)?;
+            let tagged = bank.tag_transactions(id, &transaction_ids(transactions)?, tag)?;
+            Ok(format!("Tagged {} transaction(s) of {} with '{}'.", tagged, user, tag))
+        }
+        ["untag", user, tag, transactions @ ..] if !transactions.is_empty() => {
+            let id = lookup(bank, user)?;
+            let untagged = bank.untag_transactions(id, &transaction_ids(transactions)?, tag)?;
+            Ok(format!("Removed '{}' from {} transaction(s) of {}.", tag, untagged, user))
+        }
+        ["history", user, rest @ ..] => {
+            let id = lookup(bank, user)?;
+            let tag = match rest {
+                [] => None,
+                [tag] => Some(*tag),
+                _ => return Err(String::from("usage: history <user> [tag]")),
+            };
+            Ok(bank.statement(id, tag)?.join("\n"))
+        }
+        ["payee", user, category, name @ ..] if !name.is_empty() => {
+            let id = lookup(bank, user)?;
+            let payee = Payee::new(&name.join(" "), Some(category));
+            let shown = payee.to_string();
+            bank.register_payee(id, payee)?;
+            Ok(format!("{} now shows as {}.", user, shown))
+        }
+        ["correct", user, account] => {
+            let (id, account_id) = (lookup(bank, user)?, lookup(bank, account)?);
+            bank.correct_payee(id, account_id, None)?;
+            Ok(format!("{} now shows {} from the payee directory.", user, account))
+        }
+        ["correct", user, account, category, name @ ..] if !name.is_empty() => {
+            let (id, account_id) = (lookup(bank, user)?, lookup(bank, account)?);
+            let payee = Payee::new(&name.join(" "), Some(category));
+            let shown = payee.to_string();
+            bank.correct_payee(id, account_id, Some(payee))?;
+            Ok(format!("{} now sees {} as {}.", user, account, shown))
+        }
+        ["sweep", rest @ ..] => {
+            let currency = only_currency_arg(rest)?;
+            let swept = bank.sweep_fees(currency)?;
+            Ok(format!("Swept {} {} of fees into the treasury.", swept, currency))
+        }
+        ["metrics"] => Ok(bank.metrics().to_string()),
+        ["check"] => {
+            let mismatches = bank.check_integrity();
+            if mismatches.is_empty() {
+                Ok(format!("{}\nIntegrity check passed.", bank.treasury.aggregates))
+            } else {
+                Err(format!("Integrity check failed:\n  {}", mismatches.join("\n  ")))
+            }
+        }
+        ["show", "treasury"] => Ok(bank.treasury.to_string()),
+        ["show", "all"] => {
+            let mut users: Vec<&User> = bank.users().collect();
+            users.sort_by_key(|user| user.id);
+            let mut lines: Vec<String> = users.iter().map(|user| user.to_string()).collect();
+            lines.push(bank.treasury.to_string());
+            Ok(lines.join("\n"))
+        }
+        ["show", user] => {
+            let id = lookup(bank, user)?;
+            Ok(bank.get_user(id).expect("looked up above").to_string())
+        }
+        _ => Err(format!("unrecognised command '{}', try 'help'", words.join(" "))),
+    }
+}
+
+pub(crate) fn lookup(bank: &Bank, name_or_id: &str) -> Result<UserId, String> {
+    bank.find_user(name_or_id)
+        .ok_or_else(|| format!("Unknown user '{}'", name_or_id))
+}
+
+/// An account id such as `A3`, or else a user whose primary account is meant.
+fn lookup_account(bank: &Bank, word: &str) -> Result<AccountId, String> {
+    if let Some(id) = word.strip_prefix(['A', 'a']).and_then(|id| id.parse::<u32>().ok()) {
+        let account = AccountId::from(id);
+        bank.owner_of(account)?;
+        return Ok(account);
+    }
+    bank.primary_account(lookup(bank, word)?).map_err(String::from)
+}
+
+/// Parse `[checking | savings | term <days>]`; `None` if the words do not fit.
+fn account_kind(bank: &Bank, words: &[&str]) -> Option<Result<AccountKind, String>> {
+    match words {
+        [] => Some(Ok(AccountKind::default())),
+        ["term", days] => Some(
+            days.parse()
+                .map_err(|_| format!("Invalid number of days '{}'", days))
+                .and_then(|days| AccountKind::term_deposit(bank.clock().now(), days)),
+        ),
+        [kind] => Some(kind.parse()),
+        _ => None,
+    }
+}
+
+/// Split an optional leading currency code off the remaining arguments.
+fn currency_arg<'a, 'b>(rest: &'a [&'b str]) -> Result<(Currency, &'a [&'b str]), String> {
+    match rest {
+        [first, tail @ ..] if first.parse::<Currency>().is_ok() => Ok((first.parse()?, tail)),
+        _ => Ok((Currency::Usd, rest)),
+    }
+}
+
+pub(crate) fn only_currency_arg(rest: &[&str]) -> Result<Currency, String> {
+    match rest {
+        [] => Ok(Currency::Usd),
+        [code] => code.parse(),
+        _ => Err(String::from("too many arguments")),
+    }
+}
+
+fn transaction_ids(args: &[&str]) -> Result<Vec<u64>, String> {
+    args.iter()
+        .map(|arg| arg.parse().map_err(|_| format!("Invalid transaction id '{}'", arg)))
+        .collect()
+}
@@ -0,0 +1,63 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::bank::Bank;
+use crate::currency::Currency;
+use crate::money::Money;
+use crate::store::Store;
+
+/// Named starting states a sandbox bank can be reset to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Seed {
+    /// No users at all.
+    #[default]
+    Empty,
+    /// Alice with a borrowable 1000 USD deposit (980 after fees) and Bob with nothing.
+    Lending,
+    /// Alice, Bob and Carol with 1000 USD of test money each.
+    Funded,
+}
+
+impl Seed {
+    /// Populate an empty sandbox bank.
+    pub(crate) fn populate<S: Store>(self, bank: &mut Bank<S>) -> Result<(), String> {
+        match self {
+            Seed::Empty => {}
+            Seed::Lending => {
+                let alice = bank.open_account("Alice")?;
+                bank.open_account("Bob")?;
+                bank.deposit(alice, Money::from_major(1000), Currency::Usd, true)?;
+            }
+            Seed::Funded => {
+                for name in ["Alice", "Bob", "Carol"] {
+                    let id = bank.open_account(name)?;
+                    bank.faucet(id, Money::from_major(1000), Currency::Usd)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Seed::Empty => write!(f, "empty"),
+            Seed::Lending => write!(f, "lending"),
+            Seed::Funded => write!(f, "funded"),
+        }
+    }
+}
+
+impl FromStr for Seed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "empty" => Ok(Seed::Empty),
+            "lending" => Ok(Seed::Lending),
+            "funded" => Ok(Seed::Funded),
+            _ => Err(format!("Unknown seed '{}', expected empty, lending or funded", s)),
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::account::AccountKind;
+use crate::bank::Bank;
+use crate::currency::Currency;
+use crate::fees::FeeSchedule;
+use crate::money::{MAX_BPS, Money};
+use crate::policy::Policy;
+use crate::time::{self, MockClock};
+use crate::types::{AccountId, UserId};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Walkthroughs the `demo` command can run, each against a fresh in-memory
+/// bank on a mock clock and narrating what happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scenario {
+    /// Alice lends to Bob, who pays part of it back a few months later.
+    Lending,
+    /// Depositors rush to take their money out while some of it is lent out
+    /// or locked in a term deposit.
+    BankRun,
+    /// Every fee the treasury charges, from deposits to salary advances and
+    /// merchant fees, up to sweeping them into reserves.
+    Fees,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 3] = [Scenario::Lending, Scenario::BankRun, Scenario::Fees];
+
+    /// One line on what the scenario shows.
+    pub fn summary(self) -> &'static str {
+        match self {
+            Scenario::Lending => "borrowing limits, loan repayment and a year of deposit interest",
+            Scenario::BankRun => "everyone withdrawing at once, against loans, term deposits and savings limits",
+            Scenario::Fees => "entry and exit fees, salary advance and merchant fees, and sweeping them",
+        }
+    }
+
+    pub fn run(self) -> Result<(), String> {
+        let clock = Arc::new(MockClock::new(SystemTime::now()));
+        let mut bank = Bank::new();
+        bank.set_clock(clock.clone());
+        match self {
+            Scenario::Lending => lending(&mut bank, &clock)?,
+            Scenario::BankRun => bank_run(&mut bank, &clock)?,
+            Scenario::Fees => fees(&mut bank, &clock)?,
+        }
+        let mismatches = bank.check_integrity();
+        if !mismatches.is_empty() {
+            return Err(format!("Bank totals drifted: {}", mismatches.join("; ")));
+        }
+        println!("\nBank totals check out.");
+        Ok(())
+    }
+}
+
+fn lending(bank: &mut Bank, clock: &MockClock) -> Result<(), String> {
+    let alice = bank.open_account("Alice")?;
+    let bob = bank.open_account("Bob")?;
+
+    println!("Alice deposits 1000.00 USD and lets others borrow against it.");
+    bank.deposit(alice, Money::from_major(1000), Currency::Usd, true)?;
+    println!("  Alice holds {} after the entry fee.", deposited(bank, alice, Currency::Usd));
+
+    println!("\nBob asks Alice for 200.00 USD.");
+    if let Err(err) = bank.borrow_between(bob, alice, Money::from_major(200), Currency::Usd) {
+        println!("  Refused: {}", err);
+    }
+    println!("Bob settles for 90.00 USD.");
+    let borrowed = bank.borrow_between(bob, alice, Money::from_major(90), Currency::Usd)?;
+    println!("  Bob borrowed {}; Alice holds {}.", borrowed, deposited(bank, alice, Currency::Usd));
+
+    clock.advance(Duration::from_secs(time::SECONDS_PER_YEAR / 2));
+    let loan = bank
+        .get_user(bob)
+        .and_then(|user| user.debts().next())
+        .map(|loan| loan.id)
+        .ok_or("Bob's loan is missing")?;
+    println!("\nSix months later Bob pays back 50.00 USD, interest first.");
+    let paid = bank.repay(loan, Money::from_major(50))?;
+    let owed = bank
+        .get_user(bob)
+        .and_then(|user| user.outstanding(loan, bank.clock()))
+        .unwrap_or(Money::ZERO);
+    println!("  Paid {}; {} is still owed.", paid, owed);
+
+    clock.advance(Duration::from_secs(time::SECONDS_PER_YEAR / 2));
+    println!("\nA year after her deposit, Alice's interest is applied.");
+    let interest = bank.apply_interest(alice, Currency::Usd)?;
+    println!("  Alice earned {} and holds {}.", interest, deposited(bank, alice, Currency::Usd));
+
+    println!("\nWhat the next period looks like:");
+    for id in [alice, bob] {
+        for forecast in bank.interest_forecast(id)? {
+            println!("  {}: {}", name(bank, id), forecast);
+        }
+    }
+    Ok(())
+}
+
+fn bank_run(bank: &mut Bank, clock: &MockClock) -> Result<(), String> {
+    let usd = Currency::Usd;
+    let dana = bank.open_account("Dana")?;
+    let eli = bank.open_account("Eli")?;
+    let fay = bank.open_account("Fay")?;
+    let gus = bank.open_account_of_kind("Gus", AccountKind::Checking)?;
+    let hal = bank.open_account("Hal")?;
+
+    println!("Dana and Eli deposit 2000.00 USD each and lend against it; Fay deposits 1000.00 USD.");
+    bank.deposit(dana, Money::from_major(2000), usd, true)?;
+    bank.deposit(eli, Money::from_major(2000), usd, true)?;
+    bank.deposit(fay, Money::from_major(1000), usd, false)?;
+    println!("Gus puts 3000.00 USD into a 90-day term deposit.");
+    let term = bank.add_account(gus, AccountKind::term_deposit(bank.clock().now(), 90)?)?;
+    bank.deposit_into(term, Money::from_major(3000), usd, false)?;
+    println!("Hal borrows 150.00 USD from each lender and spends it at once.");
+    bank.borrow_between(hal, dana, Money::from_major(150), usd)?;
+    bank.borrow_between(hal, eli, Money::from_major(150), usd)?;
+    withdraw_everything(bank, hal, usd)?;
+    println!("  The treasury holds {} in reserves.", reserves(bank, usd));
+
+    println!("\nA rumour spreads and everyone wants out.");
+    println!("Fay takes her money out 50.00 USD at a time.");
+    for _ in 0..10 {
+        if let Err(err) = bank.withdraw(fay, Money::from_major(50), usd) {
+            println!("  Refused: {}", err);
+            break;
+        }
+        println!("  Fay withdrew 50.00; reserves {}.", reserves(bank, usd));
+    }
+    for id in [dana, eli] {
+        withdraw_everything(bank, id, usd)?;
+    }
+    println!("Gus tries to break his term deposit.");
+    if let Err(err) = bank.withdraw_from(term, Money::from_major(100), usd) {
+        println!("  Refused: {}", err);
+    }
+    println!("  Reserves are down to {}, with {} still lent to Hal.", reserves(bank, usd), lent_out(bank, usd));
+
+    clock.advance(90 * DAY);
+    println!("\nNinety days later the term deposit matures.");
+    let account = bank.primary_account(gus)?;
+    bank.transfer_between(term, account, deposited_in(bank, gus, term, usd)?, usd)?;
+    withdraw_everything(bank, gus, usd)?;
+    println!("  The treasury is left with {} in reserves.", reserves(bank, usd));
+    Ok(())
+}
+
+fn fees(bank: &mut Bank, clock: &MockClock) -> Result<(), String> {
+    let usd = Currency::Usd;
+    let schedule = FeeSchedule {
+        entry_bps: 100,
+        exit_bps: 200,
+        min_entry: Money::from_major(1),
+        min_exit: Money::from_major(2),
+        entry_cap: Some(Money::from_major(25)),
+        exit_cap: Some(Money::from_major(25)),
+    };
+    let change = bank.propose_policy(Policy::Fees(schedule), "Olga")?;
+    bank.confirm_policy(change, "Pat")?;
+    println!("Operator Olga proposes new fees and Pat confirms them: the treasury charges {}.", schedule);
+
+    let acme = bank.open_account_of_kind("Acme Corp", AccountKind::Checking)?;
+    let ivy = bank.open_account_of_kind("Ivy", AccountKind::Checking)?;
+    let shop = bank.open_account_of_kind("Corner Shop", AccountKind::Checking)?;
+    println!("\nAcme makes three deposits.");
+    for amount in [10, 1000, 9000] {
+        let before = deposited(bank, acme, usd);
+        bank.deposit(acme, Money::from_major(amount), usd, false)?;
+        let credited = deposited(bank, acme, usd).checked_sub(before).unwrap_or(Money::ZERO);
+        println!("  Deposited {}, credited {}.", Money::from_major(amount), credited);
+    }
+
+    println!("\nAcme pays Ivy 2000.00 USD a month; transfers are free.");
+    for month in 0..3 {
+        if month > 0 {
+            clock.advance(30 * DAY);
+        }
+        bank.transfer(acme, ivy, Money::from_major(2000), usd)?;
+    }
+    let (limit, currency) = bank.advance_limit(ivy)?;
+    println!("  Ivy's salary is recognised; she can get up to {} {} early.", limit, currency);
+
+    println!("\nIvy buys a 300.00 USD bike from the Corner Shop in 3 installments.");
+    let (account, shop_account) = (bank.primary_account(ivy)?, bank.primary_account(shop)?);
+    bank.buy_in_installments(account, shop_account, Money::from_major(300), usd, 3)?;
+    println!("  The shop is paid {} at once.", deposited(bank, shop, usd));
+
+    clock.advance(15 * DAY);
+    println!("\nHalfway through the month Ivy takes a 500.00 USD salary advance.");
+    bank.take_salary_advance(account, Money::from_major(500))?;
+    clock.advance(15 * DAY);
+    println!("Her next salary repays it, fee included, and the installment due.");
+    bank.transfer(acme, ivy, Money::from_major(2000), usd)?;
+    let before = deposited(bank, ivy, usd);
+    println!("Ivy withdraws 50.00 USD.");
+    bank.withdraw(ivy, Money::from_major(50), usd)?;
+    let paid = before.checked_sub(deposited(bank, ivy, usd)).unwrap_or(Money::ZERO);
+    println!("  That cost her {} in all.", paid);
+
+    let collected = bank.treasury.fees_collected.get(&usd).copied().unwrap_or_default();
+    println!("\nFees collected in USD:");
+    println!("  entry {}, exit {}, advance {}, merchant {}", collected.entry, collected.exit, collected.advance, collected.merchant);
+    let before = reserves(bank, usd);
+    let swept = bank.sweep_fees(usd)?;
+    println!("Sweeping {} into reserves takes them from {} to {}.", swept, before, reserves(bank, usd));
+    Ok(())
+}
+
+/// Withdraw as much of the user's primary account as the exit fee leaves room
+/// for, narrating the outcome.
+fn withdraw_everything(bank: &mut Bank, id: UserId, currency: Currency) -> Result<(), String> {
+    let account = bank.primary_account(id)?;
+    let available = deposited_in(bank, id, account, currency)?;
+    let exit_bps = u64::from(bank.treasury.fees.exit_bps);
+    let mut amount = Money::from_minor(available.minor() * MAX_BPS / (MAX_BPS + exit_bps));
+    while amount > Money::ZERO {
+        let fee = bank.treasury.fees.exit_fee(amount);
+        if amount.checked_add(fee).is_some_and(|total| total <= available) {
+            break;
+        }
+        amount = Money::from_minor(amount.minor() - 1);
+    }
+    match bank.withdraw_from(account, amount, currency) {
+        Ok(_) => println!("  {} withdrew {}; reserves {}.", name(bank, id), amount, reserves(bank, currency)),
+        Err(err) => println!("  {} could not withdraw: {}", name(bank, id), err),
+    }
+    Ok(())
+}
+
+fn deposited(bank: &Bank, id: UserId, currency: Currency) -> Money {
+    bank.get_user(id).map_or(Money::ZERO, |user| user.balance(currency).deposited)
+}
+
+fn deposited_in(bank: &Bank, id: UserId, account: AccountId, currency: Currency) -> Result<Money, String> {
+    let user = bank.get_user(id).ok_or_else(|| format!("Unknown user {}", id))?;
+    Ok(user.account(account)?.balance(currency).deposited)
+}
+
+fn reserves(bank: &Bank, currency: Currency) -> Money {
+    bank.treasury.balance(currency).deposited
+}
+
+/// Principal still out on loans in `currency`.
+fn lent_out(bank: &Bank, currency: Currency) -> Money {
+    bank.users()
+        .flat_map(|user| user.debts())
+        .filter(|loan| loan.currency == currency)
+        .fold(Money::ZERO, |total, loan| total.checked_add(loan.remaining).unwrap_or(total))
+}
+
+fn name(bank: &Bank, id: UserId) -> String {
+    bank.get_user(id).map_or_else(|| id.to_string(), |user| user.name.clone())
+}
+
+impl fmt::Display for Scenario {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scenario::Lending => write!(f, "lending"),
+            Scenario::BankRun => write!(f, "bank-run"),
+            Scenario::Fees => write!(f, "fees"),
+        }
+    }
+}
+
+impl FromStr for Scenario {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lending" => Ok(Scenario::Lending),
+            "bank-run" => Ok(Scenario::BankRun),
+            "fees" => Ok(Scenario::Fees),
+            _ => Err(format!("Unknown scenario '{}', expected lending, bank-run or fees", s)),
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::bank::Bank;
+use crate::currency::Currency;
+use crate::money::Money;
+use crate::repl;
+use crate::time::MockClock;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A scenario file: given/when/then steps run as an executable specification
+/// against a fresh in-memory bank on a mock clock starting at the epoch.
+///
+/// `given` and `when` lines take a REPL command (see `repl`) or `wait <days>`.
+/// A command ending in `fails`, optionally followed by `with <text>`, must be
+/// refused with an error containing that text. `then` lines check one figure:
+/// `balance <user>`, `debt <user>`, `reserves` or `fee revenue`, followed by
+/// `is <amount> [currency]`. `and` repeats the keyword above it, and lines
+/// starting with `#` are comments.
+///
+/// ```text
+/// given create alice checking
+/// and deposit alice 1000
+/// when withdraw alice 5000 fails with Insufficient USD funds
+/// then balance alice is 980.00
+/// and fee revenue is 20.00 USD
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    line: usize,
+    action: Action,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    /// A REPL command and, if it must be refused, text its error contains.
+    Command { words: Vec<String>, fails: Option<String> },
+    Wait { days: u32 },
+    Expect { quantity: Quantity, expected: Money, currency: Currency },
+}
+
+/// A figure a `then` line checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Quantity {
+    /// Deposited across all of a user's accounts.
+    Balance(String),
+    /// Owed on a user's loans, interest included.
+    Debt(String),
+    Reserves,
+    FeeRevenue,
+}
+
+impl Script {
+    /// Run the steps in order, stopping at the first that does not hold, then
+    /// check the bank-wide totals. Returns the number of `then` checks made.
+    pub fn run(&self) -> Result<usize, String> {
+        let clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+        let mut bank = Bank::new();
+        bank.set_clock(clock.clone());
+        let mut checks = 0;
+        for step in &self.steps {
+            step.run(&mut bank, &clock)
+                .map_err(|err| format!("line {}: {}", step.line, err))?;
+            if let Action::Expect { .. } = step.action {
+                checks += 1;
+            }
+        }
+        let mismatches = bank.check_integrity();
+        if !mismatches.is_empty() {
+            return Err(format!("Bank totals drifted: {}", mismatches.join("; ")));
+        }
+        Ok(checks)
+    }
+}
+
+impl Step {
+    fn run(&self, bank: &mut Bank, clock: &MockClock) -> Result<(), String> {
+        match &self.action {
+            Action::Command { words, fails } => {
+                let command = words.join(" ");
+                let words: Vec<&str> = words.iter().map(String::as_str).collect();
+                match (repl::execute(bank, &words), fails) {
+                    (Ok(_), None) => Ok(()),
+                    (Ok(message), Some(_)) => Err(format!("expected '{}' to fail, but: {}", command, message)),
+                    (Err(err), Some(text)) if err.contains(text.as_str()) => Ok(()),
+                    (Err(err), Some(text)) => {
+                        Err(format!("expected '{}' to fail with '{}', but: {}", command, text, err))
+                    }
+                    (Err(err), None) => Err(format!("'{}' failed: {}", command, err)),
+                }
+            }
+            Action::Wait { days } => {
+                clock.advance(DAY * *days);
+                Ok(())
+            }
+            Action::Expect { quantity, expected, currency } => {
+                let actual = quantity.measure(bank, *currency)?;
+                if actual != *expected {
+                    return Err(format!("expected {} to be {} {}, found {}", quantity, expected, currency, actual));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Quantity {
+    fn measure(&self, bank: &Bank, currency: Currency) -> Result<Money, String> {
+        match self {
+            Quantity::Balance(user) => {
+                let id = repl::lookup(bank, user)?;
+                Ok(bank.get_user(id).map_or(Money::ZERO, |user| user.balance(currency).deposited))
+            }
+            Quantity::Debt(user) => {
+                let id = repl::lookup(bank, user)?;
+                let debts = bank.get_user(id).into_iter().flat_map(|user| user.debts());
+                debts
+                    .filter(|loan| loan.currency == currency)
+                    .try_fold(Money::ZERO, |total, loan| total.checked_add(loan.outstanding(bank.clock())))
+                    .ok_or_else(|| String::from("Arithmetic overflow"))
+            }
+            Quantity::Reserves => Ok(bank.treasury.balance(currency).deposited),
+            Quantity::FeeRevenue => Ok(bank.treasury.fee_revenue(currency)),
+        }
+    }
+}
+
+impl FromStr for Script {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut steps = Vec::new();
+        let mut section = None;
+        for (index, text) in s.lines().enumerate() {
+            let line = index + 1;
+            let at = |err: String| format!("line {}: {}", line, err);
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let Some((keyword, rest)) = words.split_first() else {
+                continue;
+            };
+            if keyword.starts_with('#') {
+                continue;
+            }
+            section = match *keyword {
+                "given" | "when" | "then" => Some(*keyword),
+                "and" => Some(section.ok_or_else(|| at(String::from("'and' must follow a given, when or then line")))?),
+                _ => return Err(at(format!("expected given, when, then or and, found '{}'", keyword))),
+            };
+            let action = match section {
+                Some("then") => expectation(rest),
+                _ => action(rest),
+            };
+            steps.push(Step { line, action: action.map_err(at)? });
+        }
+        Ok(Script { steps })
+    }
+}
+
+/// Parse a `given` or `when` line after its keyword.
+fn action(words: &[&str]) -> Result<Action, String> {
+    if let ["wait", days] = words {
+        let days = days.parse().map_err(|_| format!("Invalid number of days '{}'", days))?;
+        return Ok(Action::Wait { days });
+    }
+    let (command, fails) = match words.iter().position(|word| *word == "fails") {
+        Some(at) => match &words[at + 1..] {
+            [] => (&words[..at], Some(String::new())),
+            ["with", text @ ..] if !text.is_empty() => (&words[..at], Some(text.join(" "))),
+            _ => return Err(String::from("expected 'fails' or 'fails with <text>' to end the line")),
+        },
+        None => (words, None),
+    };
+    if command.is_empty() {
+        return Err(String::from("expected a command or 'wait <days>'"));
+    }
+    Ok(Action::Command {
+        words: command.iter().map(ToString::to_string).collect(),
+        fails,
+    })
+}
+
+/// Parse a `then` line after its keyword.
+fn expectation(words: &[&str]) -> Result<Action, String> {
+    let usage = || {
+        String::from(
+            "expected 'balance <user>', 'debt <user>', 'reserves' or 'fee revenue', then 'is <amount> [currency]'",
+        )
+    };
+    let at = words.iter().position(|word| *word == "is").ok_or_else(usage)?;
+    let quantity = match &words[..at] {
+        ["balance", user] => Quantity::Balance(user.to_string()),
+        ["debt", user] => Quantity::Debt(user.to_string()),
+        ["reserves"] => Quantity::Reserves,
+        ["fee", "revenue"] => Quantity::FeeRevenue,
+        _ => return Err(usage()),
+    };
+    let [amount, rest @ ..] = &words[at + 1..] else {
+        return Err(usage());
+    };
+    Ok(Action::Expect {
+        quantity,
+        expected: amount.parse()?,
+        currency: repl::only_currency_arg(rest)?,
+    })
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quantity::Balance(user) => write!(f, "the balance of {}", user),
+            Quantity::Debt(user) => write!(f, "the debt of {}", user),
+            Quantity::Reserves => write!(f, "the treasury reserves"),
+            Quantity::FeeRevenue => write!(f, "the fee revenue"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Script;
+
+    fn run(text: &str) -> Result<usize, String> {
+        text.parse::<Script>()?.run()
+    }
+
+    #[test]
+    fn the_example_scenarios_pass() {
+        assert_eq!(run(include_str!("../scenarios/lending.scenario")), Ok(6));
+    }
+
+    #[test]
+    fn the_documented_example_passes() {
+        let text = "
+            given create alice checking
+            and deposit alice 1000
+            when withdraw alice 5000 fails with Insufficient USD funds
+            then balance alice is 980.00
+            and fee revenue is 20.00 USD
+        ";
+        assert_eq!(run(text), Ok(2));
+    }
+
+    #[test]
+    fn malformed_lines_are_refused_with_their_number() {
+        let refused = [
+            ("and create alice", "line 1: 'and' must follow"),
+            ("# comment\ngiven create alice\nsuppose create bob", "line 3: expected given, when, then or and"),
+            ("then alice is rich", "line 1: expected 'balance <user>'"),
+            ("when deposit alice 10 fails loudly", "line 1: expected 'fails' or 'fails with <text>'"),
+            ("given wait soon", "line 1: Invalid number of days"),
+        ];
+        for (text, error) in refused {
+            let err = text.parse::<Script>().unwrap_err();
+            assert!(err.starts_with(error), "{:?} gave {:?}", text, err);
+        }
+    }
+
+    #[test]
+    fn steps_that_do_not_hold_stop_the_run() {
+        let failing = [
+            ("given create alice\nthen balance alice is 1.00", "line 2: expected the balance of alice to be 1.00 USD"),
+            ("given create alice\nwhen deposit alice 10 fails", "line 2: expected 'deposit alice 10' to fail"),
+            ("given create alice\nwhen withdraw alice 10 fails with overflow", "to fail with 'overflow'"),
+            ("given create alice\nwhen withdraw bob 10", "line 2: 'withdraw bob 10' failed"),
+        ];
+        for (text, error) in failing {
+            let err = run(text).unwrap_err();
+            assert!(err.contains(error), "{:?} gave {:?}", text, err);
+        }
+    }
+}
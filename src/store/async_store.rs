@@ -0,0 +1,148 @@
+use std::future::Future;
+
+use tokio::runtime::Handle;
+
+use crate::bank::Environment;
+use crate::event::RecordedEvent;
+use crate::ledger::Transaction;
+use crate::payee::Payee;
+use crate::store::Store;
+use crate::types::UserId;
+use crate::user::{Treasury, User};
+
+/// `Store` with async methods, for backends built on an async driver. Built
+/// with the `async` feature; see `Store` for what each method must do.
+///
+/// A `Bank` only writes to a synchronous `Store`, so `AsyncBank::open` wraps
+/// the store in a `BlockingStore` and runs the bank on tokio's blocking pool.
+#[allow(dead_code)] // implemented only by tests until an async backend lands
+pub trait AsyncStore {
+    fn load_users(&self) -> impl Future<Output = Result<Vec<User>, String>> + Send;
+    fn save_user(&mut self, user: &User) -> impl Future<Output = Result<(), String>> + Send;
+    fn load_treasury(&self) -> impl Future<Output = Result<Treasury, String>> + Send;
+    fn save_treasury(&mut self, treasury: &Treasury) -> impl Future<Output = Result<(), String>> + Send;
+    fn append_transaction(
+        &mut self,
+        owner: Option<UserId>,
+        transaction: &Transaction,
+    ) -> impl Future<Output = Result<(), String>> + Send;
+    fn save_tags(&mut self, transaction: &Transaction) -> impl Future<Output = Result<(), String>> + Send;
+    fn save_payee(
+        &mut self,
+        account: UserId,
+        payee: Option<&Payee>,
+    ) -> impl Future<Output = Result<(), String>> + Send;
+    fn clear(&mut self) -> impl Future<Output = Result<(), String>> + Send;
+
+    fn load_environment(&self) -> impl Future<Output = Result<Environment, String>> + Send {
+        async { Ok(Environment::Production) }
+    }
+
+    fn save_environment(&mut self, _environment: Environment) -> impl Future<Output = Result<(), String>> + Send {
+        async { Ok(()) }
+    }
+
+    fn append_event(&mut self, _event: &RecordedEvent) -> impl Future<Output = Result<(), String>> + Send {
+        async { Ok(()) }
+    }
+
+    fn load_events(&self) -> impl Future<Output = Result<Option<Vec<RecordedEvent>>, String>> + Send {
+        async { Ok(None) }
+    }
+
+    fn begin(&mut self) -> impl Future<Output = Result<(), String>> + Send {
+        async { Ok(()) }
+    }
+
+    fn commit(&mut self) -> impl Future<Output = Result<(), String>> + Send {
+        async { Ok(()) }
+    }
+
+    fn rollback(&mut self) -> impl Future<Output = Result<(), String>> + Send {
+        async { Ok(()) }
+    }
+}
+
+/// An `AsyncStore` used as a `Store`: each call blocks its thread until the
+/// store's future completes on `runtime`. It must only be called off the
+/// runtime's worker threads, as `AsyncBank` does by writing on the blocking
+/// pool.
+#[derive(Debug)]
+#[allow(dead_code)] // made only by `AsyncBank::open`
+pub struct BlockingStore<S> {
+    store: S,
+    runtime: Handle,
+}
+
+#[allow(dead_code)]
+impl<S: AsyncStore> BlockingStore<S> {
+    pub fn new(store: S, runtime: Handle) -> Self {
+        BlockingStore { store, runtime }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.store
+    }
+}
+
+impl<S: AsyncStore> Store for BlockingStore<S> {
+    fn load_users(&self) -> Result<Vec<User>, String> {
+        self.runtime.block_on(self.store.load_users())
+    }
+
+    fn save_user(&mut self, user: &User) -> Result<(), String> {
+        self.runtime.block_on(self.store.save_user(user))
+    }
+
+    fn load_treasury(&self) -> Result<Treasury, String> {
+        self.runtime.block_on(self.store.load_treasury())
+    }
+
+    fn save_treasury(&mut self, treasury: &Treasury) -> Result<(), String> {
+        self.runtime.block_on(self.store.save_treasury(treasury))
+    }
+
+    fn append_transaction(&mut self, owner: Option<UserId>, transaction: &Transaction) -> Result<(), String> {
+        self.runtime.block_on(self.store.append_transaction(owner, transaction))
+    }
+
+    fn save_tags(&mut self, transaction: &Transaction) -> Result<(), String> {
+        self.runtime.block_on(self.store.save_tags(transaction))
+    }
+
+    fn save_payee(&mut self, account: UserId, payee: Option<&Payee>) -> Result<(), String> {
+        self.runtime.block_on(self.store.save_payee(account, payee))
+    }
+
+    fn clear(&mut self) -> Result<(), String> {
+        self.runtime.block_on(self.store.clear())
+    }
+
+    fn load_environment(&self) -> Result<Environment, String> {
+        self.runtime.block_on(self.store.load_environment())
+    }
+
+    fn save_environment(&mut self, environment: Environment) -> Result<(), String> {
+        self.runtime.block_on(self.store.save_environment(environment))
+    }
+
+    fn append_event(&mut self, event: &RecordedEvent) -> Result<(), String> {
+        self.runtime.block_on(self.store.append_event(event))
+    }
+
+    fn load_events(&self) -> Result<Option<Vec<RecordedEvent>>, String> {
+        self.runtime.block_on(self.store.load_events())
+    }
+
+    fn begin(&mut self) -> Result<(), String> {
+        self.runtime.block_on(self.store.begin())
+    }
+
+    fn commit(&mut self) -> Result<(), String> {
+        self.runtime.block_on(self.store.commit())
+    }
+
+    fn rollback(&mut self) -> Result<(), String> {
+        self.runtime.block_on(self.store.rollback())
+    }
+}
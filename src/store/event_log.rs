@@ -0,0 +1,184 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::event::{self, RecordedEvent};
+use crate::ledger::Transaction;
+use crate::payee::Payee;
+use crate::store::Store;
+use crate::types::UserId;
+use crate::user::{Treasury, User};
+
+/// Keeps nothing but an append-only log of events, one JSON `RecordedEvent`
+/// per line. The bank is replayed from the log whenever it is opened, so the
+/// state writes every other store makes are dropped here; a snapshot such as
+/// `export` or `Bank::save_json` can be derived from the log at any time.
+///
+/// Events appended between `begin` and `commit` are held back and written
+/// together on `commit`, so an operation that fails partway leaves none of
+/// its events in the log.
+#[derive(Debug)]
+pub struct EventLogStore {
+    path: PathBuf,
+    file: File,
+    /// The lines of the open transaction, if there is one.
+    pending: Option<String>,
+}
+
+impl EventLogStore {
+    /// Open (or create) the log at `path`.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| format!("Cannot open {}: {}", path.display(), err))?;
+        Ok(EventLogStore {
+            path: path.to_path_buf(),
+            file,
+            pending: None,
+        })
+    }
+
+    fn write(&mut self, lines: &str) -> Result<(), String> {
+        self.file
+            .write_all(lines.as_bytes())
+            .map_err(|err| format!("Cannot write {}: {}", self.path.display(), err))
+    }
+}
+
+impl Store for EventLogStore {
+    fn load_users(&self) -> Result<Vec<User>, String> {
+        Ok(Vec::new())
+    }
+
+    fn save_user(&mut self, _user: &User) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn load_treasury(&self) -> Result<Treasury, String> {
+        Ok(Treasury::default())
+    }
+
+    fn save_treasury(&mut self, _treasury: &Treasury) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn append_transaction(&mut self, _owner: Option<UserId>, _transaction: &Transaction) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn save_tags(&mut self, _transaction: &Transaction) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn save_payee(&mut self, _account: UserId, _payee: Option<&Payee>) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// The log is never truncated; a reset is recorded as an event like any
+    /// other change.
+    fn clear(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn append_event(&mut self, event: &RecordedEvent) -> Result<(), String> {
+        let mut line = serde_json::to_string(event).map_err(|err| format!("Cannot serialize event: {}", err))?;
+        line.push('\n');
+        match &mut self.pending {
+            Some(pending) => {
+                pending.push_str(&line);
+                Ok(())
+            }
+            None => self.write(&line),
+        }
+    }
+
+    fn load_events(&self) -> Result<Option<Vec<RecordedEvent>>, String> {
+        let data = match fs::read_to_string(&self.path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(format!("Cannot read {}: {}", self.path.display(), err)),
+        };
+        let events = data
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                event::decode(line).map_err(|err| format!("{} line {}: {}", self.path.display(), index + 1, err))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Some(events))
+    }
+
+    fn begin(&mut self) -> Result<(), String> {
+        self.pending = Some(String::new());
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), String> {
+        match self.pending.take() {
+            Some(lines) if !lines.is_empty() => self.write(&lines),
+            _ => Ok(()),
+        }
+    }
+
+    fn rollback(&mut self) -> Result<(), String> {
+        self.pending = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+
+    use super::EventLogStore;
+    use crate::event::{BankEvent, EVENT_VERSION, NextIds, RecordedEvent};
+    use crate::store::Store;
+
+    fn log_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.jsonl", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn reset() -> RecordedEvent {
+        RecordedEvent {
+            version: EVENT_VERSION,
+            at: SystemTime::UNIX_EPOCH,
+            ids: NextIds::current(),
+            event: BankEvent::Reset,
+        }
+    }
+
+    #[test]
+    fn events_are_written_on_commit() {
+        let path = log_path("event-log-commit");
+        let mut store = EventLogStore::open(&path).unwrap();
+        store.begin().unwrap();
+        store.append_event(&reset()).unwrap();
+        store.append_event(&reset()).unwrap();
+        assert_eq!(store.load_events().unwrap().unwrap().len(), 0);
+        store.commit().unwrap();
+        let events = store.load_events().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn rolled_back_events_are_dropped() {
+        let path = log_path("event-log-rollback");
+        let mut store = EventLogStore::open(&path).unwrap();
+        store.begin().unwrap();
+        store.append_event(&reset()).unwrap();
+        store.rollback().unwrap();
+        store.begin().unwrap();
+        store.commit().unwrap();
+        let events = store.load_events().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(events.is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::bank::Environment;
+use crate::event::RecordedEvent;
+use crate::ledger::Transaction;
+use crate::payee::Payee;
+use crate::types::UserId;
+use crate::user::{Treasury, User};
+
+#[cfg(feature = "async")]
+pub mod async_store;
+pub mod event_log;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// Persistence backend for a `Bank`.
+///
+/// `save_user` covers everything on a `User` except its transaction history,
+/// which is written entry by entry through `append_transaction`. An `owner`
+/// of `None` means the treasury ledger. Entries never change once appended,
+/// apart from their tags, which `save_tags` replaces. `save_treasury` leaves
+/// out the payee directory, which `save_payee` writes one entry at a time.
+///
+/// A store can also keep the events of every operation with `append_event`.
+/// If `load_events` returns them, the bank is rebuilt from them on open and
+/// the state it saves is only a derived copy.
+pub trait Store {
+    fn load_users(&self) -> Result<Vec<User>, String>;
+    fn save_user(&mut self, user: &User) -> Result<(), String>;
+    fn load_treasury(&self) -> Result<Treasury, String>;
+    fn save_treasury(&mut self, treasury: &Treasury) -> Result<(), String>;
+    fn append_transaction(&mut self, owner: Option<UserId>, transaction: &Transaction) -> Result<(), String>;
+    fn save_tags(&mut self, transaction: &Transaction) -> Result<(), String>;
+    /// Store `payee` as the directory entry for `account`, or remove the entry
+    /// if it is `None`.
+    fn save_payee(&mut self, account: UserId, payee: Option<&Payee>) -> Result<(), String>;
+
+    /// Delete everything held in the store.
+    fn clear(&mut self) -> Result<(), String>;
+
+    fn load_environment(&self) -> Result<Environment, String> {
+        Ok(Environment::Production)
+    }
+
+    fn save_environment(&mut self, _environment: Environment) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Add `event` to the end of the store's event log, if it keeps one.
+    /// Called inside the operation's `begin`/`commit` group, after its other
+    /// writes. A store that keeps a log must hold events back until `commit`
+    /// and drop them on `rollback`, so the event and the change it records
+    /// stand or fall together.
+    fn append_event(&mut self, _event: &RecordedEvent) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Every event appended so far, oldest first, if the bank should be
+    /// rebuilt from them rather than loaded.
+    fn load_events(&self) -> Result<Option<Vec<RecordedEvent>>, String> {
+        Ok(None)
+    }
+
+    /// Start grouping writes so they land together or not at all.
+    fn begin(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// The default store: state lives only in the `Bank` itself, so there is
+/// nothing to load and writes are discarded.
+#[derive(Debug, Default)]
+pub struct MemoryStore;
+
+impl Store for MemoryStore {
+    fn load_users(&self) -> Result<Vec<User>, String> {
+        Ok(Vec::new())
+    }
+
+    fn save_user(&mut self, _user: &User) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn load_treasury(&self) -> Result<Treasury, String> {
+        Ok(Treasury::default())
+    }
+
+    fn save_treasury(&mut self, _treasury: &Treasury) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn append_transaction(&mut self, _owner: Option<UserId>, _transaction: &Transaction) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn save_tags(&mut self, _transaction: &Transaction) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn save_payee(&mut self, _account: UserId, _payee: Option<&Payee>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
@@ -0,0 +1,1136 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::account::{Account, AccountKind};
+use crate::advance::SalaryAdvance;
+use crate::bank::Environment;
+use crate::currency::{Balance, Currency};
+use crate::fees::FeesCollected;
+use crate::installment::InstallmentPlan;
+use crate::ledger::Transaction;
+use crate::loan::Loan;
+use crate::money::Money;
+use crate::overdraft::OverdraftAgreement;
+use crate::payee::Payee;
+use crate::types::{AccountId, LoanId, PlanId, UserId};
+use crate::store::Store;
+use crate::user::{Treasury, User};
+
+/// Schema steps applied in order; `PRAGMA user_version` records how many ran.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE meta (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        next_user_id INTEGER NOT NULL
+    );
+    CREATE TABLE users (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        has_deposited INTEGER NOT NULL,
+        borrowable INTEGER NOT NULL
+    );
+    CREATE TABLE balances (
+        owner INTEGER,
+        currency TEXT NOT NULL,
+        deposited INTEGER NOT NULL,
+        withdrawn INTEGER NOT NULL,
+        UNIQUE (owner, currency)
+    );
+    CREATE TABLE facility (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        limit_minor INTEGER NOT NULL,
+        rate_bps INTEGER NOT NULL,
+        drawn INTEGER NOT NULL,
+        interest_expense INTEGER NOT NULL
+    );
+    CREATE TABLE loans (
+        id INTEGER PRIMARY KEY,
+        borrower INTEGER NOT NULL,
+        lender INTEGER NOT NULL,
+        principal INTEGER NOT NULL,
+        currency TEXT NOT NULL,
+        rate_bps INTEGER NOT NULL,
+        start_nanos INTEGER NOT NULL,
+        outstanding INTEGER NOT NULL
+    );
+    CREATE TABLE transactions (
+        id INTEGER PRIMARY KEY,
+        owner INTEGER,
+        timestamp_nanos INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        currency TEXT NOT NULL,
+        fee INTEGER NOT NULL,
+        counterparty INTEGER
+    );
+    CREATE INDEX transactions_owner ON transactions (owner, id);",
+    // The next user id is derived from the users table.
+    "DROP TABLE meta;",
+    "CREATE TABLE settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    "ALTER TABLE loans ADD COLUMN accrued_interest INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE loans ADD COLUMN last_accrued_nanos INTEGER;",
+    "ALTER TABLE balances ADD COLUMN interest_since_nanos INTEGER;",
+    "CREATE TABLE fees_collected (
+        currency TEXT PRIMARY KEY,
+        entry INTEGER NOT NULL,
+        exit INTEGER NOT NULL
+    );",
+    "CREATE TABLE overdrafts (
+        owner INTEGER PRIMARY KEY,
+        currency TEXT NOT NULL,
+        limit_minor INTEGER NOT NULL,
+        rate_bps INTEGER NOT NULL,
+        overdrawn INTEGER NOT NULL,
+        interest_since_nanos INTEGER
+    );",
+    "ALTER TABLE users ADD COLUMN account_kind TEXT NOT NULL DEFAULT 'savings';
+    ALTER TABLE users ADD COLUMN maturity_nanos INTEGER;
+    ALTER TABLE users ADD COLUMN window_start_nanos INTEGER;
+    ALTER TABLE users ADD COLUMN window_withdrawals INTEGER NOT NULL DEFAULT 0;",
+    "CREATE TABLE transaction_tags (
+        transaction_id INTEGER NOT NULL,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (transaction_id, tag)
+    );",
+    "CREATE TABLE payees (
+        account INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        category TEXT,
+        uri TEXT
+    );
+    CREATE TABLE payee_corrections (
+        owner INTEGER NOT NULL,
+        account INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        category TEXT,
+        uri TEXT,
+        PRIMARY KEY (owner, account)
+    );",
+    "CREATE TABLE accounts (
+        id INTEGER PRIMARY KEY,
+        owner INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        maturity_nanos INTEGER,
+        borrowable INTEGER NOT NULL,
+        window_start_nanos INTEGER,
+        window_withdrawals INTEGER NOT NULL
+    );
+    INSERT INTO accounts (id, owner, kind, maturity_nanos, borrowable, window_start_nanos, window_withdrawals)
+        SELECT id, id, account_kind, maturity_nanos, borrowable, window_start_nanos, window_withdrawals FROM users;
+    CREATE TABLE account_balances (
+        account INTEGER NOT NULL,
+        currency TEXT NOT NULL,
+        deposited INTEGER NOT NULL,
+        withdrawn INTEGER NOT NULL,
+        interest_since_nanos INTEGER,
+        PRIMARY KEY (account, currency)
+    );
+    INSERT INTO account_balances (account, currency, deposited, withdrawn, interest_since_nanos)
+        SELECT owner, currency, deposited, withdrawn, interest_since_nanos FROM balances WHERE owner IS NOT NULL;
+    DELETE FROM balances WHERE owner IS NOT NULL;
+    ALTER TABLE users DROP COLUMN borrowable;
+    ALTER TABLE users DROP COLUMN account_kind;
+    ALTER TABLE users DROP COLUMN maturity_nanos;
+    ALTER TABLE users DROP COLUMN window_start_nanos;
+    ALTER TABLE users DROP COLUMN window_withdrawals;",
+    "CREATE TABLE salary_advances (
+        owner INTEGER PRIMARY KEY,
+        currency TEXT NOT NULL,
+        owed INTEGER NOT NULL,
+        fee INTEGER NOT NULL,
+        taken_nanos INTEGER NOT NULL
+    );
+    ALTER TABLE fees_collected ADD COLUMN advance INTEGER NOT NULL DEFAULT 0;",
+    "CREATE TABLE installment_plans (
+        id INTEGER PRIMARY KEY,
+        payer INTEGER NOT NULL,
+        payee INTEGER NOT NULL,
+        account INTEGER NOT NULL,
+        currency TEXT NOT NULL,
+        price INTEGER NOT NULL,
+        installments INTEGER NOT NULL,
+        paid INTEGER NOT NULL,
+        remaining INTEGER NOT NULL,
+        fee INTEGER NOT NULL,
+        start_nanos INTEGER NOT NULL
+    );
+    ALTER TABLE fees_collected ADD COLUMN merchant INTEGER NOT NULL DEFAULT 0;",
+    // Loans made before this are repaid between primary accounts.
+    "ALTER TABLE loans ADD COLUMN account INTEGER;
+    ALTER TABLE loans ADD COLUMN lender_account INTEGER;",
+    "ALTER TABLE facility ADD COLUMN currency TEXT NOT NULL DEFAULT 'USD';
+    ALTER TABLE facility ADD COLUMN interest_since_nanos INTEGER;",
+];
+
+/// Persists users, their accounts, treasury totals and the ledger in an SQLite
+/// database. Rows with a NULL `owner` belong to the treasury.
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Open (or create) the database at `path` and bring its schema up to date.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let conn = Connection::open(path)
+            .map_err(|err| format!("Cannot open {}: {}", path.display(), err))?;
+        let mut store = SqliteStore { conn };
+        store.migrate()?;
+        Ok(store)
+    }
+
+    fn migrate(&mut self) -> Result<(), String> {
+        let applied: usize = self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(db_error)?;
+        let tx = self.conn.transaction().map_err(db_error)?;
+        for (version, sql) in MIGRATIONS.iter().enumerate().skip(applied) {
+            tx.execute_batch(sql).map_err(db_error)?;
+            tx.pragma_update(None, "user_version", version + 1)
+                .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)
+    }
+
+    /// The user with `id` and everything they own, if they exist.
+    pub fn load_user(&self, id: UserId) -> Result<Option<User>, String> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT name, has_deposited FROM users WHERE id = ?1",
+                params![u32::from(id)],
+                |row| Ok((row.get::<_, String>(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(db_error)?;
+        let Some((name, has_deposited)) = row else {
+            return Ok(None);
+        };
+        Ok(Some(User {
+            id,
+            name,
+            accounts: self.load_accounts(id)?,
+            has_deposited,
+            loans: self.load_loans(id)?,
+            overdraft: self.load_overdraft(id)?,
+            salary_advance: self.load_salary_advance(id)?,
+            installment_plans: self.load_installment_plans(id)?,
+            payee_corrections: self.load_payees(
+                "SELECT account, name, category, uri FROM payee_corrections WHERE owner = ?1",
+                params![u32::from(id)],
+            )?,
+            transactions: self.load_transactions(Some(id))?,
+        }))
+    }
+
+    fn load_balances(&self, owner: Option<UserId>) -> Result<HashMap<Currency, Balance>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT currency, deposited, withdrawn, interest_since_nanos FROM balances WHERE owner IS ?1")
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map(params![owner.map(u32::from)], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    money(row.get(1)?),
+                    money(row.get(2)?),
+                    row.get::<_, Option<i64>>(3)?.map(time),
+                ))
+            })
+            .map_err(db_error)?;
+        let mut balances = HashMap::new();
+        for row in rows {
+            let (currency, deposited, withdrawn, interest_since) = row.map_err(db_error)?;
+            balances.insert(
+                currency.parse()?,
+                Balance {
+                    deposited,
+                    withdrawn,
+                    interest_since,
+                },
+            );
+        }
+        Ok(balances)
+    }
+
+    fn load_accounts(&self, owner: UserId) -> Result<Vec<Account>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, kind, maturity_nanos, borrowable, window_start_nanos, window_withdrawals
+                 FROM accounts WHERE owner = ?1 ORDER BY id",
+            )
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map(params![u32::from(owner)], |row| {
+                Ok((
+                    row.get::<_, u32>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<i64>>(2)?.map(time),
+                    row.get(3)?,
+                    row.get::<_, Option<i64>>(4)?.map(time),
+                    row.get::<_, u32>(5)?,
+                ))
+            })
+            .map_err(db_error)?;
+        let mut accounts = Vec::new();
+        for row in rows {
+            let (id, kind, maturity, borrowable, window_start, window_withdrawals) = row.map_err(db_error)?;
+            let id = AccountId::from(id);
+            let kind = match (kind.as_str(), maturity) {
+                ("term", Some(maturity)) => AccountKind::TermDeposit { maturity },
+                ("term", None) => return Err(format!("Term deposit {} has no maturity", id)),
+                (kind, _) => kind.parse()?,
+            };
+            accounts.push(Account {
+                id,
+                kind,
+                balances: self.load_account_balances(id)?,
+                borrowable,
+                window_start,
+                window_withdrawals,
+            });
+        }
+        Ok(accounts)
+    }
+
+    fn load_account_balances(&self, account: AccountId) -> Result<HashMap<Currency, Balance>, String> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT currency, deposited, withdrawn, interest_since_nanos FROM account_balances WHERE account = ?1",
+            )
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map(params![u32::from(account)], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    money(row.get(1)?),
+                    money(row.get(2)?),
+                    row.get::<_, Option<i64>>(3)?.map(time),
+                ))
+            })
+            .map_err(db_error)?;
+        let mut balances = HashMap::new();
+        for row in rows {
+            let (currency, deposited, withdrawn, interest_since) = row.map_err(db_error)?;
+            balances.insert(
+                currency.parse()?,
+                Balance {
+                    deposited,
+                    withdrawn,
+                    interest_since,
+                },
+            );
+        }
+        Ok(balances)
+    }
+
+    fn load_loans(&self, id: UserId) -> Result<Vec<Loan>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, borrower, lender, principal, currency, rate_bps, start_nanos, outstanding,
+                     accrued_interest, last_accrued_nanos, account, lender_account
+                 FROM loans WHERE borrower = ?1 OR lender = ?1 ORDER BY id",
+            )
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map(params![u32::from(id)], |row| {
+                Ok((
+                    row.get::<_, u32>(0)?,
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, u32>(2)?,
+                    money(row.get(3)?),
+                    row.get::<_, String>(4)?,
+                    row.get::<_, u32>(5)?,
+                    time(row.get(6)?),
+                    money(row.get(7)?),
+                    money(row.get(8)?),
+                    row.get::<_, Option<i64>>(9)?.map(time),
+                    row.get::<_, Option<u32>>(10)?.map(AccountId::from),
+                    row.get::<_, Option<u32>>(11)?.map(AccountId::from),
+                ))
+            })
+            .map_err(db_error)?;
+        let mut loans = Vec::new();
+        for row in rows {
+            let (
+                id,
+                borrower,
+                lender,
+                principal,
+                currency,
+                rate_bps,
+                start,
+                remaining,
+                accrued_interest,
+                last_accrued,
+                account,
+                lender_account,
+            ) = row.map_err(db_error)?;
+            loans.push(Loan {
+                id: LoanId::from(id),
+                borrower: UserId::from(borrower),
+                lender: UserId::from(lender),
+                account,
+                lender_account,
+                principal,
+                currency: currency.parse()?,
+                rate_bps,
+                start,
+                remaining,
+                accrued_interest,
+                last_accrued,
+            });
+        }
+        Ok(loans)
+    }
+
+    fn load_overdraft(&self, id: UserId) -> Result<Option<OverdraftAgreement>, String> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT currency, limit_minor, rate_bps, overdrawn, interest_since_nanos
+                 FROM overdrafts WHERE owner = ?1",
+                params![u32::from(id)],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        money(row.get(1)?),
+                        row.get::<_, u32>(2)?,
+                        money(row.get(3)?),
+                        row.get::<_, Option<i64>>(4)?.map(time),
+                    ))
+                },
+            )
+            .optional()
+            .map_err(db_error)?;
+        let Some((currency, limit, rate_bps, overdrawn, interest_since)) = row else {
+            return Ok(None);
+        };
+        Ok(Some(OverdraftAgreement {
+            currency: currency.parse()?,
+            limit,
+            rate_bps,
+            overdrawn,
+            interest_since,
+        }))
+    }
+
+    fn load_salary_advance(&self, id: UserId) -> Result<Option<SalaryAdvance>, String> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT currency, owed, fee, taken_nanos FROM salary_advances WHERE owner = ?1",
+                params![u32::from(id)],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        money(row.get(1)?),
+                        money(row.get(2)?),
+                        time(row.get(3)?),
+                    ))
+                },
+            )
+            .optional()
+            .map_err(db_error)?;
+        let Some((currency, owed, fee, taken)) = row else {
+            return Ok(None);
+        };
+        Ok(Some(SalaryAdvance {
+            currency: currency.parse()?,
+            owed,
+            fee,
+            taken,
+        }))
+    }
+
+    fn load_installment_plans(&self, id: UserId) -> Result<Vec<InstallmentPlan>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, payee, account, currency, price, installments, paid, remaining, fee, start_nanos
+                 FROM installment_plans WHERE payer = ?1 ORDER BY id",
+            )
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map(params![u32::from(id)], |row| {
+                Ok((
+                    row.get::<_, u32>(0)?,
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, u32>(2)?,
+                    row.get::<_, String>(3)?,
+                    money(row.get(4)?),
+                    row.get::<_, u32>(5)?,
+                    row.get::<_, u32>(6)?,
+                    money(row.get(7)?),
+                    money(row.get(8)?),
+                    time(row.get(9)?),
+                ))
+            })
+            .map_err(db_error)?;
+        let mut plans = Vec::new();
+        for row in rows {
+            let (plan, payee, account, currency, price, installments, paid, remaining, fee, start) =
+                row.map_err(db_error)?;
+            plans.push(InstallmentPlan {
+                id: PlanId::from(plan),
+                payer: id,
+                payee: UserId::from(payee),
+                account: AccountId::from(account),
+                currency: currency.parse()?,
+                price,
+                installments,
+                paid,
+                remaining,
+                fee,
+                start,
+            });
+        }
+        Ok(plans)
+    }
+
+    fn load_fees_collected(&self) -> Result<HashMap<Currency, FeesCollected>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT currency, entry, exit, advance, merchant FROM fees_collected")
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    money(row.get(1)?),
+                    money(row.get(2)?),
+                    money(row.get(3)?),
+                    money(row.get(4)?),
+                ))
+            })
+            .map_err(db_error)?;
+        let mut collected = HashMap::new();
+        for row in rows {
+            let (currency, entry, exit, advance, merchant) = row.map_err(db_error)?;
+            collected.insert(currency.parse()?, FeesCollected { entry, exit, advance, merchant });
+        }
+        Ok(collected)
+    }
+
+    fn load_transactions(&self, owner: Option<UserId>) -> Result<Vec<Transaction>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, timestamp_nanos, kind, amount, currency, fee, counterparty
+                 FROM transactions WHERE owner IS ?1 ORDER BY id",
+            )
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map(params![owner.map(u32::from)], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    time(row.get(1)?),
+                    row.get::<_, String>(2)?,
+                    money(row.get(3)?),
+                    row.get::<_, String>(4)?,
+                    money(row.get(5)?),
+                    row.get::<_, Option<u32>>(6)?,
+                ))
+            })
+            .map_err(db_error)?;
+        let mut transactions = Vec::new();
+        for row in rows {
+            let (id, timestamp, kind, amount, currency, fee, counterparty) = row.map_err(db_error)?;
+            transactions.push(Transaction {
+                id: id as u64,
+                timestamp,
+                kind: decode(kind)?,
+                amount,
+                currency: currency.parse()?,
+                fee,
+                counterparty: counterparty.map(UserId::from),
+                tags: self.load_tags(id)?,
+            });
+        }
+        Ok(transactions)
+    }
+
+    fn load_tags(&self, transaction_id: i64) -> Result<BTreeSet<String>, String> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT tag FROM transaction_tags WHERE transaction_id = ?1")
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map(params![transaction_id], |row| row.get::<_, String>(0))
+            .map_err(db_error)?;
+        rows.collect::<Result<_, _>>().map_err(db_error)
+    }
+
+    /// Payees keyed by account from `sql`, which selects account, name,
+    /// category and uri, in that order.
+    fn load_payees(&self, sql: &str, params: impl rusqlite::Params) -> Result<BTreeMap<UserId, Payee>, String> {
+        let mut stmt = self.conn.prepare(sql).map_err(db_error)?;
+        let rows = stmt
+            .query_map(params, |row| {
+                Ok((
+                    UserId::from(row.get::<_, u32>(0)?),
+                    Payee {
+                        name: row.get(1)?,
+                        category: row.get(2)?,
+                        uri: row.get(3)?,
+                    },
+                ))
+            })
+            .map_err(db_error)?;
+        rows.collect::<Result<_, _>>().map_err(db_error)
+    }
+}
+
+impl Store for SqliteStore {
+    fn load_users(&self) -> Result<Vec<User>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id FROM users ORDER BY id")
+            .map_err(db_error)?;
+        let ids = stmt
+            .query_map([], |row| row.get::<_, u32>(0))
+            .map_err(db_error)?
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(db_error)?;
+        let mut users = Vec::new();
+        for id in ids {
+            let id = UserId::from(id);
+            users.extend(self.load_user(id)?);
+        }
+        Ok(users)
+    }
+
+    fn save_user(&mut self, user: &User) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO users (id, name, has_deposited) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (id) DO UPDATE SET name = excluded.name, has_deposited = excluded.has_deposited",
+                params![u32::from(user.id), user.name, user.has_deposited],
+            )
+            .map_err(db_error)?;
+        for account in &user.accounts {
+            let maturity = match account.kind {
+                AccountKind::TermDeposit { maturity } => Some(maturity),
+                _ => None,
+            };
+            self.conn
+                .execute(
+                    "INSERT OR REPLACE INTO accounts
+                         (id, owner, kind, maturity_nanos, borrowable, window_start_nanos, window_withdrawals)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        u32::from(account.id),
+                        u32::from(user.id),
+                        account.kind.name(),
+                        maturity.map(nanos).transpose()?,
+                        account.borrowable,
+                        account.window_start.map(nanos).transpose()?,
+                        account.window_withdrawals,
+                    ],
+                )
+                .map_err(db_error)?;
+            for (currency, balance) in &account.balances {
+                self.conn
+                    .execute(
+                        "INSERT OR REPLACE INTO account_balances
+                             (account, currency, deposited, withdrawn, interest_since_nanos)
+                         VALUES (?1, ?2, ?3, ?4, ?5)",
+                        params![
+                            u32::from(account.id),
+                            currency.to_string(),
+                            minor(balance.deposited)?,
+                            minor(balance.withdrawn)?,
+                            balance.interest_since.map(nanos).transpose()?,
+                        ],
+                    )
+                    .map_err(db_error)?;
+            }
+        }
+        self.conn
+            .execute("DELETE FROM overdrafts WHERE owner = ?1", params![u32::from(user.id)])
+            .map_err(db_error)?;
+        if let Some(overdraft) = &user.overdraft {
+            self.conn
+                .execute(
+                    "INSERT INTO overdrafts (owner, currency, limit_minor, rate_bps, overdrawn, interest_since_nanos)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        u32::from(user.id),
+                        overdraft.currency.to_string(),
+                        minor(overdraft.limit)?,
+                        overdraft.rate_bps,
+                        minor(overdraft.overdrawn)?,
+                        overdraft.interest_since.map(nanos).transpose()?,
+                    ],
+                )
+                .map_err(db_error)?;
+        }
+        self.conn
+            .execute("DELETE FROM salary_advances WHERE owner = ?1", params![u32::from(user.id)])
+            .map_err(db_error)?;
+        if let Some(advance) = &user.salary_advance {
+            self.conn
+                .execute(
+                    "INSERT INTO salary_advances (owner, currency, owed, fee, taken_nanos) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        u32::from(user.id),
+                        advance.currency.to_string(),
+                        minor(advance.owed)?,
+                        minor(advance.fee)?,
+                        nanos(advance.taken)?,
+                    ],
+                )
+                .map_err(db_error)?;
+        }
+        self.conn
+            .execute("DELETE FROM payee_corrections WHERE owner = ?1", params![u32::from(user.id)])
+            .map_err(db_error)?;
+        for (account, payee) in &user.payee_corrections {
+            self.conn
+                .execute(
+                    "INSERT INTO payee_corrections (owner, account, name, category, uri)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![u32::from(user.id), u32::from(*account), payee.name, payee.category, payee.uri],
+                )
+                .map_err(db_error)?;
+        }
+        for plan in &user.installment_plans {
+            self.conn
+                .execute(
+                    "INSERT OR REPLACE INTO installment_plans
+                         (id, payer, payee, account, currency, price, installments, paid, remaining, fee, start_nanos)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                    params![
+                        u32::from(plan.id),
+                        u32::from(plan.payer),
+                        u32::from(plan.payee),
+                        u32::from(plan.account),
+                        plan.currency.to_string(),
+                        minor(plan.price)?,
+                        plan.installments,
+                        plan.paid,
+                        minor(plan.remaining)?,
+                        minor(plan.fee)?,
+                        nanos(plan.start)?,
+                    ],
+                )
+                .map_err(db_error)?;
+        }
+        for loan in &user.loans {
+            self.conn
+                .execute(
+                    "INSERT OR REPLACE INTO loans
+                         (id, borrower, lender, principal, currency, rate_bps, start_nanos, outstanding,
+                          accrued_interest, last_accrued_nanos, account, lender_account)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                    params![
+                        u32::from(loan.id),
+                        u32::from(loan.borrower),
+                        u32::from(loan.lender),
+                        minor(loan.principal)?,
+                        loan.currency.to_string(),
+                        loan.rate_bps,
+                        nanos(loan.start)?,
+                        minor(loan.remaining)?,
+                        minor(loan.accrued_interest)?,
+                        loan.last_accrued.map(nanos).transpose()?,
+                        loan.account.map(u32::from),
+                        loan.lender_account.map(u32::from),
+                    ],
+                )
+                .map_err(db_error)?;
+        }
+        Ok(())
+    }
+
+    fn load_treasury(&self) -> Result<Treasury, String> {
+        let mut treasury = Treasury {
+            balances: self.load_balances(None)?,
+            transactions: self.load_transactions(None)?,
+            fees_collected: self.load_fees_collected()?,
+            ..Default::default()
+        };
+        for (account, payee) in self.load_payees("SELECT account, name, category, uri FROM payees", [])? {
+            treasury.payees.register(account, payee);
+        }
+        let facility = self
+            .conn
+            .query_row(
+                "SELECT currency, limit_minor, rate_bps, drawn, interest_expense, interest_since_nanos FROM facility",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        money(row.get(1)?),
+                        row.get::<_, u32>(2)?,
+                        money(row.get(3)?),
+                        money(row.get(4)?),
+                        row.get::<_, Option<i64>>(5)?.map(time),
+                    ))
+                },
+            )
+            .optional()
+            .map_err(db_error)?;
+        if let Some((currency, limit, rate_bps, drawn, interest_expense, interest_since)) = facility {
+            treasury.facility.currency = currency.parse()?;
+            treasury.facility.limit = limit;
+            treasury.facility.rate_bps = rate_bps;
+            treasury.facility.drawn = drawn;
+            treasury.facility.interest_expense = interest_expense;
+            treasury.facility.interest_since = interest_since;
+        }
+        let interest: Option<String> = self
+            .conn
+            .query_row("SELECT value FROM settings WHERE key = 'interest'", [], |row| row.get(0))
+            .optional()
+            .map_err(db_error)?;
+        if let Some(interest) = interest {
+            treasury.interest = serde_json::from_str(&interest)
+                .map_err(|err| format!("Invalid interest setting: {}", err))?;
+        }
+        let fees: Option<String> = self
+            .conn
+            .query_row("SELECT value FROM settings WHERE key = 'fees'", [], |row| row.get(0))
+            .optional()
+            .map_err(db_error)?;
+        if let Some(fees) = fees {
+            treasury.fees = serde_json::from_str(&fees).map_err(|err| format!("Invalid fee setting: {}", err))?;
+        }
+        let policy_changes: Option<String> = self
+            .conn
+            .query_row("SELECT value FROM settings WHERE key = 'policy_changes'", [], |row| row.get(0))
+            .optional()
+            .map_err(db_error)?;
+        if let Some(policy_changes) = policy_changes {
+            treasury.policy_changes = serde_json::from_str(&policy_changes)
+                .map_err(|err| format!("Invalid policy changes: {}", err))?;
+        }
+        Ok(treasury)
+    }
+
+    fn save_treasury(&mut self, treasury: &Treasury) -> Result<(), String> {
+        save_balances(&self.conn, None, &treasury.balances)?;
+        self.conn.execute("DELETE FROM fees_collected", []).map_err(db_error)?;
+        for (currency, fees) in &treasury.fees_collected {
+            self.conn
+                .execute(
+                    "INSERT INTO fees_collected (currency, entry, exit, advance, merchant) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        currency.to_string(),
+                        minor(fees.entry)?,
+                        minor(fees.exit)?,
+                        minor(fees.advance)?,
+                        minor(fees.merchant)?,
+                    ],
+                )
+                .map_err(db_error)?;
+        }
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO facility
+                     (id, currency, limit_minor, rate_bps, drawn, interest_expense, interest_since_nanos)
+                 VALUES (1, ?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    treasury.facility.currency.to_string(),
+                    minor(treasury.facility.limit)?,
+                    treasury.facility.rate_bps,
+                    minor(treasury.facility.drawn)?,
+                    minor(treasury.facility.interest_expense)?,
+                    treasury.facility.interest_since.map(nanos).transpose()?,
+                ],
+            )
+            .map_err(db_error)?;
+        let interest = serde_json::to_string(&treasury.interest)
+            .map_err(|err| format!("Cannot encode interest setting: {}", err))?;
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO settings (key, value) VALUES ('interest', ?1)",
+                params![interest],
+            )
+            .map_err(db_error)?;
+        let fees = serde_json::to_string(&treasury.fees)
+            .map_err(|err| format!("Cannot encode fee setting: {}", err))?;
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO settings (key, value) VALUES ('fees', ?1)",
+                params![fees],
+            )
+            .map_err(db_error)?;
+        let policy_changes = serde_json::to_string(&treasury.policy_changes)
+            .map_err(|err| format!("Cannot encode policy changes: {}", err))?;
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO settings (key, value) VALUES ('policy_changes', ?1)",
+                params![policy_changes],
+            )
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    fn append_transaction(&mut self, owner: Option<UserId>, transaction: &Transaction) -> Result<(), String> {
+        let kind = encode(transaction.kind)?;
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO transactions
+                     (id, owner, timestamp_nanos, kind, amount, currency, fee, counterparty)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    i64::try_from(transaction.id).map_err(|_| "Transaction id out of range")?,
+                    owner.map(u32::from),
+                    nanos(transaction.timestamp)?,
+                    kind,
+                    minor(transaction.amount)?,
+                    transaction.currency.to_string(),
+                    minor(transaction.fee)?,
+                    transaction.counterparty.map(u32::from),
+                ],
+            )
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    fn save_tags(&mut self, transaction: &Transaction) -> Result<(), String> {
+        let id = i64::try_from(transaction.id).map_err(|_| "Transaction id out of range")?;
+        self.conn
+            .execute("DELETE FROM transaction_tags WHERE transaction_id = ?1", params![id])
+            .map_err(db_error)?;
+        for tag in &transaction.tags {
+            self.conn
+                .execute(
+                    "INSERT INTO transaction_tags (transaction_id, tag) VALUES (?1, ?2)",
+                    params![id, tag],
+                )
+                .map_err(db_error)?;
+        }
+        Ok(())
+    }
+
+    fn save_payee(&mut self, account: UserId, payee: Option<&Payee>) -> Result<(), String> {
+        match payee {
+            Some(payee) => self.conn.execute(
+                "INSERT OR REPLACE INTO payees (account, name, category, uri) VALUES (?1, ?2, ?3, ?4)",
+                params![u32::from(account), payee.name, payee.category, payee.uri],
+            ),
+            None => self
+                .conn
+                .execute("DELETE FROM payees WHERE account = ?1", params![u32::from(account)]),
+        }
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), String> {
+        self.conn
+            .execute_batch(
+                "DELETE FROM users; DELETE FROM balances; DELETE FROM facility;
+                 DELETE FROM loans; DELETE FROM transactions; DELETE FROM settings;
+                 DELETE FROM fees_collected; DELETE FROM overdrafts;
+                 DELETE FROM transaction_tags; DELETE FROM payees; DELETE FROM payee_corrections;
+                 DELETE FROM accounts; DELETE FROM account_balances; DELETE FROM salary_advances;
+                 DELETE FROM installment_plans;",
+            )
+            .map_err(db_error)
+    }
+
+    fn load_environment(&self) -> Result<Environment, String> {
+        let value: Option<String> = self
+            .conn
+            .query_row("SELECT value FROM settings WHERE key = 'environment'", [], |row| row.get(0))
+            .optional()
+            .map_err(db_error)?;
+        value.map_or(Ok(Environment::Production), decode)
+    }
+
+    fn save_environment(&mut self, environment: Environment) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO settings (key, value) VALUES ('environment', ?1)",
+                params![encode(environment)?],
+            )
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    fn begin(&mut self) -> Result<(), String> {
+        self.conn.execute_batch("BEGIN").map_err(db_error)
+    }
+
+    fn commit(&mut self) -> Result<(), String> {
+        self.conn.execute_batch("COMMIT").map_err(db_error)
+    }
+
+    fn rollback(&mut self) -> Result<(), String> {
+        self.conn.execute_batch("ROLLBACK").map_err(db_error)
+    }
+}
+
+fn save_balances(
+    conn: &Connection,
+    owner: Option<UserId>,
+    balances: &HashMap<Currency, Balance>,
+) -> Result<(), String> {
+    for (currency, balance) in balances {
+        // `UNIQUE (owner, currency)` treats NULL owners as distinct, so the
+        // treasury rows are replaced explicitly.
+        conn.execute(
+            "DELETE FROM balances WHERE owner IS ?1 AND currency = ?2",
+            params![owner.map(u32::from), currency.to_string()],
+        )
+        .map_err(db_error)?;
+        conn.execute(
+            "INSERT INTO balances (owner, currency, deposited, withdrawn, interest_since_nanos)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                owner.map(u32::from),
+                currency.to_string(),
+                minor(balance.deposited)?,
+                minor(balance.withdrawn)?,
+                balance.interest_since.map(nanos).transpose()?,
+            ],
+        )
+        .map_err(db_error)?;
+    }
+    Ok(())
+}
+
+/// Store a unit-only enum as its variant name.
+fn encode<T: Serialize>(value: T) -> Result<String, String> {
+    match serde_json::to_value(value) {
+        Ok(Value::String(name)) => Ok(name),
+        _ => Err(String::from("Cannot encode value as text")),
+    }
+}
+
+fn decode<T: DeserializeOwned>(name: String) -> Result<T, String> {
+    serde_json::from_value(Value::String(name)).map_err(|err| format!("Invalid stored value: {}", err))
+}
+
+fn minor(amount: Money) -> Result<i64, String> {
+    i64::try_from(amount.minor()).map_err(|_| format!("Amount {} too large to store", amount))
+}
+
+fn money(minor: i64) -> Money {
+    Money::from_minor(minor.max(0) as u64)
+}
+
+fn nanos(time: SystemTime) -> Result<i64, String> {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    i64::try_from(elapsed.as_nanos()).map_err(|_| String::from("Timestamp out of range"))
+}
+
+fn time(nanos: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_nanos(nanos.max(0) as u64)
+}
+
+fn db_error(err: rusqlite::Error) -> String {
+    format!("Database error: {}", err)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use rusqlite::Connection;
+
+    use super::{SqliteStore, MIGRATIONS};
+    use crate::bank::Bank;
+    use crate::currency::Currency;
+    use crate::export::export;
+    use crate::money::Money;
+    use crate::store::Store;
+
+    /// A database file in the temporary directory, removed on drop.
+    struct TempDb(PathBuf);
+
+    impl TempDb {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("banking-{}-{}.db", std::process::id(), name));
+            let _ = fs::remove_file(&path);
+            TempDb(path)
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn user_version(db: &TempDb) -> usize {
+        let conn = Connection::open(&db.0).unwrap();
+        conn.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn new_databases_get_every_migration_once() {
+        let db = TempDb::new("fresh");
+        SqliteStore::open(&db.0).unwrap();
+        assert_eq!(user_version(&db), MIGRATIONS.len());
+        SqliteStore::open(&db.0).unwrap();
+        assert_eq!(user_version(&db), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn older_databases_are_migrated_forward() {
+        let db = TempDb::new("older");
+        let applied = MIGRATIONS.len() - 1;
+        {
+            let conn = Connection::open(&db.0).unwrap();
+            for sql in &MIGRATIONS[..applied] {
+                conn.execute_batch(sql).unwrap();
+            }
+            conn.pragma_update(None, "user_version", applied).unwrap();
+            assert!(conn.prepare("SELECT interest_since_nanos FROM facility").is_err());
+        }
+        let store = SqliteStore::open(&db.0).unwrap();
+        assert_eq!(user_version(&db), MIGRATIONS.len());
+        assert!(store.conn.prepare("SELECT currency, interest_since_nanos FROM facility").is_ok());
+    }
+
+    #[test]
+    fn banks_are_reloaded_as_they_were_saved() {
+        let db = TempDb::new("round-trip");
+        let usd = |major| Money::from_major(major);
+        let mut bank = Bank::open(SqliteStore::open(&db.0).unwrap()).unwrap();
+        let (a, b) = (bank.open_account("Ada").unwrap(), bank.open_account("Bob").unwrap());
+        bank.deposit(a, usd(1_000), Currency::Usd, true).unwrap();
+        bank.deposit(b, usd(500), Currency::Eur, false).unwrap();
+        bank.borrow_between(b, a, usd(50), Currency::Usd).unwrap();
+        bank.transfer(b, a, usd(20), Currency::Usd).unwrap();
+        let deposit = bank.get_user(a).unwrap().transactions[0].id;
+        bank.tag_transactions(a, &[deposit], "salary").unwrap();
+        let saved = export(&bank).unwrap();
+        drop(bank);
+
+        let reopened = Bank::open(SqliteStore::open(&db.0).unwrap()).unwrap();
+        assert_eq!(export(&reopened).unwrap(), saved);
+        assert!(reopened.get_user(a).unwrap().transactions[0].tags.contains("salary"));
+    }
+
+    #[test]
+    fn rolled_back_writes_are_not_kept() {
+        let db = TempDb::new("rollback");
+        let mut store = SqliteStore::open(&db.0).unwrap();
+        let mut bank = Bank::new();
+        let id = bank.open_account("Ada").unwrap();
+        let user = bank.get_user(id).unwrap();
+        store.begin().unwrap();
+        store.save_user(user).unwrap();
+        store.rollback().unwrap();
+        assert!(store.load_user(id).unwrap().is_none());
+        store.save_user(user).unwrap();
+        assert_eq!(store.load_user(id).unwrap().map(|user| user.name), Some(String::from("Ada")));
+    }
+}
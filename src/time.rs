@@ -0,0 +1,62 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Length of the year that annual rates are prorated over.
+pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// Source of the current time for interest accrual.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The real wall clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to, so accrual can be fast-forwarded
+/// deterministically.
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<SystemTime>,
+}
+
+impl MockClock {
+    pub fn new(start: SystemTime) -> Self {
+        MockClock { now: Mutex::new(start) }
+    }
+
+    /// Move the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        *now += by;
+    }
+
+    /// Jump the clock to `to`, which may be in the past.
+    pub fn set(&self, to: SystemTime) {
+        *self.now.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = to;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// The clock banks use unless given another one.
+pub fn system_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+/// Prorate a yearly `amount` (in minor units) over `elapsed`, rounding down.
+pub fn prorate(amount: u64, elapsed: Duration) -> u64 {
+    let prorated = u128::from(amount) * u128::from(elapsed.as_secs()) / u128::from(SECONDS_PER_YEAR);
+    u64::try_from(prorated).unwrap_or(u64::MAX)
+}
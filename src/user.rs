@@ -1,106 +1,392 @@
-#![allow(unused)]
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::mem;
+use std::time::{Duration, SystemTime};
 
-#[derive(Debug, Default)]
+use serde::{Deserialize, Serialize};
+
+use crate::account::Account;
+use crate::advance::SalaryAdvance;
+use crate::aggregates::Aggregates;
+use crate::bank::BankError;
+use crate::currency::{Balance, Currency};
+use crate::facility::LiquidityFacility;
+use crate::fees::{FeeSchedule, FeesCollected};
+use crate::income;
+use crate::installment::InstallmentPlan;
+use crate::interest::{Compounding, InterestForecast, InterestStrategy};
+use crate::ledger::{Transaction, TransactionKind};
+use crate::loan::Loan;
+use crate::money::Money;
+use crate::overdraft::OverdraftAgreement;
+use crate::payee::{Payee, PayeeDirectory};
+use crate::policy::{Policy, PolicyChanges};
+use crate::time::{self, Clock};
+use crate::types::{AccountId, LoanId, UserId};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct User {
-   pub id: u32,
+   pub id: UserId,
    pub name: String,
-   pub total_deposited: u32,
-   pub total_withdrawn: u32,
+   /// The user's accounts, oldest first; the first is their primary account.
+   pub accounts: Vec<Account>,
    pub has_deposited: bool,
-   pub borrowable: bool,
+   pub loans: Vec<Loan>,
+   pub overdraft: Option<OverdraftAgreement>,
+   pub salary_advance: Option<SalaryAdvance>,
+   /// Purchases the user is paying off in installments.
+   pub installment_plans: Vec<InstallmentPlan>,
+   /// The user's own names for counterparties, overriding the payee directory.
+   pub payee_corrections: BTreeMap<UserId, Payee>,
+   pub transactions: Vec<Transaction>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Treasury {
-   pub sum_deposited: u32,
-   pub sum_withdrawn: u32,
+   pub balances: HashMap<Currency, Balance>,
+   pub facility: LiquidityFacility,
+   pub interest: InterestStrategy,
+   pub fees: FeeSchedule,
+   /// Proposed changes to `interest`, `fees` and `facility`, with their outcome.
+   pub policy_changes: PolicyChanges,
+   pub fees_collected: HashMap<Currency, FeesCollected>,
+   pub payees: PayeeDirectory,
+   pub transactions: Vec<Transaction>,
+   /// Rebuilt when a bank is loaded, then kept current by `Bank` operations.
+   #[serde(skip)]
+   pub aggregates: Aggregates,
 }
 
 impl User {
-    /// Deposit `amount` into the user’s account and the treasury.
-    pub fn deposit(&mut self, amount: u32, treasury: &mut Treasury, is_borrowable: bool) {
-        self.total_deposited = self
-            .total_deposited
-            .checked_add(amount)
+    /// A copy of the user without their ledger, to `restore` if an operation
+    /// fails part way through.
+    pub fn checkpoint(&mut self) -> User {
+        let transactions = mem::take(&mut self.transactions);
+        let checkpoint = self.clone();
+        self.transactions = transactions;
+        checkpoint
+    }
+
+    /// Go back to `checkpoint`, dropping the ledger entries after `mark`.
+    pub fn restore(&mut self, mut checkpoint: User, mark: usize) {
+        self.transactions.truncate(mark);
+        checkpoint.transactions = mem::take(&mut self.transactions);
+        *self = checkpoint;
+    }
+
+    /// The account used when none is named: the user's first.
+    pub fn primary_account(&self) -> Option<&Account> {
+        self.accounts.first()
+    }
+
+    pub fn account(&self, id: AccountId) -> Result<&Account, BankError> {
+        self.accounts
+            .iter()
+            .find(|account| account.id == id)
+            .ok_or_else(|| BankError::NotFound(format!("User {} has no account {}", self.id, id)))
+    }
+
+    pub fn account_mut(&mut self, id: AccountId) -> Result<&mut Account, BankError> {
+        let user = self.id;
+        self.accounts
+            .iter_mut()
+            .find(|account| account.id == id)
+            .ok_or_else(|| BankError::NotFound(format!("User {} has no account {}", user, id)))
+    }
+
+    /// Check that funds may leave account `id` at `now`, and count the debit
+    /// against its monthly withdrawal limit. Every debit of an account goes
+    /// through here first; an operation that then fails is undone along with
+    /// the count.
+    pub fn start_debit(&mut self, id: AccountId, now: SystemTime) -> Result<(), BankError> {
+        let account = self.account_mut(id)?;
+        account.check_withdrawal(now)?;
+        account.record_withdrawal(now);
+        Ok(())
+    }
+
+    /// Totals held in `currency` across all the user's accounts, zero if none
+    /// of them ever touched it.
+    pub fn balance(&self, currency: Currency) -> Balance {
+        self.accounts
+            .iter()
+            .map(|account| account.balance(currency))
+            .fold(Balance::default(), combine)
+    }
+
+    /// Totals held across all the user's accounts, per currency.
+    pub fn total_balance(&self) -> BTreeMap<Currency, Balance> {
+        let mut totals: BTreeMap<Currency, Balance> = BTreeMap::new();
+        for (currency, balance) in self.accounts.iter().flat_map(|account| &account.balances) {
+            let total = totals.entry(*currency).or_default();
+            *total = combine(*total, *balance);
+        }
+        totals
+    }
+
+    /// Exchange `amount` of `from` held in `account` into `to` at `rate_bps`
+    /// units of `to` per unit of `from` (10_000 = 1:1). Returns the amount
+    /// credited in `to`.
+    pub fn convert(
+        &mut self,
+        account: AccountId,
+        amount: Money,
+        from: Currency,
+        to: Currency,
+        rate_bps: u32,
+        treasury: &mut Treasury,
+    ) -> Result<Money, String> {
+        if from == to {
+            return Err(String::from("Cannot convert a currency into itself"));
+        }
+        let index = self.account_index(account)?;
+        if self.accounts[index].balance(from).deposited < amount {
+            return Err(format!("Insufficient {} funds to convert", from));
+        }
+        let converted = amount
+            .checked_mul_bps(rate_bps)
+            .ok_or("Arithmetic overflow when converting")?;
+        let credited = self.accounts[index]
+            .balance(to)
+            .deposited
+            .checked_add(converted)
+            .ok_or("Arithmetic overflow when converting")?;
+        let treasury_credited = treasury
+            .balance(to)
+            .deposited
+            .checked_add(converted)
+            .ok_or("Arithmetic overflow when converting")?;
+        treasury.cover_shortfall(from, amount)?;
+        let treasury_debited = treasury
+            .balance(from)
+            .deposited
+            .checked_sub(amount)
+            .ok_or("Insufficient treasury reserves")?;
+
+        let debited = self.accounts[index].balance_mut(from);
+        debited.deposited = debited.deposited.checked_sub(amount).ok_or("Arithmetic overflow")?;
+        self.accounts[index].balance_mut(to).deposited = credited;
+        treasury.balance_mut(from).deposited = treasury_debited;
+        treasury.balance_mut(to).deposited = treasury_credited;
+
+        self.transactions
+            .push(Transaction::new(TransactionKind::ExchangeOut, amount, from, Money::ZERO, None));
+        self.transactions
+            .push(Transaction::new(TransactionKind::ExchangeIn, converted, to, Money::ZERO, None));
+        treasury
+            .transactions
+            .push(Transaction::new(TransactionKind::ExchangeOut, amount, from, Money::ZERO, Some(self.id)));
+        treasury
+            .transactions
+            .push(Transaction::new(TransactionKind::ExchangeIn, converted, to, Money::ZERO, Some(self.id)));
+        Ok(converted)
+    }
+
+    /// Every operation recorded against this user, oldest first.
+    pub fn history(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    /// Position of `account` in `self.accounts`.
+    fn account_index(&self, id: AccountId) -> Result<usize, BankError> {
+        self.accounts
+            .iter()
+            .position(|account| account.id == id)
+            .ok_or_else(|| BankError::NotFound(format!("User {} has no account {}", self.id, id)))
+    }
+
+    /// Credit the net `amount` of a deposit on which `fee` was charged to
+    /// `account`. An overdraft in `currency` is paid back before the balance
+    /// grows.
+    fn credit_deposit(
+        &mut self,
+        account: AccountId,
+        amount: Money,
+        fee: Money,
+        currency: Currency,
+        treasury: &mut Treasury,
+        is_borrowable: bool,
+    ) -> Result<(), String> {
+        let index = self.account_index(account)?;
+        let repaid = match &mut self.overdraft {
+            Some(overdraft) if overdraft.currency == currency => overdraft.repay(amount),
+            _ => Money::ZERO,
+        };
+        let account = &mut self.accounts[index];
+        let balance = account.balance_mut(currency);
+        balance.deposited = balance
+            .deposited
+            .checked_add(amount.checked_sub(repaid).expect("overdraft repayment exceeds deposit"))
             .expect("deposit overflow");
+        account.borrowable = is_borrowable;
         self.has_deposited = true;
-        self.borrowable = is_borrowable;
-        treasury.sum_deposited = treasury
-            .sum_deposited
+        let reserves = treasury.balance_mut(currency);
+        reserves.deposited = reserves
+            .deposited
             .checked_add(amount)
             .expect("treasury deposit overflow");
+        let collected = treasury.fees_collected.entry(currency).or_default();
+        collected.entry = collected.entry.checked_add(fee).expect("fee revenue overflow");
+        self.transactions
+            .push(Transaction::new(TransactionKind::Deposit, amount, currency, fee, None));
+        treasury
+            .transactions
+            .push(Transaction::new(TransactionKind::Deposit, amount, currency, fee, Some(self.id)));
+        treasury.repay_facility(currency, amount);
+        Ok(())
     }
 
-    /// Withdraw `amount` from the user’s account and the treasury.
-    pub fn withdraw(
+    /// Credit minted sandbox funds to the user's `account` and the treasury,
+    /// without fees.
+    pub fn receive_test_funds(
         &mut self,
-        amount: u32,
+        account: AccountId,
+        amount: Money,
+        currency: Currency,
         treasury: &mut Treasury,
-    ) -> Result<u32, String> {
-        if self.total_withdrawn + amount <= self.total_deposited {
-            // Deduct from deposited balance
-            self.total_deposited -= amount;
-            self.total_withdrawn = self
-                .total_withdrawn
-                .checked_add(amount)
-                .expect("withdraw overflow");
-            // Adjust treasury
-            treasury.sum_deposited -= amount;
-            treasury.sum_withdrawn = treasury
-                .sum_withdrawn
-                .checked_add(amount)
-                .expect("treasury withdrawal overflow");
-            Ok(self.total_withdrawn)
-        } else {
-            Err(String::from("Something Went Wrong"))
-        }
+    ) -> Result<Money, String> {
+        let account = self.account_mut(account)?;
+        let credited = account.balance(currency).deposited
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow")?;
+        let reserves = treasury.balance(currency).deposited
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow")?;
+        account.balance_mut(currency).deposited = credited;
+        treasury.balance_mut(currency).deposited = reserves;
+        self.has_deposited = true;
+        self.transactions
+            .push(Transaction::new(TransactionKind::Faucet, amount, currency, Money::ZERO, None));
+        treasury
+            .transactions
+            .push(Transaction::new(TransactionKind::Faucet, amount, currency, Money::ZERO, Some(self.id)));
+        Ok(credited)
     }
 
-    /// Calculate the entry fee (2% fee) for the given deposit amount.
-    pub fn calculate_entry_fee(amount: u32) -> u32 {
-        const MAX_BPS: u32 = 10_000;
-        const ENTRY_FEE_BPS: u32 = 200; // 2%
-        (amount.saturating_mul(ENTRY_FEE_BPS)) / MAX_BPS
-    }
+    /// Debit `amount` plus `fee` from the user's `account` and the treasury.
+    /// Whatever the balance cannot cover is drawn on the user's overdraft in
+    /// `currency`, if there is one with enough headroom.
+    fn debit_withdrawal(
+        &mut self,
+        account: AccountId,
+        amount: Money,
+        fee: Money,
+        currency: Currency,
+        treasury: &mut Treasury,
+    ) -> Result<Money, String> {
+        let index = self.account_index(account)?;
+        let total = amount
+            .checked_add(fee)
+            .ok_or("Withdrawal fee calculation error")?;
+        let balance = self.accounts[index].balance(currency);
+        let available = balance.deposited.checked_sub(balance.withdrawn).unwrap_or(Money::ZERO);
+        let shortfall = total.checked_sub(available).unwrap_or(Money::ZERO);
+        let overdraft = self
+            .overdraft
+            .as_mut()
+            .filter(|overdraft| overdraft.currency == currency && shortfall <= overdraft.headroom());
+        if shortfall > Money::ZERO && overdraft.is_none() {
+            return Err(format!("Insufficient {} funds: {} available, {} needed", currency, available, total));
+        }
+        treasury.cover_shortfall(currency, total)?;
+        let reserves = treasury.balance(currency).deposited
+            .checked_sub(total)
+            .ok_or("Insufficient treasury reserves")?;
+        if let Some(overdraft) = overdraft {
+            overdraft.overdrawn = overdraft.overdrawn
+                .checked_add(shortfall)
+                .ok_or("Arithmetic overflow")?;
+        }
 
-    /// Calculate the exit fee (4% fee) for the given withdrawal amount.
-    pub fn calculate_exit_fee(amount: u32) -> u32 {
-        const MAX_BPS: u32 = 10_000;
-        const EXIT_FEE_BPS: u32 = 400; // 4%
-        (amount.saturating_mul(EXIT_FEE_BPS)) / MAX_BPS
+        // Deduct from deposited balance
+        let balance = self.accounts[index].balance_mut(currency);
+        balance.deposited = balance
+            .deposited
+            .checked_sub(total.checked_sub(shortfall).expect("shortfall exceeds withdrawal"))
+            .expect("withdraw underflow");
+        balance.withdrawn = balance
+            .withdrawn
+            .checked_add(total)
+            .expect("withdraw overflow");
+        let withdrawn = balance.withdrawn;
+        // Adjust treasury
+        let reserves_balance = treasury.balance_mut(currency);
+        reserves_balance.deposited = reserves;
+        reserves_balance.withdrawn = reserves_balance
+            .withdrawn
+            .checked_add(total)
+            .expect("treasury withdrawal overflow");
+        let collected = treasury.fees_collected.entry(currency).or_default();
+        collected.exit = collected.exit.checked_add(fee).expect("fee revenue overflow");
+        self.transactions
+            .push(Transaction::new(TransactionKind::Withdrawal, amount, currency, fee, None));
+        treasury
+            .transactions
+            .push(Transaction::new(TransactionKind::Withdrawal, amount, currency, fee, Some(self.id)));
+        Ok(withdrawn)
     }
 
-    /// Deposit with an entry fee deducted.
-    /// The net deposit (amount minus fee) is credited into the user's account.
-    pub fn deposit_with_fee(&mut self, amount: u32, treasury: &mut Treasury, is_borrowable: bool) {
-        let fee = Self::calculate_entry_fee(amount);
-        let net_amount = amount.checked_sub(fee)
-            .expect("Fee exceeds deposit amount");
-        self.deposit(net_amount, treasury, is_borrowable);
-        // Optionally, you might record the fee separately.
+    /// Deposit with an entry fee from `fees` deducted.
+    /// The net deposit (amount minus fee) is credited into the user's `account`.
+    /// Returns the net amount credited.
+    pub fn deposit_with_fee(
+        &mut self,
+        account: AccountId,
+        amount: Money,
+        currency: Currency,
+        treasury: &mut Treasury,
+        fees: &FeeSchedule,
+        is_borrowable: bool,
+    ) -> Result<Money, String> {
+        let fee = fees.entry_fee(amount);
+        let net_amount = amount
+            .checked_sub(fee)
+            .ok_or_else(|| format!("Entry fee {} exceeds deposit amount {}", fee, amount))?;
+        self.credit_deposit(account, net_amount, fee, currency, treasury, is_borrowable)?;
+        Ok(net_amount)
     }
 
-    /// Withdraw funds along with an exit fee.
+    /// Withdraw funds from `account` along with an exit fee from `fees`.
     /// The total withdrawal is the requested amount plus the fee.
-    pub fn withdraw_with_fee(&mut self, amount: u32, treasury: &mut Treasury) -> Result<u32, String> {
-        let fee = Self::calculate_exit_fee(amount);
-        let total = amount.checked_add(fee)
-            .ok_or("Withdrawal fee calculation error")?;
-        self.withdraw(total, treasury)
+    pub fn withdraw_with_fee(
+        &mut self,
+        account: AccountId,
+        amount: Money,
+        currency: Currency,
+        treasury: &mut Treasury,
+        fees: &FeeSchedule,
+    ) -> Result<Money, String> {
+        let fee = fees.exit_fee(amount);
+        self.debit_withdrawal(account, amount, fee, currency, treasury)
     }
 
-    /// Borrow funds from a lender.
-    /// The borrower is allowed to borrow up to 10% of the lender's deposited funds,
-    /// provided the lender has enabled borrowing.
-    pub fn borrow(&mut self, lender: &mut User, amount: u32) -> Result<u32, String> {
-        const BORROW_PERCENTAGE: u32 = 10; // 10% borrowing limit
+    /// Borrow funds into `account` from the lender's `lender_account`.
+    /// The borrower is allowed to borrow up to 10% of the deposited funds in that
+    /// account, provided the lender has enabled borrowing on it. The resulting
+    /// `Loan` is recorded on both users, starting at `clock`'s now. Only the
+    /// lender's deposit in `currency` counts toward the limit.
+    pub fn borrow(
+        &mut self,
+        account: AccountId,
+        lender: &mut User,
+        lender_account: AccountId,
+        amount: Money,
+        currency: Currency,
+        clock: &dyn Clock,
+    ) -> Result<Money, String> {
+        const BORROW_PERCENTAGE: u64 = 10; // 10% borrowing limit
         
-        if !lender.borrowable {
+        let index = self.account_index(account)?;
+        let lender_index = lender.account_index(lender_account)?;
+        if !lender.accounts[lender_index].borrowable {
             return Err(String::from("Lender has not enabled borrowing"));
         }
 
         // Calculate maximum borrowable amount (10% of lender's deposited amount)
-        let max_borrowable = (lender.total_deposited * BORROW_PERCENTAGE) / 100;
+        let lender_deposited = lender.accounts[lender_index].balance(currency).deposited;
+        let max_borrowable = Money::from_minor((lender_deposited.minor() * BORROW_PERCENTAGE) / 100);
         
         if amount > max_borrowable {
             return Err(format!(
@@ -110,45 +396,890 @@ impl User {
             ));
         }
 
-        if lender.total_deposited < amount {
+        if lender_deposited < amount {
             return Err(String::from("Insufficient funds in lender's account"));
         }
 
         // Update balances
-        lender.total_deposited = lender.total_deposited
+        let credited = self.accounts[index].balance(currency).deposited
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow")?;
+
+        lender.accounts[lender_index].balance_mut(currency).deposited = lender_deposited
             .checked_sub(amount)
             .ok_or("Arithmetic overflow")?;
 
-        self.total_deposited = self.total_deposited
+        self.accounts[index].balance_mut(currency).deposited = credited;
+
+        let loan = Loan::new(
+            self.id,
+            account,
+            lender.id,
+            lender_account,
+            amount,
+            currency,
+            clock.now(),
+        );
+        lender.loans.push(loan.clone());
+        self.loans.push(loan);
+        lender
+            .transactions
+            .push(Transaction::new(TransactionKind::Lend, amount, currency, Money::ZERO, Some(self.id)));
+        self.transactions
+            .push(Transaction::new(TransactionKind::Borrow, amount, currency, Money::ZERO, Some(lender.id)));
+
+        Ok(amount)
+    }
+
+    /// Pay up to `amount` towards the loan `loan_id` owed to `lender`, settling
+    /// accrued interest before principal. The payment moves from this user's
+    /// `account` in the loan's currency to the lender's `lender_account`, and is
+    /// capped at what is still owed. Returns the amount paid.
+    pub fn repay(
+        &mut self,
+        account: AccountId,
+        lender: &mut User,
+        lender_account: AccountId,
+        loan_id: LoanId,
+        amount: Money,
+        clock: &dyn Clock,
+    ) -> Result<Money, String> {
+        let now = clock.now();
+        let index = self.account_index(account)?;
+        let lender_index = lender.account_index(lender_account)?;
+        let loan = self
+            .debts()
+            .find(|loan| loan.id == loan_id && loan.lender == lender.id)
+            .ok_or_else(|| format!("No loan {} owed by {} to {}", loan_id, self.id, lender.id))?;
+        if loan.is_closed() {
+            return Err(format!("Loan {} is already repaid", loan_id));
+        }
+        let currency = loan.currency;
+        let owed = loan.remaining
+            .checked_add(loan.interest_due(now))
+            .ok_or("Arithmetic overflow")?;
+        let payment = amount.min(owed);
+
+        let available = self.accounts[index].balance(currency).deposited;
+        if available < payment {
+            return Err(String::from("Insufficient funds in borrower's account"));
+        }
+        let debited = available
+            .checked_sub(payment)
+            .ok_or("Arithmetic overflow")?;
+        let credited = lender.accounts[lender_index].balance(currency).deposited
+            .checked_add(payment)
+            .ok_or("Arithmetic overflow")?;
+
+        self.accounts[index].balance_mut(currency).deposited = debited;
+        lender.accounts[lender_index].balance_mut(currency).deposited = credited;
+        for loan in self.loans.iter_mut().chain(lender.loans.iter_mut()) {
+            if loan.id == loan_id {
+                loan.apply_payment(payment, now);
+            }
+        }
+        self.transactions
+            .push(Transaction::new(TransactionKind::RepaymentOut, payment, currency, Money::ZERO, Some(lender.id)));
+        lender
+            .transactions
+            .push(Transaction::new(TransactionKind::RepaymentIn, payment, currency, Money::ZERO, Some(self.id)));
+        Ok(payment)
+    }
+
+    /// Total this user still owes on loan `loan_id`, interest included.
+    pub fn outstanding(&self, loan_id: LoanId, clock: &dyn Clock) -> Option<Money> {
+        self.debts().find(|loan| loan.id == loan_id).map(|loan| loan.outstanding(clock))
+    }
+
+    /// The interest-earning balances whose principal changed since `before`
+    /// while their interest clock did not, so interest on the old principal
+    /// up to `now` is still owed: the account index, the currency, the old
+    /// principal and when it started earning.
+    pub fn unsettled_balances(&self, before: &User, now: SystemTime) -> Vec<(usize, Currency, Money, SystemTime)> {
+        let mut unsettled = Vec::new();
+        for (index, account) in self.accounts.iter().enumerate() {
+            let Some(previous) = before.accounts.iter().find(|previous| previous.id == account.id) else {
+                continue;
+            };
+            if !account.kind.earns_interest() {
+                continue;
+            }
+            for (&currency, balance) in &account.balances {
+                let old = previous.balance(currency);
+                if let Some(since) = old.interest_since
+                    && since < now
+                    && balance.interest_since == old.interest_since
+                    && balance.deposited != old.deposited
+                {
+                    unsettled.push((index, currency, old.deposited, since));
+                }
+            }
+        }
+        unsettled
+    }
+
+    /// Start the interest clock at `now` on balances that have just become
+    /// non-zero, and stop it on balances that have been emptied.
+    pub fn mark_interest_start(&mut self, now: SystemTime) {
+        for balance in self.accounts.iter_mut().flat_map(|account| account.balances.values_mut()) {
+            if balance.deposited == Money::ZERO {
+                balance.interest_since = None;
+            } else if balance.interest_since.is_none() {
+                balance.interest_since = Some(now);
+            }
+        }
+        if let Some(overdraft) = &mut self.overdraft {
+            if overdraft.overdrawn == Money::ZERO {
+                overdraft.interest_since = None;
+            } else if overdraft.interest_since.is_none() {
+                overdraft.interest_since = Some(now);
+            }
+        }
+    }
+
+    /// Send `amount` of `currency` from `from` straight to the receiver's `to`
+    /// account. Both balances change together or not at all; treasury totals are
+    /// unaffected since the funds stay in the bank.
+    pub fn transfer_to(
+        &mut self,
+        from: AccountId,
+        receiver: &mut User,
+        to: AccountId,
+        amount: Money,
+        currency: Currency,
+    ) -> Result<Money, String> {
+        if self.id == receiver.id {
+            return Err(String::from("Cannot transfer to yourself"));
+        }
+        if amount == Money::ZERO {
+            return Err(String::from("Cannot transfer nothing"));
+        }
+        let index = self.account_index(from)?;
+        let receiver_index = receiver.account_index(to)?;
+        let available = self.accounts[index].balance(currency).deposited;
+        if available < amount {
+            return Err(String::from("Insufficient funds in sender's account"));
+        }
+        let debited = available
+            .checked_sub(amount)
+            .ok_or("Arithmetic overflow")?;
+        let credited = receiver.accounts[receiver_index].balance(currency).deposited
             .checked_add(amount)
             .ok_or("Arithmetic overflow")?;
 
+        self.accounts[index].balance_mut(currency).deposited = debited;
+        receiver.accounts[receiver_index].balance_mut(currency).deposited = credited;
+        self.transactions
+            .push(Transaction::new(TransactionKind::TransferOut, amount, currency, Money::ZERO, Some(receiver.id)));
+        receiver
+            .transactions
+            .push(Transaction::new(TransactionKind::TransferIn, amount, currency, Money::ZERO, Some(self.id)));
         Ok(amount)
     }
+
+    /// Move `amount` of `currency` between two of the user's own accounts.
+    pub fn move_between(&mut self, from: AccountId, to: AccountId, amount: Money, currency: Currency) -> Result<Money, String> {
+        if from == to {
+            return Err(String::from("Cannot transfer to the same account"));
+        }
+        if amount == Money::ZERO {
+            return Err(String::from("Cannot transfer nothing"));
+        }
+        let (index, target) = (self.account_index(from)?, self.account_index(to)?);
+        let available = self.accounts[index].balance(currency).deposited;
+        if available < amount {
+            return Err(String::from("Insufficient funds in sender's account"));
+        }
+        let debited = available
+            .checked_sub(amount)
+            .ok_or("Arithmetic overflow")?;
+        let credited = self.accounts[target].balance(currency).deposited
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow")?;
+
+        self.accounts[index].balance_mut(currency).deposited = debited;
+        self.accounts[target].balance_mut(currency).deposited = credited;
+        self.transactions
+            .push(Transaction::new(TransactionKind::TransferOut, amount, currency, Money::ZERO, Some(self.id)));
+        self.transactions
+            .push(Transaction::new(TransactionKind::TransferIn, amount, currency, Money::ZERO, Some(self.id)));
+        Ok(amount)
+    }
+
+    /// Credit `amount` of `currency` advanced by the treasury against the
+    /// user's next salary to `account`. Only one advance may be outstanding.
+    pub fn take_salary_advance(
+        &mut self,
+        account: AccountId,
+        amount: Money,
+        currency: Currency,
+        now: SystemTime,
+        treasury: &mut Treasury,
+    ) -> Result<Money, String> {
+        if self.salary_advance.is_some() {
+            return Err(format!("User {} already has an outstanding salary advance", self.id));
+        }
+        let index = self.account_index(account)?;
+        let credited = self.accounts[index].balance(currency).deposited
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow")?;
+        let reserves = treasury.balance(currency).deposited
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow")?;
+        self.accounts[index].balance_mut(currency).deposited = credited;
+        treasury.balance_mut(currency).deposited = reserves;
+        self.salary_advance = Some(SalaryAdvance::new(currency, amount, now));
+        self.transactions
+            .push(Transaction::new(TransactionKind::SalaryAdvance, amount, currency, Money::ZERO, None));
+        treasury
+            .transactions
+            .push(Transaction::new(TransactionKind::SalaryAdvance, amount, currency, Money::ZERO, Some(self.id)));
+        Ok(amount)
+    }
+
+    /// Pay the outstanding salary advance back out of `account`, fee first, as
+    /// far as its balance allows. Returns the amount paid.
+    pub fn repay_salary_advance(&mut self, account: AccountId, treasury: &mut Treasury) -> Result<Money, String> {
+        let index = self.account_index(account)?;
+        let Some(advance) = &mut self.salary_advance else {
+            return Ok(Money::ZERO);
+        };
+        let currency = advance.currency;
+        let available = self.accounts[index].balance(currency).deposited;
+        let payment = available.min(advance.total());
+        if payment == Money::ZERO {
+            return Ok(Money::ZERO);
+        }
+        treasury.cover_shortfall(currency, payment)?;
+        let reserves = treasury.balance(currency).deposited
+            .checked_sub(payment)
+            .ok_or("Insufficient treasury reserves")?;
+        let (fee, principal) = advance.repay(payment);
+        if advance.is_repaid() {
+            self.salary_advance = None;
+        }
+        self.accounts[index].balance_mut(currency).deposited = available
+            .checked_sub(payment)
+            .expect("advance repayment exceeds balance");
+        treasury.balance_mut(currency).deposited = reserves;
+        let collected = treasury.fees_collected.entry(currency).or_default();
+        collected.advance = collected.advance.checked_add(fee).expect("fee revenue overflow");
+        self.transactions
+            .push(Transaction::new(TransactionKind::AdvanceRepayment, principal, currency, fee, None));
+        treasury
+            .transactions
+            .push(Transaction::new(TransactionKind::AdvanceRepayment, principal, currency, fee, Some(self.id)));
+        Ok(payment)
+    }
+
+    /// If the credit just recorded looks like the user's next salary, pay back
+    /// any outstanding salary advance from `account`. Returns the amount paid.
+    pub fn collect_salary_advance(
+        &mut self,
+        account: AccountId,
+        now: SystemTime,
+        treasury: &mut Treasury,
+    ) -> Result<Money, String> {
+        let Some(advance) = self.salary_advance else {
+            return Ok(Money::ZERO);
+        };
+        let Some((credit, earlier)) = self.transactions.split_last() else {
+            return Ok(Money::ZERO);
+        };
+        let salary = income::detect(self.id, earlier)
+            .is_some_and(|income| income.currency == advance.currency && income.matches(credit, now));
+        if !salary || self.account(account)?.check_withdrawal(now).is_err() {
+            return Ok(Money::ZERO);
+        }
+        let paid = self.repay_salary_advance(account, treasury)?;
+        if paid > Money::ZERO {
+            self.account_mut(account)?.record_withdrawal(now);
+        }
+        Ok(paid)
+    }
+
+    /// After a credit to `account`, repay the salary advance if it was the
+    /// salary and collect any installments that have fallen due.
+    pub fn settle_after_credit(&mut self, account: AccountId, now: SystemTime, treasury: &mut Treasury) -> Result<(), String> {
+        self.collect_salary_advance(account, now, treasury)?;
+        self.collect_installments(now, treasury)?;
+        Ok(())
+    }
+
+    /// Start paying off `plan`, collecting its first installment at once.
+    /// Fails without changing anything if the plan's account cannot cover it.
+    pub fn open_installment_plan(
+        &mut self,
+        plan: InstallmentPlan,
+        now: SystemTime,
+        treasury: &mut Treasury,
+    ) -> Result<Money, String> {
+        let account = self.account(plan.account)?;
+        account.check_withdrawal(now)?;
+        if account.balance(plan.currency).deposited < plan.next_installment() {
+            return Err(String::from("Insufficient funds for the first installment"));
+        }
+        self.transactions.push(Transaction::new(
+            TransactionKind::InstallmentPurchase,
+            plan.price,
+            plan.currency,
+            Money::ZERO,
+            Some(plan.payee),
+        ));
+        self.installment_plans.push(plan);
+        self.collect_installments(now, treasury)
+    }
+
+    /// Credit `price` less the merchant `fee` for a purchase `payer` makes in
+    /// installments to `account`. The treasury pays it up front.
+    pub fn receive_installment_sale(
+        &mut self,
+        account: AccountId,
+        payer: UserId,
+        price: Money,
+        fee: Money,
+        currency: Currency,
+        treasury: &mut Treasury,
+    ) -> Result<Money, String> {
+        let index = self.account_index(account)?;
+        let net = price.checked_sub(fee).ok_or("Merchant fee exceeds price")?;
+        let credited = self.accounts[index].balance(currency).deposited
+            .checked_add(net)
+            .ok_or("Arithmetic overflow")?;
+        let reserves = treasury.balance(currency).deposited
+            .checked_add(net)
+            .ok_or("Arithmetic overflow")?;
+        self.accounts[index].balance_mut(currency).deposited = credited;
+        treasury.balance_mut(currency).deposited = reserves;
+        self.transactions
+            .push(Transaction::new(TransactionKind::InstallmentSale, net, currency, fee, Some(payer)));
+        treasury
+            .transactions
+            .push(Transaction::new(TransactionKind::InstallmentSale, net, currency, fee, Some(self.id)));
+        Ok(net)
+    }
+
+    /// Collect every installment due by `now` that its plan's account can
+    /// cover. An installment the account cannot cover stays overdue, along
+    /// with the later ones of that plan, until a later collection succeeds.
+    /// Returns the amount collected.
+    pub fn collect_installments(&mut self, now: SystemTime, treasury: &mut Treasury) -> Result<Money, String> {
+        let mut collected = Money::ZERO;
+        for plan in &mut self.installment_plans {
+            let account = self
+                .accounts
+                .iter_mut()
+                .find(|account| account.id == plan.account)
+                .ok_or_else(|| format!("Unknown account {}", plan.account))?;
+            while plan.overdue(now) > 0 && account.check_withdrawal(now).is_ok() {
+                let (amount, currency) = (plan.next_installment(), plan.currency);
+                let available = account.balance(currency).deposited;
+                if available < amount {
+                    break;
+                }
+                treasury.cover_shortfall(currency, amount)?;
+                let reserves = treasury.balance(currency).deposited
+                    .checked_sub(amount)
+                    .ok_or("Insufficient treasury reserves")?;
+                account.balance_mut(currency).deposited = available
+                    .checked_sub(amount)
+                    .expect("installment exceeds balance");
+                treasury.balance_mut(currency).deposited = reserves;
+                account.record_withdrawal(now);
+                let (fee, principal) = plan.pay_installment();
+                let fees = treasury.fees_collected.entry(currency).or_default();
+                fees.merchant = fees.merchant.checked_add(fee).expect("fee revenue overflow");
+                self.transactions
+                    .push(Transaction::new(TransactionKind::Installment, principal, currency, fee, Some(plan.payee)));
+                treasury
+                    .transactions
+                    .push(Transaction::new(TransactionKind::Installment, principal, currency, fee, Some(self.id)));
+                collected = collected.checked_add(amount).ok_or("Arithmetic overflow")?;
+            }
+        }
+        Ok(collected)
+    }
+
+    /// Installments due by `now` and not yet paid, across all plans.
+    pub fn overdue_installments(&self, now: SystemTime) -> u32 {
+        self.installment_plans.iter().map(|plan| plan.overdue(now)).sum()
+    }
+
+    /// Loans this user owes to others.
+    pub fn debts(&self) -> impl Iterator<Item = &Loan> {
+        self.loans.iter().filter(move |loan| loan.borrower == self.id)
+    }
+}
+
+impl fmt::Display for User {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "User {} {}", self.id, self.name)?;
+        for account in &self.accounts {
+            write!(f, "\n  account {} ({}", account.id, account.kind)?;
+            if account.borrowable {
+                write!(f, ", borrowable")?;
+            }
+            write!(f, ")")?;
+            write_balances(f, "    ", &account.balances)?;
+        }
+        for loan in &self.loans {
+            write!(
+                f,
+                "\n  loan {}: {} owes {} {} {} (principal {})",
+                loan.id, loan.borrower, loan.lender, loan.remaining, loan.currency, loan.principal
+            )?;
+            if loan.accrued_interest > Money::ZERO {
+                write!(f, " plus {} interest", loan.accrued_interest)?;
+            }
+            if loan.is_closed() {
+                write!(f, " repaid")?;
+            }
+        }
+        if let Some(overdraft) = &self.overdraft {
+            write!(f, "\n  {}", overdraft)?;
+        }
+        if let Some(advance) = &self.salary_advance {
+            write!(f, "\n  {}", advance)?;
+        }
+        for plan in &self.installment_plans {
+            write!(f, "\n  {}", plan)?;
+            if plan.is_paid_off() {
+                write!(f, " paid off")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Treasury {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Treasury (interest: {}; fees: {})", self.interest, self.fees)?;
+        write_balances(f, "  ", &self.balances)?;
+        if self.facility.limit > Money::ZERO || self.facility.drawn > Money::ZERO {
+            write!(f, "\n  {}", self.facility)?;
+        }
+        let mut collected: Vec<_> = self.fees_collected.iter().collect();
+        collected.sort_by_key(|(currency, _)| **currency);
+        for (currency, fees) in collected {
+            write!(
+                f,
+                "\n  {}: fees collected entry {}, exit {}, advance {}, merchant {}",
+                currency, fees.entry, fees.exit, fees.advance, fees.merchant
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// One line per currency, in a stable order, each starting with `indent`.
+fn write_balances(f: &mut fmt::Formatter<'_>, indent: &str, balances: &HashMap<Currency, Balance>) -> fmt::Result {
+    let mut balances: Vec<_> = balances.iter().collect();
+    balances.sort_by_key(|(currency, _)| **currency);
+    for (currency, balance) in balances {
+        write!(f, "\n{}{}: deposited {}, withdrawn {}", indent, currency, balance.deposited, balance.withdrawn)?;
+    }
+    Ok(())
+}
+
+/// Two balances in the same currency added together. Interest is taken to run
+/// from the earlier of the two start times.
+fn combine(total: Balance, balance: Balance) -> Balance {
+    Balance {
+        deposited: total.deposited.checked_add(balance.deposited).unwrap_or(Money::from_minor(u64::MAX)),
+        withdrawn: total.withdrawn.checked_add(balance.withdrawn).unwrap_or(Money::from_minor(u64::MAX)),
+        interest_since: match (total.interest_since, balance.interest_since) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        },
+    }
 }
 
 impl Treasury {
-    /// Calculate the interest rate and apply interest to the user's deposit.
-    /// Returns the interest amount applied.
-    pub fn apply_interest(&mut self, user: &mut User) -> Result<u32, String> {
-        let interest = Self::calculate_interest_rate(self, user)?;
-        user.total_deposited = user.total_deposited
-            .checked_add(interest)
+    /// A copy of the treasury without its ledger, to `restore` if an
+    /// operation fails part way through.
+    pub fn checkpoint(&mut self) -> Treasury {
+        let transactions = mem::take(&mut self.transactions);
+        let checkpoint = self.clone();
+        self.transactions = transactions;
+        checkpoint
+    }
+
+    /// Go back to `checkpoint`, dropping the ledger entries after `mark`.
+    pub fn restore(&mut self, mut checkpoint: Treasury, mark: usize) {
+        self.transactions.truncate(mark);
+        checkpoint.transactions = mem::take(&mut self.transactions);
+        *self = checkpoint;
+    }
+
+    /// Start applying `policy` at `now` in place of the current interest,
+    /// fees or facility terms. Facility interest owed so far is charged at
+    /// the old rate first. The facility cannot move to another currency
+    /// while anything is drawn on it.
+    pub fn set_policy(&mut self, policy: Policy, now: SystemTime) -> Result<(), String> {
+        match policy {
+            Policy::Interest(interest) => self.interest = interest,
+            Policy::Fees(fees) => self.fees = fees,
+            Policy::Facility { currency, limit, rate_bps } => {
+                if currency != self.facility.currency && self.facility.drawn > Money::ZERO {
+                    return Err(format!(
+                        "The facility still has {} {} drawn; it must be repaid before moving to {}",
+                        self.facility.drawn, self.facility.currency, currency
+                    ));
+                }
+                let interest = self.facility.accrue_interest(now)?;
+                self.record_facility(TransactionKind::FacilityInterest, interest);
+                self.facility.currency = currency;
+                self.facility.limit = limit;
+                self.facility.rate_bps = rate_bps;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw whatever the `currency` reserves are short of `amount` on the
+    /// liquidity facility, if it is in `currency` and has the headroom.
+    /// Otherwise leaves the reserves short for the caller to refuse.
+    pub fn cover_shortfall(&mut self, currency: Currency, amount: Money) -> Result<(), String> {
+        let reserves = self.balance(currency).deposited;
+        let Some(shortfall) = amount.checked_sub(reserves).filter(|shortfall| *shortfall > Money::ZERO) else {
+            return Ok(());
+        };
+        if self.facility.currency != currency || shortfall > self.facility.headroom() {
+            return Ok(());
+        }
+        self.facility.draw(shortfall)?;
+        self.balance_mut(currency).deposited = reserves
+            .checked_add(shortfall)
+            .ok_or("Arithmetic overflow when drawing on the facility")?;
+        self.record_facility(TransactionKind::FacilityDraw, shortfall);
+        Ok(())
+    }
+
+    /// Pay the liquidity facility back out of `amount` that just came into
+    /// the `currency` reserves. Returns the amount repaid.
+    pub fn repay_facility(&mut self, currency: Currency, amount: Money) -> Money {
+        if self.facility.currency != currency {
+            return Money::ZERO;
+        }
+        let reserves = self.balance(currency).deposited;
+        let repaid = self.facility.repay(amount.min(reserves));
+        self.balance_mut(currency).deposited = reserves.checked_sub(repaid).unwrap_or(Money::ZERO);
+        self.record_facility(TransactionKind::FacilityRepayment, repaid);
+        repaid
+    }
+
+    /// Charge the facility interest owed up to `now` on what was drawn in
+    /// `previous` if an operation has drawn or repaid since without charging
+    /// it, and keep the facility's interest clock running only while
+    /// something is drawn. Returns the interest charged.
+    pub fn settle_facility(&mut self, previous: &LiquidityFacility, now: SystemTime) -> Result<Money, String> {
+        let mut charged = Money::ZERO;
+        if self.facility.drawn != previous.drawn && self.facility.interest_since == previous.interest_since {
+            charged = previous.interest_due(now);
+            let overflow = || String::from("Arithmetic overflow when accruing facility interest");
+            self.facility.drawn = self.facility.drawn.checked_add(charged).ok_or_else(overflow)?;
+            self.facility.interest_expense = self.facility.interest_expense.checked_add(charged).ok_or_else(overflow)?;
+            self.facility.interest_since = Some(now);
+            self.record_facility(TransactionKind::FacilityInterest, charged);
+        }
+        if self.facility.drawn == Money::ZERO {
+            self.facility.interest_since = None;
+        } else if self.facility.interest_since.is_none() {
+            self.facility.interest_since = Some(now);
+        }
+        Ok(charged)
+    }
+
+    fn record_facility(&mut self, kind: TransactionKind, amount: Money) {
+        if amount > Money::ZERO {
+            let currency = self.facility.currency;
+            self.transactions.push(Transaction::new(kind, amount, currency, Money::ZERO, None));
+        }
+    }
+
+    /// Fees collected in `currency` and not yet swept, entry and exit combined.
+    pub fn fee_revenue(&self, currency: Currency) -> Money {
+        self.fees_collected.get(&currency).map_or(Money::ZERO, FeesCollected::total)
+    }
+
+    /// Move the fees collected in `currency` into the treasury's main pool.
+    /// Returns the amount swept.
+    pub fn sweep_fees(&mut self, currency: Currency) -> Result<Money, String> {
+        let swept = self.fee_revenue(currency);
+        let reserves = self.balance(currency).deposited
+            .checked_add(swept)
+            .ok_or("Arithmetic overflow when sweeping fees")?;
+        self.balance_mut(currency).deposited = reserves;
+        self.fees_collected.remove(&currency);
+        self.transactions
+            .push(Transaction::new(TransactionKind::FeeSweep, swept, currency, Money::ZERO, None));
+        Ok(swept)
+    }
+
+    /// Totals held in `currency`, zero if nothing was ever deposited in it.
+    pub fn balance(&self, currency: Currency) -> Balance {
+        self.balances.get(&currency).copied().unwrap_or_default()
+    }
+
+    pub fn balance_mut(&mut self, currency: Currency) -> &mut Balance {
+        self.balances.entry(currency).or_default()
+    }
+
+    /// Credit each of the user's interest-earning accounts holding `currency`
+    /// with the interest earned up to `until` under `self.interest`. Compound
+    /// schedules only pay for whole periods; the remainder carries over to the
+    /// next accrual. Interest on an overdraft in `currency` is charged in the
+    /// same pass.
+    /// Returns the deposit interest applied across all accounts.
+    pub fn accrue_until(&mut self, user: &mut User, currency: Currency, until: SystemTime) -> Result<Money, String> {
+        let mut pending = Vec::new();
+        for (index, account) in user.accounts.iter().enumerate() {
+            if !account.kind.earns_interest() || !account.balances.contains_key(&currency) {
+                continue;
+            }
+            let balance = account.balance(currency);
+            let since = balance.interest_since.unwrap_or(until);
+            let (interest, through) = self.interest_between(account, currency, since, until)?;
+            let credited = balance.deposited
+                .checked_add(interest)
+                .ok_or("Arithmetic overflow when applying interest")?;
+            pending.push((index, interest, credited, through));
+        }
+        let total = pending
+            .iter()
+            .try_fold(Money::ZERO, |total, (_, interest, _, _)| total.checked_add(*interest))
             .ok_or("Arithmetic overflow when applying interest")?;
-        self.sum_deposited = self.sum_deposited
-            .checked_add(interest)
+        let reserves = self.balance(currency).deposited
+            .checked_add(total)
             .ok_or("Arithmetic overflow when applying interest to treasury")?;
-        Ok(interest)
+        for (index, interest, credited, through) in pending {
+            let balance = user.accounts[index].balance_mut(currency);
+            balance.deposited = credited;
+            balance.interest_since = Some(through);
+            user.transactions
+                .push(Transaction::new(TransactionKind::Interest, interest, currency, Money::ZERO, None));
+            self.transactions
+                .push(Transaction::new(TransactionKind::Interest, interest, currency, Money::ZERO, Some(user.id)));
+        }
+        self.balance_mut(currency).deposited = reserves;
+        self.charge_overdraft_interest(user, currency, until)?;
+        Ok(total)
+    }
+
+    /// Credit `user` with the interest their `unsettled_balances` since
+    /// `before` earned on the old principal up to `now`, and restart the
+    /// interest clock on those balances at `now`. Rates come from
+    /// `previous`, the treasury as it was when `before` was taken; under the
+    /// legacy formula, a treasury it cannot work out a rate for pays nothing.
+    /// Returns the interest credited.
+    pub fn settle_interest(
+        &mut self,
+        previous: &Treasury,
+        before: &User,
+        user: &mut User,
+        now: SystemTime,
+    ) -> Result<Money, String> {
+        let overflow = || String::from("Arithmetic overflow when settling interest");
+        let mut total = Money::ZERO;
+        for (index, currency, principal, since) in user.unsettled_balances(before, now) {
+            let interest = match previous.interest {
+                InterestStrategy::Compound(schedule) => schedule.settle(principal, since, now),
+                InterestStrategy::Legacy => {
+                    let account = before.account(user.accounts[index].id)?;
+                    let yearly = Self::calculate_interest_rate(previous, account, currency).unwrap_or(Money::ZERO);
+                    Money::from_minor(time::prorate(yearly.minor(), now.duration_since(since).unwrap_or_default()))
+                }
+            };
+            let balance = user.accounts[index].balance_mut(currency);
+            balance.interest_since = Some(now);
+            if interest == Money::ZERO {
+                continue;
+            }
+            balance.deposited = balance.deposited.checked_add(interest).ok_or_else(overflow)?;
+            let reserves = self.balance_mut(currency);
+            reserves.deposited = reserves.deposited.checked_add(interest).ok_or_else(overflow)?;
+            total = total.checked_add(interest).ok_or_else(overflow)?;
+            user.transactions
+                .push(Transaction::new(TransactionKind::Interest, interest, currency, Money::ZERO, None));
+            self.transactions
+                .push(Transaction::new(TransactionKind::Interest, interest, currency, Money::ZERO, Some(user.id)));
+        }
+        Ok(total)
+    }
+
+    /// Interest `account` earns on its `currency` balance from `since` to
+    /// `until` under `self.interest`, and how far accrual got.
+    fn interest_between(
+        &self,
+        account: &Account,
+        currency: Currency,
+        since: SystemTime,
+        until: SystemTime,
+    ) -> Result<(Money, SystemTime), String> {
+        match self.interest {
+            InterestStrategy::Compound(schedule) => Ok(schedule.accrue(account.balance(currency).deposited, since, until)),
+            InterestStrategy::Legacy => {
+                let yearly = Self::calculate_interest_rate(self, account, currency)?;
+                let elapsed = until.duration_since(since).unwrap_or_default();
+                Ok((Money::from_minor(time::prorate(yearly.minor(), elapsed)), until))
+            }
+        }
+    }
+
+    /// The period forecasts look ahead: one compounding period, or a month
+    /// under the legacy formula.
+    pub fn forecast_period(&self) -> Duration {
+        match self.interest {
+            InterestStrategy::Compound(schedule) => schedule.compounding.period(),
+            InterestStrategy::Legacy => Compounding::Monthly.period(),
+        }
+    }
+
+    /// What the user's `currency` balances would earn, and their loans and
+    /// overdraft would cost, over the `forecast_period` from `now` if rates
+    /// and balances stay as they are. Uses the same calculations as accrual.
+    pub fn forecast(&self, user: &User, currency: Currency, now: SystemTime) -> Result<InterestForecast, String> {
+        let period = self.forecast_period();
+        let until = now + period;
+        let overflow = || String::from("Arithmetic overflow when forecasting interest");
+        let mut earned = Money::ZERO;
+        for account in &user.accounts {
+            if !account.kind.earns_interest() || !account.balances.contains_key(&currency) {
+                continue;
+            }
+            let (interest, _) = self.interest_between(account, currency, now, until)?;
+            earned = earned.checked_add(interest).ok_or_else(overflow)?;
+        }
+        let mut cost = Money::ZERO;
+        for loan in user.debts().filter(|loan| loan.currency == currency) {
+            let interest = loan.interest_due(until).checked_sub(loan.interest_due(now)).unwrap_or(Money::ZERO);
+            cost = cost.checked_add(interest).ok_or_else(overflow)?;
+        }
+        if let Some(overdraft) = user.overdraft.as_ref().filter(|overdraft| overdraft.currency == currency) {
+            let interest = overdraft.interest_due(until).checked_sub(overdraft.interest_due(now)).unwrap_or(Money::ZERO);
+            cost = cost.checked_add(interest).ok_or_else(overflow)?;
+        }
+        Ok(InterestForecast {
+            currency,
+            period,
+            earned,
+            cost,
+        })
+    }
+
+    /// Add the interest due up to `until` on the user's overdraft in
+    /// `currency` to the overdrawn amount and restart its interest clock.
+    /// Call it before the overdrawn amount changes, so the old amount is
+    /// charged for the time it was owed. Returns the interest charged.
+    pub fn charge_overdraft_interest(
+        &mut self,
+        user: &mut User,
+        currency: Currency,
+        until: SystemTime,
+    ) -> Result<Money, String> {
+        let Some(overdraft) = user.overdraft.as_mut().filter(|overdraft| overdraft.currency == currency) else {
+            return Ok(Money::ZERO);
+        };
+        if overdraft.overdrawn == Money::ZERO {
+            return Ok(Money::ZERO);
+        }
+        let charged = overdraft.interest_due(until);
+        overdraft.overdrawn = overdraft.overdrawn
+            .checked_add(charged)
+            .ok_or("Arithmetic overflow when charging overdraft interest")?;
+        overdraft.interest_since = Some(until);
+        if charged == Money::ZERO {
+            return Ok(charged);
+        }
+        user.transactions
+            .push(Transaction::new(TransactionKind::OverdraftInterest, charged, currency, Money::ZERO, None));
+        self.transactions
+            .push(Transaction::new(TransactionKind::OverdraftInterest, charged, currency, Money::ZERO, Some(user.id)));
+        Ok(charged)
     }
     
-    /// Calculate interest rate based on treasury and user's deposit.
-    /// Returns `interest = (treasury.sum_deposited * user.total_deposited) / treasury.sum_withdrawn`
+    /// Calculate interest rate based on treasury and an account's deposit in
+    /// `currency`, as used by `InterestStrategy::Legacy`.
+    /// Returns the yearly `interest = (treasury.deposited * account.deposited) / treasury.withdrawn`
     /// or an error if the treasury state is invalid.
-    pub fn calculate_interest_rate(treasury: &Treasury, user: &User) -> Result<u32, String> {
-        if treasury.sum_deposited > 0 && treasury.sum_withdrawn > 0 {
-            Ok((treasury.sum_deposited.saturating_mul(user.total_deposited)) / treasury.sum_withdrawn)
+    pub fn calculate_interest_rate(treasury: &Treasury, account: &Account, currency: Currency) -> Result<Money, String> {
+        let reserves = treasury.balance(currency);
+        if reserves.deposited > Money::ZERO && reserves.withdrawn > Money::ZERO {
+            Ok(Money::from_minor(
+                (reserves.deposited.minor().saturating_mul(account.balance(currency).deposited.minor()))
+                    / reserves.withdrawn.minor(),
+            ))
         } else {
             Err(String::from("Invalid treasury state"))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::account::AccountKind;
+    use crate::bank::Bank;
+    use crate::currency::Currency;
+    use crate::money::Money;
+
+    #[test]
+    fn withdrawing_more_than_is_available_is_refused() {
+        let mut bank = Bank::new();
+        let id = bank.open_account_of_kind("Ada", AccountKind::Checking).unwrap();
+        bank.deposit(id, Money::from_major(100), Currency::Usd, false).unwrap();
+
+        let err = bank.withdraw(id, Money::from_major(95), Currency::Usd).unwrap_err();
+        assert!(err.message().starts_with("Insufficient USD funds"), "{}", err);
+        assert_eq!(bank.get_user(id).unwrap().balance(Currency::Usd).deposited, Money::from_major(98));
+    }
+
+    #[test]
+    fn overdraft_covers_what_is_not_available() {
+        let mut bank = Bank::new();
+        let id = bank.open_account_of_kind("Ada", AccountKind::Checking).unwrap();
+        bank.deposit(id, Money::from_major(100), Currency::Usd, false).unwrap();
+        // 98 deposited, 26 withdrawn with the fee: 72 left, of which 46 is available.
+        bank.withdraw(id, Money::from_major(25), Currency::Usd).unwrap();
+        bank.grant_overdraft(id, Currency::Usd, Money::from_major(100), 0).unwrap();
+
+        // 52 with the fee, 6 of it past what is available.
+        bank.withdraw(id, Money::from_major(50), Currency::Usd).unwrap();
+        let user = bank.get_user(id).unwrap();
+        assert_eq!(user.overdraft.unwrap().overdrawn, Money::from_major(6));
+        assert_eq!(user.balance(Currency::Usd).deposited, Money::from_major(26));
+    }
+
+    #[test]
+    fn overdraft_headroom_limits_the_shortfall() {
+        let mut bank = Bank::new();
+        let id = bank.open_account_of_kind("Ada", AccountKind::Checking).unwrap();
+        bank.deposit(id, Money::from_major(100), Currency::Usd, false).unwrap();
+        bank.withdraw(id, Money::from_major(25), Currency::Usd).unwrap();
+        bank.grant_overdraft(id, Currency::Usd, Money::from_major(5), 0).unwrap();
+
+        assert!(bank.withdraw(id, Money::from_major(50), Currency::Usd).is_err());
+        assert_eq!(bank.get_user(id).unwrap().overdraft.unwrap().overdrawn, Money::ZERO);
+    }
+
+    #[test]
+    fn deposits_pay_back_the_overdraft_first() {
+        let mut bank = Bank::new();
+        let id = bank.open_account_of_kind("Ada", AccountKind::Checking).unwrap();
+        let other = bank.open_account("Bob").unwrap();
+        bank.deposit(other, Money::from_major(1_000), Currency::Usd, false).unwrap();
+        bank.grant_overdraft(id, Currency::Usd, Money::from_major(100), 0).unwrap();
+        bank.deposit(id, Money::from_major(100), Currency::Usd, false).unwrap();
+        // 98 deposited; 104 with the fee leaves 6 overdrawn.
+        bank.withdraw(id, Money::from_major(100), Currency::Usd).unwrap();
+        assert!(bank.revoke_overdraft(id).unwrap_err().message().contains("still owes 6.00"));
+        assert!(bank.grant_overdraft(id, Currency::Eur, Money::from_major(100), 0).is_err());
+
+        // 9.80 after the entry fee: 6 repays the overdraft, 3.80 is deposited.
+        bank.deposit(id, Money::from_major(10), Currency::Usd, false).unwrap();
+        let user = bank.get_user(id).unwrap();
+        assert_eq!(user.overdraft.unwrap().overdrawn, Money::ZERO);
+        assert_eq!(user.balance(Currency::Usd).deposited, Money::from_minor(380));
+        bank.revoke_overdraft(id).unwrap();
+        assert!(bank.get_user(id).unwrap().overdraft.is_none());
+    }
+}